@@ -0,0 +1,530 @@
+//! Local HTTP/JSON API sharing a single tor control connection.
+//!
+//! | method | path                    | description                                        |
+//! |--------|-------------------------|----------------------------------------------------|
+//! | GET    | `/circuits`             | circuits                                           |
+//! | GET    | `/streams`              | streams                                            |
+//! | GET    | `/relays?q=query`       | relays of the consensus, with their country        |
+//! | GET    | `/relays/<fingerprint>` | a single relay                                     |
+//! | GET    | `/config/<keyword>`     | configuration values                               |
+//! | GET    | `/events?events=A,B`    | Server-Sent-Events feed of `CIRC` and `STREAM`     |
+//! | POST   | `/circuits`             | `{"id": "0", "path": [...]}` extends a circuit     |
+//! | POST   | `/streams/<id>/attach`  | `{"circuit": "12"}` attaches a stream              |
+//! | PUT    | `/config`               | `{"Keyword": "value" or null}` sets options        |
+//!
+//! The mutating endpoints are only served with `--allow-mutations`, and only
+//! accept `Content-Type: application/json`. `HEAD` is answered as `GET`.
+//! `/events` answers `503` once the feed from tor is lost.
+//!
+//! Over TCP, requests whose `Host` is not the listening address (or
+//! `localhost` on its port) and cross-origin requests are refused, so web pages
+//! cannot reach the API through DNS rebinding. With `--token`, requests must
+//! also carry an `Authorization: Bearer <token>` header.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use tor_analyzer_lib::error::Error;
+use tor_analyzer_lib::prelude::*;
+
+/// Events forwarded to the SSE clients.
+const EVENTS: &[&str] = &["CIRC", "STREAM"];
+
+/// How long the relay index built from the consensus is reused.
+const RELAYS_TTL: Duration = Duration::from_secs(300);
+
+/// Receives the name and the JSON data of the events.
+type Subscriber = Sender<(String, String)>;
+
+pub enum Listen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+struct State {
+    control: String,
+    ctrl: Mutex<TorController>,
+    relays: Mutex<Option<(Instant, Arc<RelayIndex>)>>,
+
+    /// SSE clients, `None` once the event feed stopped
+    subscribers: Mutex<Option<Vec<Subscriber>>>,
+    allow_mutations: bool,
+
+    /// Accepted `Host` headers, `None` on a Unix socket
+    hosts: Option<Vec<String>>,
+    token: Option<String>,
+}
+
+#[derive(Debug)]
+struct HttpError(u16, String);
+
+impl From<Error> for HttpError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::ServerResponse(code, message) => {
+                HttpError(502, format!("tor replied {code}: {}", message.trim_end()))
+            }
+            e => HttpError(500, e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for HttpError {
+    fn from(e: serde_json::Error) -> Self {
+        HttpError(400, format!("Invalid JSON body: {e}"))
+    }
+}
+
+#[derive(Deserialize)]
+struct ExtendRequest {
+    #[serde(default = "new_circuit")]
+    id: CircuitID,
+    path: Vec<RelayFingerprint>,
+}
+
+fn new_circuit() -> CircuitID {
+    CircuitID("0".into())
+}
+
+#[derive(Serialize)]
+struct ExtendResponse {
+    circuit: CircuitID,
+}
+
+#[derive(Deserialize)]
+struct AttachRequest {
+    circuit: CircuitID,
+}
+
+#[derive(Serialize)]
+struct ConfValue {
+    keyword: String,
+    value: Option<String>,
+}
+
+fn json_response<T: Serialize>(status: u16, value: &T) -> Response<io::Cursor<Vec<u8>>> {
+    let body = serde_json::to_vec(value).unwrap_or_default();
+    Response::from_data(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+fn hex_digit(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+fn error_response(status: u16, message: String) -> Response<io::Cursor<Vec<u8>>> {
+    let mut body = HashMap::new();
+    body.insert("error", message);
+    json_response(status, &body)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match (hex_digit(bytes[i + 1]), hex_digit(bytes[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        decoded.push(high << 4 | low);
+                        i += 2;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+fn parse_query_string(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|kv| !kv.is_empty())
+        .map(|kv| match kv.split_once('=') {
+            Some((k, v)) => (percent_decode(k), percent_decode(v)),
+            None => (percent_decode(kv), String::new()),
+        })
+        .collect()
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(request: &mut Request) -> Result<T, HttpError> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut body)
+        .map_err(|e| HttpError(400, e.to_string()))?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+impl State {
+    /// Refuses requests from other hosts or origins, or without the token.
+    fn check(&self, request: &Request) -> Result<(), HttpError> {
+        if let Some(ref hosts) = self.hosts {
+            let host = header(request, "Host").unwrap_or_default();
+            if !hosts.iter().any(|h| h.eq_ignore_ascii_case(host)) {
+                return Err(HttpError(421, format!("Unexpected Host {host:?}")));
+            }
+            if let Some(origin) = header(request, "Origin") {
+                let allowed = origin
+                    .strip_prefix("http://")
+                    .is_some_and(|o| hosts.iter().any(|h| h.eq_ignore_ascii_case(o)));
+                if !allowed {
+                    return Err(HttpError(
+                        403,
+                        format!("Cross-origin request from {origin}"),
+                    ));
+                }
+            }
+        }
+        if let Some(ref token) = self.token {
+            let authorization = header(request, "Authorization").unwrap_or_default();
+            if authorization.strip_prefix("Bearer ") != Some(token.as_str()) {
+                return Err(HttpError(401, "Missing or invalid bearer token".into()));
+            }
+        }
+        Ok(())
+    }
+
+    fn relays(&self) -> Result<Arc<RelayIndex>, HttpError> {
+        let mut cache = self.relays.lock().unwrap();
+        if let Some((ref updated, ref relays)) = *cache {
+            if updated.elapsed() < RELAYS_TTL {
+                return Ok(Arc::clone(relays));
+            }
+        }
+        let ors = self.ctrl.lock().unwrap().get_all_onion_router()?;
+        let relays = Arc::new(RelayIndex::new(ors, crate::open_geoip().as_ref()));
+        *cache = Some((Instant::now(), Arc::clone(&relays)));
+        Ok(relays)
+    }
+
+    fn handle(&self, request: &mut Request) -> Result<Response<io::Cursor<Vec<u8>>>, HttpError> {
+        let url = request.url().to_owned();
+        let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
+        let params = parse_query_string(query);
+        let segments: Vec<String> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(percent_decode)
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        let method = request.method().clone();
+
+        if !matches!(method, Method::Get | Method::Head) && !self.allow_mutations {
+            return Err(HttpError(
+                403,
+                "Mutating endpoints are disabled, start the daemon with --allow-mutations".into(),
+            ));
+        }
+        if matches!(method, Method::Post | Method::Put) {
+            let content_type = header(request, "Content-Type").unwrap_or_default();
+            let mime = content_type.split(';').next().unwrap_or_default().trim();
+            if !mime.eq_ignore_ascii_case("application/json") {
+                return Err(HttpError(
+                    415,
+                    "Mutating endpoints require Content-Type: application/json".into(),
+                ));
+            }
+        }
+
+        // tiny_http leaves the body out of the answers to HEAD
+        let route = match method {
+            Method::Head => Method::Get,
+            ref method => method.clone(),
+        };
+        match (&route, &segments[..]) {
+            (Method::Get, ["circuits"]) => {
+                let circuits = self.ctrl.lock().unwrap().get_circuits()?;
+                Ok(json_response(200, &circuits))
+            }
+            (Method::Get, ["streams"]) => {
+                let streams = self.ctrl.lock().unwrap().get_streams()?;
+                Ok(json_response(200, &streams))
+            }
+            (Method::Get, ["relays"]) => {
+                let query: Query = params
+                    .get("q")
+                    .map(String::as_str)
+                    .unwrap_or_default()
+                    .parse()
+                    .map_err(|e: Error| HttpError(400, e.to_string()))?;
+                let relays = self.relays()?;
+                Ok(json_response(200, &relays.query(&query)))
+            }
+            (Method::Get, ["relays", fingerprint]) => {
+                let fingerprint: RelayFingerprint = fingerprint
+                    .parse()
+                    .map_err(|e: Error| HttpError(400, e.to_string()))?;
+                let relays = self.relays()?;
+                match relays.get(&fingerprint) {
+                    Some(relay) => Ok(json_response(200, relay)),
+                    None => Err(HttpError(404, format!("Unknown relay {fingerprint}"))),
+                }
+            }
+            (Method::Get, ["config", keyword]) => {
+                let values = self.ctrl.lock().unwrap().get_conf_values(&[keyword])?;
+                let values: Vec<ConfValue> = values
+                    .into_iter()
+                    .map(|(keyword, value)| ConfValue { keyword, value })
+                    .collect();
+                Ok(json_response(200, &values))
+            }
+            (Method::Post, ["circuits"]) => {
+                let extend: ExtendRequest = read_json(request)?;
+                let response = self
+                    .ctrl
+                    .lock()
+                    .unwrap()
+                    .extend_circuit(extend.id, &extend.path)?;
+                let circuit = response
+                    .trim_end()
+                    .strip_prefix("EXTENDED ")
+                    .ok_or_else(|| HttpError(502, format!("Unexpected reply {response:?}")))?;
+                Ok(json_response(
+                    200,
+                    &ExtendResponse {
+                        circuit: CircuitID(circuit.into()),
+                    },
+                ))
+            }
+            (Method::Post, ["streams", stream, "attach"]) => {
+                let attach: AttachRequest = read_json(request)?;
+                self.ctrl
+                    .lock()
+                    .unwrap()
+                    .attach_stream(StreamID((*stream).into()), attach.circuit)?;
+                Ok(Response::from_data(Vec::new()).with_status_code(204))
+            }
+            (Method::Put, ["config"]) => {
+                let values: HashMap<String, Option<String>> = read_json(request)?;
+                let values: Vec<(String, Option<String>)> = values.into_iter().collect();
+                self.ctrl.lock().unwrap().set_conf_values(&values)?;
+                Ok(Response::from_data(Vec::new()).with_status_code(204))
+            }
+            _ => Err(HttpError(404, format!("No route for {method} {path}"))),
+        }
+    }
+
+    /// Streams events to the client until it disconnects.
+    fn serve_events(&self, request: Request) -> io::Result<()> {
+        let url = request.url().to_owned();
+        let query = url.split_once('?').map(|(_, q)| q).unwrap_or_default();
+        let filter: Option<Vec<String>> = parse_query_string(query)
+            .get("events")
+            .map(|events| events.split(',').map(|e| e.to_uppercase()).collect());
+
+        let (tx, rx) = mpsc::channel();
+        match self.subscribers.lock().unwrap().as_mut() {
+            Some(subscribers) => subscribers.push(tx),
+            None => {
+                let message = "The event feed from tor stopped".to_owned();
+                return request.respond(error_response(503, message));
+            }
+        }
+
+        let mut writer = request.into_writer();
+        writer.write_all(
+            b"HTTP/1.1 200 OK\r\n\
+            Content-Type: text/event-stream\r\n\
+            Cache-Control: no-cache\r\n\
+            Connection: close\r\n\r\n",
+        )?;
+        writer.flush()?;
+
+        for (name, data) in rx.iter() {
+            if let Some(ref filter) = filter {
+                if !filter.contains(&name) {
+                    continue;
+                }
+            }
+            write!(writer, "event: {name}\ndata: {data}\n\n")?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Forwards tor events to every SSE client, dropping the disconnected ones.
+fn broadcast_events(state: Arc<State>) -> Result<(), Error> {
+    let mut ctrl = TorController::new(&state.control)?;
+    ctrl.set_events(EVENTS)?;
+    loop {
+        let event = match ctrl.wait_event() {
+            Ok(event) => event,
+            Err(Error::Io(e)) => return Err(Error::Io(e)),
+            Err(e) => {
+                log::warn!("Invalid event: {}", e);
+                continue;
+            }
+        };
+        let name = event.name().to_owned();
+        let data = serde_json::to_string(&event).unwrap_or_default();
+        if let Some(subscribers) = state.subscribers.lock().unwrap().as_mut() {
+            subscribers.retain(|tx| tx.send((name.clone(), data.clone())).is_ok());
+        }
+    }
+}
+
+/// Unix socket of a server, removed when dropped, or on `SIGINT` and
+/// `SIGTERM`.
+pub struct SocketFile(PathBuf);
+
+impl SocketFile {
+    /// Removes the socket left by a previous run, refusing to remove anything
+    /// else or a socket a server still listens on.
+    fn remove_stale(path: &Path) -> io::Result<()> {
+        use std::os::unix::fs::FileTypeExt;
+
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("A server already listens on {}", path.display()),
+            ));
+        }
+        log::info!("Removing stale socket {}", path.display());
+        fs::remove_file(path)
+    }
+
+    fn remove_on_signals(&self) -> io::Result<()> {
+        use signal_hook::consts::{SIGINT, SIGTERM};
+
+        let mut signals = signal_hook::iterator::Signals::new([SIGINT, SIGTERM])?;
+        let path = self.0.clone();
+        thread::spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                let _ = fs::remove_file(&path);
+                let _ = signal_hook::low_level::emulate_default_handler(signal);
+                std::process::exit(128 + signal);
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            log::warn!("Could not remove {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// Opens the HTTP server, refusing non loopback addresses unless
+/// `loopback_only` is false.
+///
+/// The Unix socket is removed when the returned [`SocketFile`] is dropped.
+pub fn bind(listen: &Listen, loopback_only: bool) -> Result<(Server, Option<SocketFile>), Error> {
+    let to_error =
+        |e: Box<dyn std::error::Error + Send + Sync>| Error::Io(io::Error::other(e.to_string()));
+    match *listen {
+        Listen::Tcp(addr) => {
+            if loopback_only && !addr.ip().is_loopback() {
+                return Err(Error::Protocol(format!(
+                    "Refusing to listen on {addr}, only loopback addresses are allowed"
+                )));
+            }
+            Ok((Server::http(addr).map_err(to_error)?, None))
+        }
+        Listen::Unix(ref path) => {
+            SocketFile::remove_stale(path)?;
+            let server = Server::http_unix(path).map_err(to_error)?;
+            let socket = SocketFile(path.clone());
+            socket.remove_on_signals()?;
+            Ok((server, Some(socket)))
+        }
+    }
+}
+
+pub fn serve(
+    control: &str,
+    listen: Listen,
+    allow_mutations: bool,
+    token: Option<String>,
+) -> Result<(), Error> {
+    let (server, _socket) = bind(&listen, true)?;
+    let hosts = match listen {
+        Listen::Tcp(addr) => Some(vec![addr.to_string(), format!("localhost:{}", addr.port())]),
+        Listen::Unix(_) => None,
+    };
+
+    let state = Arc::new(State {
+        control: control.to_owned(),
+        ctrl: Mutex::new(TorController::new(control)?),
+        relays: Mutex::new(None),
+        subscribers: Mutex::new(Some(Vec::new())),
+        allow_mutations,
+        hosts,
+        token,
+    });
+
+    let events_state = Arc::clone(&state);
+    thread::spawn(move || {
+        if let Err(e) = broadcast_events(Arc::clone(&events_state)) {
+            log::error!("Event feed stopped: {}", e);
+        }
+        // Ends the streams of the clients, and refuses new ones
+        *events_state.subscribers.lock().unwrap() = None;
+    });
+
+    for mut request in server.incoming_requests() {
+        let state = Arc::clone(&state);
+        thread::spawn(move || {
+            log::debug!("{} {}", request.method(), request.url());
+            let is_events = request.method() == &Method::Get
+                && request.url().split('?').next() == Some("/events");
+            let checked = state.check(&request);
+            let result = if is_events && checked.is_ok() {
+                state.serve_events(request)
+            } else {
+                let response = match checked.and_then(|()| state.handle(&mut request)) {
+                    Ok(response) => response,
+                    Err(HttpError(status, message)) => {
+                        log::info!("{} {}: {}", request.method(), request.url(), message);
+                        error_response(status, message)
+                    }
+                };
+                request.respond(response)
+            };
+            if let Err(e) = result {
+                log::debug!("Client went away: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
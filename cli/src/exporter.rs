@@ -0,0 +1,277 @@
+//! OpenMetrics exporter.
+//!
+//! Circuits, streams and the consensus are read from the control port on each
+//! scrape (the consensus being cached for a while), the counters are fed by
+//! `CIRC` and `BW` events since the exporter started.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tiny_http::{Header, Method, Response};
+
+use tor_analyzer_lib::error::Error;
+use tor_analyzer_lib::prelude::*;
+use tor_analyzer_lib::tor::circuit::CircuitStatus;
+use tor_analyzer_lib::tor::ns::OnionRouterFlag;
+
+use crate::daemon::{bind, Listen};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// How long relay counts computed from the consensus are reused.
+const CONSENSUS_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Default)]
+struct Counters {
+    circuits_built: u64,
+    circuits_failed: BTreeMap<String, u64>,
+    bytes_read: u64,
+    bytes_written: u64,
+}
+
+#[derive(Default)]
+struct ConsensusCounts {
+    by_flag: BTreeMap<String, u64>,
+    exits_by_country: BTreeMap<String, u64>,
+}
+
+struct Exporter {
+    ctrl: Mutex<TorController>,
+    consensus: Mutex<Option<(Instant, Arc<ConsensusCounts>)>>,
+    counters: Mutex<Counters>,
+}
+
+/// OpenMetrics text being built.
+#[derive(Default)]
+struct Metrics(String);
+
+impl Metrics {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.0, "# TYPE {name} {kind}");
+        let _ = writeln!(self.0, "# HELP {name} {help}");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: u64) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            self.0.push('{');
+            for (i, (label, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.0.push(',');
+                }
+                let _ = write!(self.0, "{label}=\"{}\"", escape_label(value));
+            }
+            self.0.push('}');
+        }
+        let _ = writeln!(self.0, " {value}");
+    }
+
+    fn finish(mut self) -> String {
+        self.0.push_str("# EOF\n");
+        self.0
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn count<I: IntoIterator<Item = String>>(keys: I) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    for key in keys {
+        *counts.entry(key).or_default() += 1;
+    }
+    counts
+}
+
+impl Exporter {
+    fn consensus(&self) -> Result<Arc<ConsensusCounts>, Error> {
+        let mut cache = self.consensus.lock().unwrap();
+        if let Some((ref updated, ref counts)) = *cache {
+            if updated.elapsed() < CONSENSUS_TTL {
+                return Ok(Arc::clone(counts));
+            }
+        }
+        let ors = self.ctrl.lock().unwrap().get_all_onion_router()?;
+        let relays = RelayIndex::new(ors, crate::open_geoip().as_ref());
+        let counts = Arc::new(ConsensusCounts {
+            by_flag: count(
+                relays
+                    .iter()
+                    .flat_map(|r| r.or.flags.iter().map(|flag| flag.to_string())),
+            ),
+            exits_by_country: count(
+                relays
+                    .iter()
+                    .filter(|r| {
+                        r.or.flags.is_set(OnionRouterFlag::Exit)
+                            && !r.or.flags.is_set(OnionRouterFlag::BadExit)
+                    })
+                    .map(|r| r.country.unwrap_or("??").to_owned()),
+            ),
+        });
+        *cache = Some((Instant::now(), Arc::clone(&counts)));
+        Ok(counts)
+    }
+
+    fn render(&self) -> Result<String, Error> {
+        let (circuits, streams) = {
+            let mut ctrl = self.ctrl.lock().unwrap();
+            (ctrl.get_circuits()?, ctrl.get_streams()?)
+        };
+        let consensus = self.consensus()?;
+        let mut metrics = Metrics::default();
+
+        metrics.family(
+            "tor_circuits",
+            "gauge",
+            "Current circuits by status and purpose",
+        );
+        let circuits = count(circuits.iter().map(|c| {
+            format!(
+                "{}\0{}",
+                c.status,
+                c.purpose
+                    .as_ref()
+                    .map(|p| p.to_string())
+                    .unwrap_or_default()
+            )
+        }));
+        for (key, value) in &circuits {
+            let (status, purpose) = key.split_once('\0').unwrap();
+            metrics.sample(
+                "tor_circuits",
+                &[("status", status), ("purpose", purpose)],
+                *value,
+            );
+        }
+
+        metrics.family("tor_streams", "gauge", "Current streams by status");
+        for (status, value) in count(streams.iter().map(|s| s.status.to_string())) {
+            metrics.sample("tor_streams", &[("status", &status)], value);
+        }
+
+        {
+            let counters = self.counters.lock().unwrap();
+            metrics.family(
+                "tor_circuit_builds",
+                "counter",
+                "Circuits built or failed, by failure reason",
+            );
+            metrics.sample(
+                "tor_circuit_builds_total",
+                &[("result", "success")],
+                counters.circuits_built,
+            );
+            for (reason, value) in &counters.circuits_failed {
+                metrics.sample(
+                    "tor_circuit_builds_total",
+                    &[("result", "failure"), ("reason", reason)],
+                    *value,
+                );
+            }
+
+            metrics.family("tor_read_bytes", "counter", "Bytes read by tor");
+            metrics.sample("tor_read_bytes_total", &[], counters.bytes_read);
+            metrics.family("tor_written_bytes", "counter", "Bytes written by tor");
+            metrics.sample("tor_written_bytes_total", &[], counters.bytes_written);
+        }
+
+        metrics.family(
+            "tor_consensus_relays",
+            "gauge",
+            "Relays of the consensus by flag",
+        );
+        for (flag, value) in &consensus.by_flag {
+            metrics.sample("tor_consensus_relays", &[("flag", flag)], *value);
+        }
+
+        metrics.family(
+            "tor_consensus_exit_relays",
+            "gauge",
+            "Usable exit relays of the consensus by country",
+        );
+        for (country, value) in &consensus.exits_by_country {
+            metrics.sample("tor_consensus_exit_relays", &[("country", country)], *value);
+        }
+
+        Ok(metrics.finish())
+    }
+
+    fn handle_event(&self, event: Event) {
+        let mut counters = self.counters.lock().unwrap();
+        match event {
+            Event::Circuit(circuit) => match circuit.status {
+                CircuitStatus::Built => counters.circuits_built += 1,
+                CircuitStatus::Failed => {
+                    let reason = circuit
+                        .reason
+                        .map(|r| r.to_string())
+                        .unwrap_or_else(|| "NONE".into());
+                    *counters.circuits_failed.entry(reason).or_default() += 1;
+                }
+                _ => {}
+            },
+            Event::Bandwidth(bandwidth) => {
+                counters.bytes_read += bandwidth.read;
+                counters.bytes_written += bandwidth.written;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn listen_events(control: &str, exporter: &Exporter) -> Result<(), Error> {
+    let mut ctrl = TorController::new(control)?;
+    ctrl.set_events(&["CIRC", "BW"])?;
+    loop {
+        match ctrl.wait_event() {
+            Ok(event) => exporter.handle_event(event),
+            Err(Error::Io(e)) => return Err(Error::Io(e)),
+            Err(e) => log::warn!("Invalid event: {}", e),
+        }
+    }
+}
+
+pub fn serve(control: &str, listen: Listen) -> Result<(), Error> {
+    // Read-only, so it may be scraped from another host
+    let (server, _socket) = bind(&listen, false)?;
+    let exporter = Arc::new(Exporter {
+        ctrl: Mutex::new(TorController::new(control)?),
+        consensus: Mutex::new(None),
+        counters: Mutex::new(Counters::default()),
+    });
+
+    let events_exporter = Arc::clone(&exporter);
+    let control = control.to_owned();
+    thread::spawn(move || {
+        if let Err(e) = listen_events(&control, &events_exporter) {
+            log::error!("Event listener stopped, counters are frozen: {}", e);
+        }
+    });
+
+    for request in server.incoming_requests() {
+        let response = match (request.method(), request.url().split('?').next()) {
+            (Method::Get, Some("/metrics")) => match exporter.render() {
+                Ok(metrics) => Response::from_string(metrics)
+                    .with_header(Header::from_bytes("Content-Type", CONTENT_TYPE).unwrap()),
+                Err(e) => {
+                    log::error!("Could not collect metrics: {}", e);
+                    Response::from_string(e.to_string()).with_status_code(500)
+                }
+            },
+            _ => Response::from_string("Metrics are served on /metrics\n").with_status_code(404),
+        };
+        if let Err(e) = request.respond(response) {
+            log::debug!("Client went away: {}", e);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,658 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+
+use tor_analyzer_lib::error::Error;
+use tor_analyzer_lib::prelude::*;
+
+mod daemon;
+mod exporter;
+mod output;
+
+use output::{print_item, print_list, print_streamed, Format};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Inspect and drive a tor daemon through its control port"
+)]
+struct Args {
+    /// Tor control socket (either IPv4/IPv6/Unix)
+    #[arg(
+        short,
+        long,
+        env = "TOR_CONTROL",
+        default_value = "/var/lib/tor/control.sock"
+    )]
+    control: String,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Table, global = true)]
+    format: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List circuits
+    Circuits,
+
+    /// List streams
+    Streams,
+
+    /// List relays of the consensus, optionally filtered by a query such as `flag:Exit country:de`
+    Relays { query: Vec<String> },
+
+    /// Show a relay of the consensus
+    Relay { fingerprint: RelayFingerprint },
+
+    /// Extend a circuit (`0` builds a new one) through comma separated relay fingerprints
+    Extend {
+        id: String,
+        #[arg(value_delimiter = ',', required = true)]
+        path: Vec<RelayFingerprint>,
+    },
+
+    /// Attach a stream to a circuit (`0` lets tor choose)
+    Attach { stream: String, circuit: String },
+
+    /// Close a circuit
+    CloseCircuit {
+        id: String,
+
+        /// Only close the circuit if no stream is using it
+        #[arg(long)]
+        if_unused: bool,
+    },
+
+    /// List the entry guards, the preferred ones first
+    Guards,
+
+    /// Forget every entry guard, tor picks new ones
+    DropGuards {
+        /// New guards make some attacks easier, confirm it is wanted
+        #[arg(long, required = true)]
+        yes: bool,
+    },
+
+    /// Map an address to another for every client, `0.0.0.0`, `[::]` or `.` for tor to pick one
+    MapAddress {
+        address: HostOrAddr,
+        new_address: HostOrAddr,
+    },
+
+    /// Resolve an address through tor
+    Resolve {
+        address: HostOrAddr,
+
+        /// Look up the name of an IP address
+        #[arg(long)]
+        reverse: bool,
+
+        /// Seconds to wait for the answer
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+
+    /// List tor's address mappings (all, config, cache or control)
+    AddressMappings {
+        #[arg(default_value_t = MappingSource::All)]
+        source: MappingSource,
+    },
+
+    /// Send a signal to tor (NEWNYM, RELOAD, CLEARDNSCACHE...)
+    Signal { signal: Signal },
+
+    /// Read configuration options
+    Getconf {
+        #[arg(required = true)]
+        keywords: Vec<String>,
+    },
+
+    /// Set configuration options, given as `Key=Value` or `Key` to reset to the default
+    Setconf {
+        #[arg(required = true)]
+        assignments: Vec<String>,
+    },
+
+    /// Print asynchronous events as they arrive
+    Events {
+        #[arg(default_values_t = ["CIRC".to_owned(), "STREAM".to_owned()])]
+        events: Vec<String>,
+    },
+
+    /// Manage the client authorization credentials of onion services
+    OnionAuth {
+        #[command(subcommand)]
+        action: OnionAuthAction,
+    },
+
+    /// Fetch the descriptor of an onion service, and show which HSDirs were asked and their answers
+    HsFetch {
+        service: HsAddress,
+
+        /// HSDir to query, tor picks them by default
+        #[arg(long = "server")]
+        servers: Vec<RelayFingerprint>,
+
+        /// Seconds to wait for the HSDirs to answer
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+    },
+
+    /// Serve a local HTTP/JSON API and a Server-Sent-Events feed
+    Serve {
+        /// Loopback address to listen on
+        #[arg(short, long, default_value = "127.0.0.1:9060")]
+        listen: SocketAddr,
+
+        /// Listen on a Unix socket instead
+        #[arg(short, long, conflicts_with = "listen")]
+        unix: Option<PathBuf>,
+
+        /// Serve the endpoints extending circuits, attaching streams and setting options
+        #[arg(long)]
+        allow_mutations: bool,
+
+        /// Require `Authorization: Bearer <token>` on every request
+        #[arg(long, env = "TOR_ANALYZER_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+
+    /// Serve OpenMetrics on /metrics, for Prometheus
+    Exporter {
+        /// Address to listen on, any address is allowed as nothing can be changed
+        #[arg(short, long, default_value = "127.0.0.1:9099")]
+        listen: SocketAddr,
+
+        /// Listen on a Unix socket instead
+        #[arg(short, long, conflicts_with = "listen")]
+        unix: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum OnionAuthAction {
+    /// Generate a x25519 key pair
+    Generate,
+
+    /// Install credentials for an onion service, generating a key unless one is given
+    Add {
+        service: HsAddress,
+
+        /// Base64 x25519 private key
+        #[arg(long)]
+        key: Option<X25519PrivateKey>,
+
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Save the credentials in ClientOnionAuthDir
+        #[arg(long)]
+        permanent: bool,
+    },
+
+    /// Remove the credentials of an onion service
+    Remove { service: HsAddress },
+
+    /// List the credentials tor has
+    List { service: Option<HsAddress> },
+}
+
+#[derive(Serialize)]
+struct Credentials {
+    service: Option<HsAddress>,
+    public_key: X25519PublicKey,
+    private_key: X25519PrivateKey,
+
+    /// Line of the `authorized_clients/<name>.auth` file of the service
+    authorized_client: String,
+}
+
+const CREDENTIALS_COLUMNS: &[&str] = &["service", "public_key", "private_key", "authorized_client"];
+
+fn credentials_row(credentials: &Credentials) -> Vec<String> {
+    vec![
+        format_option(credentials.service.as_ref()),
+        credentials.public_key.to_string(),
+        credentials.private_key.to_string(),
+        credentials.authorized_client.clone(),
+    ]
+}
+
+impl Credentials {
+    fn new(service: Option<HsAddress>, private_key: X25519PrivateKey) -> Self {
+        let public_key = private_key.public_key();
+        Self {
+            service,
+            public_key,
+            private_key,
+            authorized_client: public_key.to_auth_line(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ConfValue<'a> {
+    keyword: &'a str,
+    value: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Extended {
+    circuit: CircuitID,
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        3600..=86399 => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d{:02}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+fn format_option<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+const MAPPING_COLUMNS: &[&str] = &["address", "new_address", "expires"];
+
+fn mapping_row(mapping: &AddressMapping) -> Vec<String> {
+    vec![
+        mapping.address.to_string(),
+        mapping.new_address.to_string(),
+        format_option(mapping.expires),
+    ]
+}
+
+fn open_geoip() -> Option<GeoIP> {
+    GeoIP::open_default()
+        .map_err(|e| {
+            log::warn!(
+                "GeoIP databases unavailable, countries won't be shown: {}",
+                e
+            )
+        })
+        .ok()
+}
+
+/// Waits for the next event until `deadline`, skipping the ones which cannot
+/// be parsed.
+fn next_event(ctrl: &mut TorController, deadline: Option<Instant>) -> Result<Event, Error> {
+    let timed_out = || {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "No answer from tor in time",
+        ))
+    };
+    loop {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(timed_out());
+            }
+            ctrl.set_read_timeout(Some(remaining))?;
+        }
+        match ctrl.wait_event() {
+            Ok(event) => return Ok(event),
+            Err(Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Err(timed_out())
+            }
+            Err(Error::Io(e)) => return Err(Error::Io(e)),
+            Err(e) => log::warn!("Invalid event: {}", e),
+        }
+    }
+}
+
+const RELAY_COLUMNS: &[&str] = &[
+    "fingerprint",
+    "nickname",
+    "address",
+    "port",
+    "country",
+    "as",
+    "flags",
+    "bandwidth",
+    "published",
+];
+
+fn relay_row(relay: &Relay) -> Vec<String> {
+    vec![
+        relay.or.identity.to_string(),
+        relay.or.nickname.clone(),
+        relay.or.target.addr.to_string(),
+        relay.or.target.port.to_string(),
+        format_option(relay.country),
+        format_option(
+            relay
+                .asn
+                .as_ref()
+                .map(|(asn, org)| format!("AS{asn} {org}")),
+        ),
+        relay.or.flags.to_string(),
+        format_option(relay.or.bandwidth),
+        relay.or.publication.to_string(),
+    ]
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let format = args.format;
+    match args.command {
+        Command::Serve {
+            listen,
+            unix,
+            allow_mutations,
+            token,
+        } => {
+            let listen = match unix {
+                Some(path) => daemon::Listen::Unix(path),
+                None => daemon::Listen::Tcp(listen),
+            };
+            return daemon::serve(&args.control, listen, allow_mutations, token);
+        }
+        Command::Exporter { listen, unix } => {
+            let listen = match unix {
+                Some(path) => daemon::Listen::Unix(path),
+                None => daemon::Listen::Tcp(listen),
+            };
+            return exporter::serve(&args.control, listen);
+        }
+        _ => {}
+    }
+    let mut ctrl = TorController::new(&args.control)?;
+
+    match args.command {
+        Command::Circuits => {
+            let circuits = ctrl.get_circuits()?;
+            print_list(
+                format,
+                &circuits,
+                &["id", "status", "purpose", "age", "path"],
+                |c| {
+                    vec![
+                        c.id.to_string(),
+                        c.status.to_string(),
+                        format_option(c.purpose.as_ref()),
+                        format_option(c.age().map(format_duration)),
+                        c.path
+                            .iter()
+                            .map(|s| s.to_string())
+                            .collect::<Vec<_>>()
+                            .join(","),
+                    ]
+                },
+            )?;
+        }
+        Command::Streams => {
+            let streams = ctrl.get_streams()?;
+            print_list(
+                format,
+                &streams,
+                &["id", "status", "circuit", "target"],
+                |s| {
+                    vec![
+                        s.id.to_string(),
+                        s.status.to_string(),
+                        s.circuit_id.to_string(),
+                        s.target.to_string(),
+                    ]
+                },
+            )?;
+        }
+        Command::Relays { query } => {
+            let query: Query = query.join(" ").parse()?;
+            let index = RelayIndex::new(ctrl.get_all_onion_router()?, open_geoip().as_ref());
+            let relays: Vec<&Relay> = index.query(&query);
+            print_list(format, &relays, RELAY_COLUMNS, |r| relay_row(r))?;
+        }
+        Command::Relay { fingerprint } => {
+            let or = ctrl.get_onion_router(&fingerprint)?;
+            let relay = Relay::new(or, open_geoip().as_ref());
+            print_item(format, &relay, RELAY_COLUMNS, relay_row(&relay))?;
+        }
+        Command::Extend { id, path } => {
+            let response = ctrl.extend_circuit(CircuitID(id), &path)?;
+            let circuit = response
+                .trim_end()
+                .strip_prefix("EXTENDED ")
+                .ok_or_else(|| Error::Protocol(format!("Unexpected reply {response:?}")))?;
+            let extended = Extended {
+                circuit: CircuitID(circuit.into()),
+            };
+            print_item(
+                format,
+                &extended,
+                &["circuit"],
+                vec![extended.circuit.to_string()],
+            )?;
+        }
+        Command::Attach { stream, circuit } => {
+            ctrl.attach_stream(StreamID(stream), CircuitID(circuit))?;
+        }
+        Command::CloseCircuit { id, if_unused } => {
+            ctrl.close_circuit(CircuitID(id), if_unused)?;
+        }
+        Command::Guards => {
+            let guards = ctrl.get_guards(open_geoip().as_ref())?;
+            print_list(
+                format,
+                &guards,
+                &[
+                    "fingerprint",
+                    "nickname",
+                    "address",
+                    "country",
+                    "status",
+                    "since",
+                ],
+                |g| {
+                    vec![
+                        g.fingerprint().to_string(),
+                        format_option(g.nickname()),
+                        format_option(g.relay.as_ref().map(|r| &r.or.target)),
+                        format_option(g.relay.as_ref().and_then(|r| r.country)),
+                        g.entry.status.to_string(),
+                        format_option(g.entry.since),
+                    ]
+                },
+            )?;
+        }
+        Command::DropGuards { .. } => {
+            ctrl.drop_guards()?;
+        }
+        Command::MapAddress {
+            address,
+            new_address,
+        } => {
+            let mapping = AddressMapping {
+                address: ctrl.map_address(&address, &new_address)?,
+                new_address,
+                expires: None,
+            };
+            print_item(format, &mapping, MAPPING_COLUMNS, mapping_row(&mapping))?;
+        }
+        Command::Resolve {
+            address,
+            reverse,
+            timeout,
+        } => {
+            ctrl.set_events(&["ADDRMAP"])?;
+            ctrl.resolve(&address, reverse)?;
+            let deadline = Instant::now() + Duration::from_secs(timeout);
+            let addrmap = loop {
+                if let Event::AddrMap(addrmap) = next_event(&mut ctrl, Some(deadline))? {
+                    if addrmap.address == address && addrmap.reverse == reverse {
+                        break addrmap;
+                    }
+                }
+            };
+            print_item(
+                format,
+                &addrmap,
+                &["address", "new_address", "expires", "error", "cached"],
+                vec![
+                    addrmap.address.to_string(),
+                    format_option(addrmap.new_address.as_ref()),
+                    format_option(addrmap.expires),
+                    format_option(addrmap.error.as_ref()),
+                    format_option(addrmap.cached),
+                ],
+            )?;
+        }
+        Command::AddressMappings { source } => {
+            let mappings = ctrl.get_address_mappings(source)?;
+            print_list(format, &mappings, MAPPING_COLUMNS, mapping_row)?;
+        }
+        Command::Signal { signal } => {
+            ctrl.signal(signal)?;
+        }
+        Command::Getconf { keywords } => {
+            let values = ctrl.get_conf_values(&keywords)?;
+            let values: Vec<ConfValue> = values
+                .iter()
+                .map(|(keyword, value)| ConfValue {
+                    keyword,
+                    value: value.as_deref(),
+                })
+                .collect();
+            print_list(format, &values, &["keyword", "value"], |v| {
+                vec![v.keyword.to_owned(), format_option(v.value)]
+            })?;
+        }
+        Command::Setconf { assignments } => {
+            // A single SETCONF, so that tor applies all or none of them
+            let values: Vec<(&str, Option<&str>)> = assignments
+                .iter()
+                .map(|assignment| match assignment.split_once('=') {
+                    Some((keyword, value)) => (keyword, Some(value)),
+                    None => (assignment.as_str(), None),
+                })
+                .collect();
+            ctrl.set_conf_values(&values)?;
+        }
+        Command::Events { events } => {
+            let events: Vec<String> = events.iter().map(|e| e.to_uppercase()).collect();
+            ctrl.set_events(&events)?;
+            if format == Format::Csv {
+                output::write_csv_line(&mut std::io::stdout(), ["time", "event", "data"])?;
+            }
+            loop {
+                let event = next_event(&mut ctrl, None)?;
+                let time = Time::now();
+                let data = event.to_string();
+                let data = data
+                    .strip_prefix(event.name())
+                    .unwrap_or(&data)
+                    .trim()
+                    .to_owned();
+                print_streamed(
+                    format,
+                    &event,
+                    vec![time.to_string(), event.name().to_owned(), data],
+                )?;
+            }
+        }
+        Command::OnionAuth { action } => match action {
+            OnionAuthAction::Generate => {
+                let credentials = Credentials::new(None, X25519PrivateKey::generate());
+                print_item(
+                    format,
+                    &credentials,
+                    CREDENTIALS_COLUMNS,
+                    credentials_row(&credentials),
+                )?;
+            }
+            OnionAuthAction::Add {
+                service,
+                key,
+                name,
+                permanent,
+            } => {
+                let private_key = key.unwrap_or_else(X25519PrivateKey::generate);
+                let mut auth = ClientAuth::new(service.clone(), private_key.clone());
+                auth.client_name = name;
+                auth.permanent = permanent;
+                ctrl.onion_client_auth_add(&auth)?;
+                let credentials = Credentials::new(Some(service), private_key);
+                print_item(
+                    format,
+                    &credentials,
+                    CREDENTIALS_COLUMNS,
+                    credentials_row(&credentials),
+                )?;
+            }
+            OnionAuthAction::Remove { service } => {
+                if !ctrl.onion_client_auth_remove(&service)? {
+                    log::warn!("No credentials for {}", service);
+                }
+            }
+            OnionAuthAction::List { service } => {
+                let auths = ctrl.onion_client_auth_view(service.as_ref())?;
+                print_list(
+                    format,
+                    &auths,
+                    &["service", "name", "permanent", "public_key"],
+                    |a| {
+                        vec![
+                            a.service_id.to_string(),
+                            format_option(a.client_name.as_ref()),
+                            a.permanent.to_string(),
+                            a.private_key.public_key().to_string(),
+                        ]
+                    },
+                )?;
+            }
+        },
+        Command::HsFetch {
+            service,
+            servers,
+            timeout,
+        } => {
+            ctrl.set_events(&["HS_DESC", "HS_DESC_CONTENT", "CIRC"])?;
+            ctrl.hs_fetch(&service, &servers)?;
+            let deadline = Instant::now() + Duration::from_secs(timeout);
+            let mut timeline = HsTimeline::default();
+            while !timeline.get(&service).is_some_and(|s| s.is_settled()) {
+                timeline.handle_event(&next_event(&mut ctrl, Some(deadline))?);
+            }
+            let events: Vec<_> = timeline.get(&service).unwrap().events().collect();
+            print_list(format, &events, &["time", "kind", "relay", "event"], |e| {
+                vec![
+                    e.time.to_string(),
+                    e.entry.kind().to_owned(),
+                    format_option(e.entry.relay()),
+                    e.entry.to_string(),
+                ]
+            })?;
+        }
+        Command::Serve { .. } | Command::Exporter { .. } => unreachable!(),
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+
+    let args = Args::parse();
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("tor-analyzer: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Aligned columns, for humans
+    Table,
+
+    /// JSON document, or one JSON object per line for event streams
+    Json,
+
+    /// Comma separated values, with a header line
+    Csv,
+}
+
+/// Prints `items`, using `columns` and `row` to build the table and CSV outputs.
+pub fn print_list<T, F>(format: Format, items: &[T], columns: &[&str], row: F) -> io::Result<()>
+where
+    T: Serialize,
+    F: Fn(&T) -> Vec<String>,
+{
+    let mut stdout = io::stdout().lock();
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut stdout, items)?;
+            writeln!(stdout)
+        }
+        Format::Csv => {
+            write_csv_line(&mut stdout, columns.iter().copied())?;
+            for item in items {
+                write_csv_line(&mut stdout, row(item).iter().map(String::as_str))?;
+            }
+            Ok(())
+        }
+        Format::Table => {
+            let rows: Vec<Vec<String>> = items.iter().map(row).collect();
+            let mut widths: Vec<usize> = columns.iter().map(|c| c.chars().count()).collect();
+            for row in rows.iter() {
+                for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                    *width = (*width).max(cell.chars().count());
+                }
+            }
+            let header: Vec<String> = columns.iter().map(|c| c.to_uppercase()).collect();
+            write_table_line(&mut stdout, &widths, &header)?;
+            for row in rows.iter() {
+                write_table_line(&mut stdout, &widths, row)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Prints a single item, tables are shown vertically.
+pub fn print_item<T>(format: Format, item: &T, columns: &[&str], row: Vec<String>) -> io::Result<()>
+where
+    T: Serialize,
+{
+    let mut stdout = io::stdout().lock();
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut stdout, item)?;
+            writeln!(stdout)
+        }
+        Format::Csv => {
+            write_csv_line(&mut stdout, columns.iter().copied())?;
+            write_csv_line(&mut stdout, row.iter().map(String::as_str))
+        }
+        Format::Table => {
+            let width = columns.iter().map(|c| c.chars().count()).max().unwrap_or(0);
+            for (column, value) in columns.iter().zip(row.iter()) {
+                writeln!(stdout, "{column:<width$}  {value}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Prints one element of a stream, flushing it right away.
+pub fn print_streamed<T>(format: Format, item: &T, row: Vec<String>) -> io::Result<()>
+where
+    T: Serialize,
+{
+    let mut stdout = io::stdout().lock();
+    match format {
+        Format::Json => {
+            serde_json::to_writer(&mut stdout, item)?;
+            writeln!(stdout)?;
+        }
+        Format::Csv => write_csv_line(&mut stdout, row.iter().map(String::as_str))?,
+        Format::Table => writeln!(stdout, "{}", row.join("  "))?,
+    }
+    stdout.flush()
+}
+
+fn write_table_line<W: Write>(w: &mut W, widths: &[usize], row: &[String]) -> io::Result<()> {
+    let mut line = String::new();
+    for (i, (width, cell)) in widths.iter().zip(row.iter()).enumerate() {
+        if i + 1 == row.len() {
+            line.push_str(cell);
+        } else {
+            line.push_str(&format!("{cell:<width$}  "));
+        }
+    }
+    writeln!(w, "{}", line.trim_end())
+}
+
+pub fn write_csv_line<'a, W, I>(w: &mut W, cells: I) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut line = String::new();
+    for (i, cell) in cells.into_iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+        if cell.contains([',', '"', '\n', '\r']) {
+            line.push('"');
+            line.push_str(&cell.replace('"', "\"\""));
+            line.push('"');
+        } else {
+            line.push_str(cell);
+        }
+    }
+    writeln!(w, "{line}")
+}
@@ -0,0 +1,260 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use gtk::cairo;
+use gtk::prelude::*;
+
+use tor_analyzer_lib::prelude::*;
+use tor_analyzer_lib::traffic::{Sample, Series};
+
+use crate::notebook::NotebookTab;
+
+/// Seconds the displayed rates are averaged on.
+const RATE_WINDOW: u32 = 5;
+
+pub(crate) fn format_rate(bytes_per_second: f64) -> String {
+    const UNITS: [&str; 4] = ["B/s", "KiB/s", "MiB/s", "GiB/s"];
+    let mut value = bytes_per_second;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{value:.0} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Total,
+    Circuit(CircuitID),
+    Stream(StreamID),
+}
+
+#[repr(i32)]
+enum Columns {
+    Kind,
+    Id,
+    Read,
+    Written,
+}
+const FIELD_COUNT: usize = Columns::Written as usize + 1;
+
+pub(crate) struct BandwidthTab {
+    traffic: RefCell<TrafficMonitor>,
+    selected: RefCell<Source>,
+    updating: Cell<bool>,
+    widget: Cell<Option<Rc<gtk::Widget>>>,
+    graph: gtk::DrawingArea,
+    treeview: gtk::TreeView,
+    store: gtk::ListStore,
+}
+
+impl BandwidthTab {
+    pub(crate) fn new() -> Rc<Self> {
+        let col_types = [glib::Type::STRING; FIELD_COUNT];
+        let store = gtk::ListStore::new(&col_types);
+        let me = Self {
+            traffic: RefCell::new(TrafficMonitor::default()),
+            selected: RefCell::new(Source::Total),
+            updating: Cell::new(false),
+            widget: Cell::new(None),
+            graph: gtk::DrawingArea::new(),
+            treeview: gtk::TreeView::with_model(&store),
+            store,
+        };
+        me.create()
+    }
+
+    fn create(self) -> Rc<Self> {
+        let me = Rc::new(self);
+        Rc::clone(&me).create_ui();
+        me
+    }
+
+    fn create_ui(self: Rc<Self>) {
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 8);
+        vbox.set_homogeneous(false);
+
+        self.graph.set_size_request(-1, 200);
+        let me = Rc::clone(&self);
+        self.graph.connect_draw(move |area, cr| {
+            if let Err(e) = me.draw(area, cr) {
+                log::warn!("Could not draw bandwidth graph: {}", e);
+            }
+            gtk::Inhibit(false)
+        });
+        vbox.add(&self.graph);
+
+        let sw = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        sw.set_shadow_type(gtk::ShadowType::EtchedIn);
+        sw.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        vbox.add(&sw);
+
+        let treeview = &self.treeview;
+        treeview.set_vexpand(true);
+        sw.add(treeview);
+
+        add_column!(treeview, Columns::Kind, "Kind");
+        add_column!(treeview, Columns::Id, "Id");
+        add_column!(treeview, Columns::Read, "Read");
+        add_column!(treeview, Columns::Written, "Written");
+
+        let me = Rc::clone(&self);
+        treeview.selection().connect_changed(move |selection| {
+            if me.updating.get() {
+                return;
+            }
+            if let Some((model, iter)) = selection.selected() {
+                let kind = model.value(&iter, Columns::Kind as i32).get::<String>();
+                let id = model.value(&iter, Columns::Id as i32).get::<String>();
+                let source = match (kind.as_deref(), id) {
+                    (Ok("Circuit"), Ok(id)) => Source::Circuit(CircuitID(id)),
+                    (Ok("Stream"), Ok(id)) => Source::Stream(StreamID(id)),
+                    _ => Source::Total,
+                };
+                me.selected.replace(source);
+                me.graph.queue_draw();
+            }
+        });
+
+        self.refresh_view();
+
+        let widget = Some(Rc::new(vbox.upcast()));
+        self.widget.set(widget);
+    }
+
+    /// Feeds the series, and redraws once per second on `BW` events.
+    pub(crate) fn handle_event(&self, event: &Event) {
+        let relevant = self.traffic.borrow_mut().handle_event(event);
+        if relevant && matches!(event, Event::Bandwidth(_)) {
+            self.refresh_view();
+            self.graph.queue_draw();
+        }
+    }
+
+    /// Average bytes read and written per second by a circuit, over the last seconds.
+    pub(crate) fn circuit_rate(&self, id: &CircuitID) -> Option<(f64, f64)> {
+        let now = Time::now().unix_timestamp();
+        self.traffic
+            .borrow()
+            .circuit(id)
+            .map(|series| series.rate(now, RATE_WINDOW))
+    }
+
+    fn refresh_view(&self) {
+        let traffic = self.traffic.borrow();
+        let now = Time::now().unix_timestamp();
+
+        let mut circuits: Vec<_> = traffic.circuits().collect();
+        circuits.sort_by_key(|(id, _)| id.0.parse::<u64>().unwrap_or(u64::MAX));
+        let mut streams: Vec<_> = traffic.streams().collect();
+        streams.sort_by_key(|(id, _)| id.0.parse::<u64>().unwrap_or(u64::MAX));
+
+        let rows = std::iter::once((Source::Total, traffic.total()))
+            .chain(
+                circuits
+                    .into_iter()
+                    .map(|(id, series)| (Source::Circuit(id.clone()), series)),
+            )
+            .chain(
+                streams
+                    .into_iter()
+                    .map(|(id, series)| (Source::Stream(id.clone()), series)),
+            );
+
+        self.updating.set(true);
+        self.store.clear();
+        let selected = self.selected.borrow();
+        for (source, series) in rows {
+            let (kind, id) = match source {
+                Source::Total => ("Total", String::new()),
+                Source::Circuit(ref id) => ("Circuit", id.to_string()),
+                Source::Stream(ref id) => ("Stream", id.to_string()),
+            };
+            let (read, written) = series.rate(now, RATE_WINDOW);
+            let values: [(u32, &dyn ToValue); FIELD_COUNT] = [
+                (0, &kind),
+                (1, &id),
+                (2, &format_rate(read)),
+                (3, &format_rate(written)),
+            ];
+            let iter = self.store.append();
+            self.store.set(&iter, &values);
+            if source == *selected {
+                self.treeview.selection().select_iter(&iter);
+            }
+        }
+        self.updating.set(false);
+    }
+
+    fn draw(&self, area: &gtk::DrawingArea, cr: &cairo::Context) -> Result<(), cairo::Error> {
+        let width = area.allocated_width() as f64;
+        let height = area.allocated_height() as f64;
+        cr.set_source_rgb(1., 1., 1.);
+        cr.paint()?;
+
+        let traffic = self.traffic.borrow();
+        let selected = self.selected.borrow();
+        let (title, series) = match *selected {
+            Source::Total => ("All traffic".to_owned(), Some(traffic.total())),
+            Source::Circuit(ref id) => (format!("Circuit {id}"), traffic.circuit(id)),
+            Source::Stream(ref id) => (format!("Stream {id}"), traffic.stream(id)),
+        };
+        cr.set_source_rgb(0., 0., 0.);
+        cr.set_font_size(12.);
+        cr.move_to(5., 15.);
+        let series: &Series = match series {
+            Some(series) if !series.is_empty() => series,
+            _ => {
+                cr.show_text(&format!("{title}: no traffic yet"))?;
+                return Ok(());
+            }
+        };
+        let peak = series.peak().max(1) as f64;
+        cr.show_text(&format!("{title}, peak {}", format_rate(peak)))?;
+
+        let step = width / (series.capacity().max(2) - 1) as f64;
+        let plot_height = height - 25.;
+        let lines = [
+            (
+                (0.2, 0.6, 0.2),
+                "read",
+                (|s: &Sample| s.read) as fn(&Sample) -> u64,
+            ),
+            ((0.2, 0.4, 0.8), "written", |s: &Sample| s.written),
+        ];
+        for (i, ((r, g, b), legend, value)) in lines.into_iter().enumerate() {
+            cr.set_source_rgb(r, g, b);
+            for (j, sample) in series.samples().rev().enumerate() {
+                let x = width - j as f64 * step;
+                let y = height - value(sample) as f64 / peak * plot_height;
+                if j == 0 {
+                    cr.move_to(x, y);
+                } else {
+                    cr.line_to(x, y);
+                }
+            }
+            cr.stroke()?;
+            cr.move_to(width - 140. + 70. * i as f64, 15.);
+            cr.show_text(legend)?;
+        }
+        Ok(())
+    }
+}
+
+impl NotebookTab for BandwidthTab {
+    fn get_widget(&self) -> Rc<gtk::Widget> {
+        let widget = self.widget.take().unwrap();
+        let copy = Rc::clone(&widget);
+        self.widget.set(Some(widget));
+        copy
+    }
+
+    fn label(&self) -> &'static str {
+        "Bandwidth"
+    }
+}
@@ -0,0 +1,123 @@
+use std::rc::Rc;
+
+use gtk::prelude::*;
+
+use tor_analyzer_lib::prelude::*;
+use tor_analyzer_lib::tor::status::{StatusAction, StatusSeverity};
+
+/// Bootstrap progress bar, and a banner for the warnings tor reports.
+pub(crate) struct StatusBar {
+    widget: gtk::Box,
+    progress: gtk::ProgressBar,
+    banner: gtk::InfoBar,
+    message: gtk::Label,
+}
+
+impl StatusBar {
+    pub(crate) fn new() -> Rc<Self> {
+        let me = Self {
+            widget: gtk::Box::new(gtk::Orientation::Vertical, 4),
+            progress: gtk::ProgressBar::new(),
+            banner: gtk::InfoBar::new(),
+            message: gtk::Label::new(None),
+        };
+        me.create()
+    }
+
+    fn create(self) -> Rc<Self> {
+        let me = Rc::new(self);
+        Rc::clone(&me).create_ui();
+        me
+    }
+
+    fn create_ui(self: Rc<Self>) {
+        self.progress.set_show_text(true);
+        self.progress.set_no_show_all(true);
+        self.widget.add(&self.progress);
+
+        self.message.set_xalign(0.);
+        self.message.set_line_wrap(true);
+        self.message.show();
+        self.banner.content_area().add(&self.message);
+        self.banner.set_show_close_button(true);
+        self.banner.set_no_show_all(true);
+        self.banner
+            .connect_response(|banner, _response| banner.hide());
+        self.widget.add(&self.banner);
+
+        let mutex = crate::get_tor_controller();
+        let mut ctrl = mutex.lock().unwrap();
+        match ctrl.get_bootstrap_phase() {
+            Ok(bootstrap) => {
+                drop(ctrl);
+                self.set_bootstrap(StatusSeverity::Notice, &bootstrap);
+            }
+            Err(e) => {
+                drop(ctrl);
+                self.warn(
+                    StatusSeverity::Warn,
+                    &format!("Could not read the bootstrap phase: {e}"),
+                );
+            }
+        }
+    }
+
+    pub(crate) fn widget(&self) -> &gtk::Box {
+        &self.widget
+    }
+
+    pub(crate) fn handle_event(&self, event: &Event) {
+        let status = match event {
+            Event::GeneralStatus(status) | Event::ClientStatus(status) => status,
+            _ => return,
+        };
+        match status.action {
+            StatusAction::Bootstrap(ref bootstrap) => {
+                self.set_bootstrap(status.severity, bootstrap);
+            }
+            StatusAction::CircuitEstablished | StatusAction::EnoughDirInfo => self.banner.hide(),
+            ref action if status.severity >= StatusSeverity::Warn => {
+                self.warn(status.severity, &action.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn set_bootstrap(&self, severity: StatusSeverity, bootstrap: &Bootstrap) {
+        self.progress
+            .set_fraction(f64::from(bootstrap.progress) / 100.);
+        self.progress.set_text(Some(&format!(
+            "Bootstrapped {}%: {}",
+            bootstrap.progress, bootstrap.summary
+        )));
+        self.progress.set_visible(!bootstrap.is_done());
+
+        match bootstrap.warning {
+            Some(ref warning) if severity >= StatusSeverity::Warn => {
+                let mut message =
+                    format!("Bootstrap stuck at {}%: {}", bootstrap.progress, warning);
+                if let Some(ref reason) = bootstrap.reason {
+                    message.push_str(&format!(" ({reason})"));
+                }
+                if let Some(ref hostaddr) = bootstrap.hostaddr {
+                    message.push_str(&format!(", relay {hostaddr}"));
+                }
+                self.warn(severity, &message);
+            }
+            _ if bootstrap.is_done() => self.banner.hide(),
+            _ => {}
+        }
+    }
+
+    fn warn(&self, severity: StatusSeverity, message: &str) {
+        log::warn!("{}", message);
+        self.banner
+            .set_message_type(if severity == StatusSeverity::Error {
+                gtk::MessageType::Error
+            } else {
+                gtk::MessageType::Warning
+            });
+        self.message.set_text(message);
+        self.banner.show();
+    }
+}
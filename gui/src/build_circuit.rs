@@ -110,7 +110,10 @@ impl CircuitTab {
             let mut path = Vec::new();
             store.foreach(|model, _path, iter| {
                 let value = model.value(iter, Columns::Identity as i32);
-                path.push(value.get::<String>().unwrap());
+                match value.get::<String>().unwrap().parse::<RelayFingerprint>() {
+                    Ok(fingerprint) => path.push(fingerprint),
+                    Err(e) => log::warn!("Invalid relay identity: {}", e),
+                }
                 false
             });
 
@@ -127,7 +130,7 @@ impl CircuitTab {
             let mutex = crate::get_tor_controller();
             let mut ctrl = mutex.lock().unwrap();
 
-            if let Err(e) = ctrl.extend_circuit(circuit_id, path) {
+            if let Err(e) = ctrl.extend_circuit(circuit_id, &path) {
                 popup_error!("Could not extend circuit: {}", e);
                 return;
             }
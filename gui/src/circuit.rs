@@ -8,6 +8,7 @@ use tor_analyzer_lib::country;
 use tor_analyzer_lib::error::Error;
 use tor_analyzer_lib::prelude::*;
 
+use crate::bandwidth::{format_rate, BandwidthTab};
 use crate::notebook::NotebookTab;
 
 struct Circuit {
@@ -85,12 +86,14 @@ enum Columns {
     Countries,
     Path,
     EndPoint,
+    Throughput,
     MaxColumns,
 }
 const FIELD_COUNT: usize = Columns::MaxColumns as usize;
 
 pub(crate) struct CircuitTab {
     circuits: Cell<Option<Vec<Circuit>>>,
+    bandwidth_tab: Cell<Option<Rc<BandwidthTab>>>,
     widget: Cell<Option<Rc<gtk::Widget>>>,
     store: gtk::ListStore,
 }
@@ -100,12 +103,47 @@ impl CircuitTab {
         let col_types = [glib::Type::STRING; FIELD_COUNT];
         let me = Self {
             circuits: Cell::new(None),
+            bandwidth_tab: Cell::new(None),
             widget: Cell::new(None),
             store: gtk::ListStore::new(&col_types),
         };
         me.create()
     }
 
+    pub(crate) fn set_bandwidth_tab(&self, bandwidth_tab: Rc<BandwidthTab>) {
+        if self.bandwidth_tab.take().is_some() {
+            log::warn!("Overwrite previous entry");
+        }
+        self.bandwidth_tab.set(Some(bandwidth_tab));
+    }
+
+    fn throughput(&self, id: &CircuitID) -> String {
+        let bandwidth_tab = match self.bandwidth_tab.take() {
+            Some(b) => b,
+            None => return String::new(),
+        };
+        let throughput = match bandwidth_tab.circuit_rate(id) {
+            Some((read, written)) => {
+                format!("↓ {}\n↑ {}", format_rate(read), format_rate(written))
+            }
+            None => String::new(),
+        };
+        self.bandwidth_tab.set(Some(bandwidth_tab));
+        throughput
+    }
+
+    /// Updates the throughput column only, without querying tor.
+    pub(crate) fn refresh_throughput(&self) {
+        self.store.foreach(|model, _path, iter| {
+            if let Ok(id) = model.value(iter, Columns::Id as i32).get::<String>() {
+                let throughput = self.throughput(&CircuitID(id));
+                self.store
+                    .set_value(iter, Columns::Throughput as u32, &throughput.to_value());
+            }
+            false
+        });
+    }
+
     fn create(self) -> Rc<Self> {
         let me = Rc::new(self);
         Rc::clone(&me).create_ui();
@@ -155,6 +193,7 @@ impl CircuitTab {
         add_column!(treeview, Columns::Countries, "Countries");
         add_column!(treeview, Columns::Path, "Path");
         add_column!(treeview, Columns::EndPoint, "End point");
+        add_column!(treeview, Columns::Throughput, "Throughput");
 
         let update_btn = gtk::Button::with_label("Update");
         let me = Rc::clone(&self);
@@ -188,7 +227,7 @@ impl CircuitTab {
 
             for step in c.path.iter() {
                 let mut ctrl = mutex.lock().unwrap();
-                let or = ctrl.get_onion_router(hex_encode(step.fingerprint))?;
+                let or = ctrl.get_onion_router(&step.fingerprint)?;
                 drop(ctrl);
                 path.push(or);
             }
@@ -245,6 +284,7 @@ impl CircuitTab {
                 (3, &c.countries()),
                 (4, &c.path()),
                 (5, &c.endpoint()),
+                (6, &self.throughput(&c.circuit.id)),
             ];
             self.store.set(&self.store.append(), &values);
         }
@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tor_analyzer_lib::error::Error;
+use tor_analyzer_lib::prelude::*;
+
+/// Events the tabs are interested in.
+const EVENTS: &[&str] = &[
+    "BW",
+    "CIRC",
+    "CIRC_BW",
+    "STREAM",
+    "STREAM_BW",
+    "BUILDTIMEOUT_SET",
+    "ORCONN",
+    "STATUS_CLIENT",
+    "STATUS_GENERAL",
+    "HS_DESC",
+    "HS_DESC_CONTENT",
+    "GUARD",
+    "ADDRMAP",
+];
+
+fn subscribe(ctrl: &mut TorController, severity: LogSeverity) -> Result<(), Error> {
+    let mut events: Vec<&str> = EVENTS.to_vec();
+    events.extend(severity.and_above().map(|s| s.as_str()));
+    ctrl.set_events(&events)
+}
+
+/// Reads asynchronous events on a dedicated control connection, and hands
+/// them over to the GTK main loop.
+///
+/// Log messages are received from `severity` on, a change is applied with
+/// the next event, `BW` ones arriving every second.
+pub(crate) fn spawn_listener(
+    control: String,
+    severity: Arc<Mutex<LogSeverity>>,
+) -> glib::Receiver<Event> {
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    thread::spawn(move || {
+        let result = (|| -> Result<(), Error> {
+            let mut ctrl = TorController::new(&control)?;
+            let mut subscribed = *severity.lock().unwrap();
+            subscribe(&mut ctrl, subscribed)?;
+            loop {
+                let wanted = *severity.lock().unwrap();
+                if wanted != subscribed {
+                    subscribe(&mut ctrl, wanted)?;
+                    subscribed = wanted;
+                }
+                match ctrl.wait_event() {
+                    Ok(event) => {
+                        if tx.send(event).is_err() {
+                            return Ok(());
+                        }
+                    }
+                    Err(Error::Io(e)) => return Err(Error::Io(e)),
+                    Err(e) => log::warn!("Invalid event: {}", e),
+                }
+            }
+        })();
+        if let Err(e) = result {
+            log::error!("Event listener stopped: {}", e);
+        }
+    });
+    rx
+}
@@ -0,0 +1,211 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use gtk::prelude::*;
+
+use tor_analyzer_lib::error::Error;
+use tor_analyzer_lib::prelude::*;
+
+use crate::notebook::NotebookTab;
+
+#[repr(i32)]
+enum Columns {
+    Position,
+    Fingerprint,
+    Nickname,
+    Address,
+    Country,
+    Status,
+    Since,
+    LastEvent,
+}
+const FIELD_COUNT: usize = Columns::LastEvent as usize + 1;
+const COLUMNS_TYPE: [glib::Type; FIELD_COUNT] = [
+    glib::Type::U32,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+];
+
+pub(crate) struct GuardTab {
+    gi: GeoIP,
+    guards: RefCell<GuardSet>,
+    widget: Cell<Option<Rc<gtk::Widget>>>,
+    store: gtk::ListStore,
+}
+
+impl GuardTab {
+    pub(crate) fn new() -> Rc<Self> {
+        let me = Self {
+            gi: GeoIP::new(),
+            guards: RefCell::new(GuardSet::default()),
+            widget: Cell::new(None),
+            store: gtk::ListStore::new(&COLUMNS_TYPE),
+        };
+        me.create()
+    }
+
+    fn create(self) -> Rc<Self> {
+        let me = Rc::new(self);
+        Rc::clone(&me).create_ui();
+        me
+    }
+
+    fn create_ui(self: Rc<Self>) {
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 8);
+        vbox.set_homogeneous(false);
+
+        let sw = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        sw.set_shadow_type(gtk::ShadowType::EtchedIn);
+        sw.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        vbox.add(&sw);
+
+        let treeview = gtk::TreeView::with_model(&self.store);
+        treeview.set_vexpand(true);
+        sw.add(&treeview);
+
+        add_column!(treeview, Columns::Position, "#");
+        add_column!(treeview, Columns::Fingerprint, "Fingerprint");
+        add_column!(treeview, Columns::Nickname, "Nickname");
+        add_column!(treeview, Columns::Address, "Address");
+        add_column!(treeview, Columns::Country, "Country");
+        add_column!(treeview, Columns::Status, "Status");
+        add_column!(treeview, Columns::Since, "Since");
+        add_column!(treeview, Columns::LastEvent, "Last event");
+
+        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        hbox.set_homogeneous(true);
+
+        let update_btn = gtk::Button::with_label("Update guards");
+        let me = Rc::clone(&self);
+        update_btn.connect_clicked(move |_| {
+            if let Err(e) = me.refresh() {
+                log::warn!("Could not refresh guards: {}", e);
+            }
+        });
+        hbox.add(&update_btn);
+
+        let drop_btn = gtk::Button::with_label("Drop guards");
+        let me = Rc::clone(&self);
+        drop_btn.connect_clicked(move |_| me.confirm_drop());
+        hbox.add(&drop_btn);
+        vbox.add(&hbox);
+
+        update_btn.clicked();
+
+        let widget = Some(Rc::new(vbox.upcast()));
+        self.widget.set(widget);
+    }
+
+    /// Asks before dropping the guards, new ones weaken the anonymity.
+    fn confirm_drop(self: &Rc<Self>) {
+        let dialog = gtk::MessageDialog::new(
+            None::<&gtk::ApplicationWindow>,
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Warning,
+            gtk::ButtonsType::OkCancel,
+            "Drop all entry guards?",
+        );
+        dialog.set_secondary_text(Some(
+            "Tor will pick new guards. Changing guards exposes the circuits to more relays, \
+             and makes some deanonymization attacks easier.",
+        ));
+        let me = Rc::clone(self);
+        dialog.connect_response(move |dialog, response| {
+            unsafe {
+                dialog.destroy();
+            }
+            if response != gtk::ResponseType::Ok {
+                return;
+            }
+            let result = crate::get_tor_controller().lock().unwrap().drop_guards();
+            match result {
+                Ok(()) => {
+                    if let Err(e) = me.refresh() {
+                        log::warn!("Could not refresh guards: {}", e);
+                    }
+                }
+                Err(e) => popup_error!("Could not drop guards: {}", e),
+            }
+        });
+        dialog.show();
+    }
+
+    fn refresh(&self) -> Result<(), Error> {
+        let mutex = crate::get_tor_controller();
+        let mut ctrl = mutex.lock().unwrap();
+        let guards = ctrl.get_guards(Some(&self.gi))?;
+        drop(ctrl);
+
+        self.guards.replace(GuardSet::new(guards));
+        self.refresh_view();
+        Ok(())
+    }
+
+    pub(crate) fn handle_event(&self, event: &Event) {
+        let changed = self.guards.borrow_mut().handle_event(event, |fingerprint| {
+            let mutex = crate::get_tor_controller();
+            let mut ctrl = mutex.lock().unwrap();
+            let or = ctrl.get_onion_router(fingerprint).ok()?;
+            Some(Relay::new(or, Some(&self.gi)))
+        });
+        if changed.is_some() {
+            self.refresh_view();
+        }
+    }
+
+    fn refresh_view(&self) {
+        self.store.clear();
+        for (i, guard) in self.guards.borrow().iter().enumerate() {
+            let (address, country) = match guard.relay {
+                Some(ref relay) => (
+                    relay.or.target.to_string(),
+                    relay
+                        .country()
+                        .map(|c| format!("{} {}", c.flag, c.name))
+                        .unwrap_or_default(),
+                ),
+                None => (String::new(), String::new()),
+            };
+            let since = guard.entry.since.map(|t| t.to_string()).unwrap_or_default();
+            let last_event = guard
+                .last_event
+                .map(|(time, status)| format!("{status} at {time}"))
+                .unwrap_or_default();
+            let values: [(u32, &dyn ToValue); FIELD_COUNT] = [
+                (Columns::Position as u32, &(i as u32 + 1)),
+                (
+                    Columns::Fingerprint as u32,
+                    &guard.fingerprint().to_string(),
+                ),
+                (
+                    Columns::Nickname as u32,
+                    &guard.nickname().unwrap_or_default(),
+                ),
+                (Columns::Address as u32, &address),
+                (Columns::Country as u32, &country),
+                (Columns::Status as u32, &guard.entry.status.to_string()),
+                (Columns::Since as u32, &since),
+                (Columns::LastEvent as u32, &last_event),
+            ];
+            self.store.set(&self.store.append(), &values);
+        }
+    }
+}
+
+impl NotebookTab for GuardTab {
+    fn get_widget(&self) -> Rc<gtk::Widget> {
+        let widget = self.widget.take().unwrap();
+        let copy = Rc::clone(&widget);
+        self.widget.set(Some(widget));
+        copy
+    }
+
+    fn label(&self) -> &'static str {
+        "Guards"
+    }
+}
@@ -0,0 +1,220 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use gtk::prelude::*;
+
+use tor_analyzer_lib::logs::LogForwarder;
+use tor_analyzer_lib::prelude::*;
+
+use crate::notebook::NotebookTab;
+
+/// Messages kept, in the buffer and in the view.
+const CAPACITY: usize = 2000;
+
+/// Severity the event listener subscribes from by default, debug messages are
+/// too many.
+const LOG_SEVERITY: LogSeverity = LogSeverity::Info;
+
+#[repr(i32)]
+enum Columns {
+    Time,
+    Severity,
+    Message,
+    Colour,
+    Level,
+}
+const FIELD_COUNT: usize = Columns::Level as usize + 1;
+const COLUMNS_TYPE: [glib::Type; FIELD_COUNT] = [
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::U32,
+];
+
+fn colour(severity: LogSeverity) -> &'static str {
+    match severity {
+        LogSeverity::Debug => "gray",
+        LogSeverity::Info => "black",
+        LogSeverity::Notice => "blue",
+        LogSeverity::Warn => "darkorange",
+        LogSeverity::Error => "red",
+    }
+}
+
+pub(crate) struct LogTab {
+    buffer: RefCell<LogBuffer>,
+    min_severity: Cell<LogSeverity>,
+
+    /// Severity the event listener subscribes from, shared with its thread
+    subscription: Arc<Mutex<LogSeverity>>,
+    filter: RefCell<String>,
+    widget: Cell<Option<Rc<gtk::Widget>>>,
+    store: gtk::ListStore,
+    treefilter: gtk::TreeModelFilter,
+}
+
+impl LogTab {
+    pub(crate) fn new() -> Rc<Self> {
+        let store = gtk::ListStore::new(&COLUMNS_TYPE);
+        let treefilter = gtk::TreeModelFilter::new(&store, None);
+        let mut buffer = LogBuffer::new(CAPACITY);
+        buffer.add_sink(LogForwarder);
+        let me = Self {
+            buffer: RefCell::new(buffer),
+            min_severity: Cell::new(LogSeverity::Notice),
+            subscription: Arc::new(Mutex::new(LOG_SEVERITY)),
+            filter: RefCell::new(String::new()),
+            widget: Cell::new(None),
+            store,
+            treefilter,
+        };
+        me.create()
+    }
+
+    fn create(self) -> Rc<Self> {
+        let me = Rc::new(self);
+        Rc::clone(&me).create_ui();
+        me
+    }
+
+    fn create_ui(self: Rc<Self>) {
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 8);
+        vbox.set_homogeneous(false);
+
+        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        hbox.add(&gtk::Label::new(Some("Receive")));
+        let subscription = gtk::ComboBoxText::new();
+        for s in LogSeverity::ALL {
+            subscription.append(Some(s.as_str()), s.as_str());
+        }
+        subscription.set_active_id(Some(LOG_SEVERITY.as_str()));
+        let me = Rc::clone(&self);
+        subscription.connect_changed(move |combobox| {
+            if let Some(s) = combobox.active_id().and_then(|id| id.parse().ok()) {
+                *me.subscription.lock().unwrap() = s;
+            }
+        });
+        hbox.add(&subscription);
+
+        hbox.add(&gtk::Label::new(Some("Show")));
+        let severity = gtk::ComboBoxText::new();
+        for s in LogSeverity::ALL {
+            severity.append(Some(s.as_str()), s.as_str());
+        }
+        severity.set_active_id(Some(self.min_severity.get().as_str()));
+        let me = Rc::clone(&self);
+        severity.connect_changed(move |combobox| {
+            if let Some(s) = combobox.active_id().and_then(|id| id.parse().ok()) {
+                me.min_severity.set(s);
+                me.treefilter.refilter();
+            }
+        });
+        hbox.add(&severity);
+
+        let search_entry = gtk::Entry::new();
+        search_entry.set_placeholder_text(Some("Filter messages"));
+        search_entry.set_hexpand(true);
+        let me = Rc::clone(&self);
+        search_entry.connect_changed(move |entry| {
+            me.filter.replace(entry.text().to_lowercase());
+            me.treefilter.refilter();
+        });
+        hbox.add(&search_entry);
+
+        let clear_btn = gtk::Button::with_label("Clear");
+        let me = Rc::clone(&self);
+        clear_btn.connect_clicked(move |_| {
+            me.buffer.borrow_mut().clear();
+            me.store.clear();
+        });
+        hbox.add(&clear_btn);
+        vbox.add(&hbox);
+
+        let sw = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        sw.set_shadow_type(gtk::ShadowType::EtchedIn);
+        sw.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        vbox.add(&sw);
+
+        let me = Rc::clone(&self);
+        self.treefilter
+            .set_visible_func(move |model, iter| -> bool {
+                let level = model
+                    .value(iter, Columns::Level as i32)
+                    .get::<u32>()
+                    .unwrap_or_default();
+                if level < me.min_severity.get() as u32 {
+                    return false;
+                }
+                let filter = me.filter.borrow();
+                filter.is_empty()
+                    || model
+                        .value(iter, Columns::Message as i32)
+                        .get::<String>()
+                        .map(|message| message.to_lowercase().contains(filter.as_str()))
+                        .unwrap_or(false)
+            });
+        let treeview = gtk::TreeView::with_model(&self.treefilter);
+        treeview.set_vexpand(true);
+        sw.add(&treeview);
+
+        for column in [
+            add_column!(treeview, Columns::Time, "Time"),
+            add_column!(treeview, Columns::Severity, "Severity"),
+            add_column!(treeview, Columns::Message, "Message"),
+        ] {
+            for renderer in column.cells() {
+                TreeViewColumnExt::add_attribute(
+                    &column,
+                    &renderer,
+                    "foreground",
+                    Columns::Colour as i32,
+                );
+            }
+        }
+
+        let widget = Some(Rc::new(vbox.upcast()));
+        self.widget.set(widget);
+    }
+
+    /// Severity to subscribe from, changed with the selector.
+    pub(crate) fn subscription(&self) -> Arc<Mutex<LogSeverity>> {
+        Arc::clone(&self.subscription)
+    }
+
+    pub(crate) fn handle_event(&self, event: &Event) {
+        let mut buffer = self.buffer.borrow_mut();
+        if !buffer.handle_event(event) {
+            return;
+        }
+        let (time, message) = buffer.iter().last().unwrap();
+        let values: [(u32, &dyn ToValue); FIELD_COUNT] = [
+            (Columns::Time as u32, &time.to_string()),
+            (Columns::Severity as u32, &message.severity.as_str()),
+            (Columns::Message as u32, &message.message),
+            (Columns::Colour as u32, &colour(message.severity)),
+            (Columns::Level as u32, &(message.severity as u32)),
+        ];
+        self.store.set(&self.store.append(), &values);
+
+        if self.store.iter_n_children(None) as usize > CAPACITY {
+            if let Some(first) = self.store.iter_first() {
+                self.store.remove(&first);
+            }
+        }
+    }
+}
+
+impl NotebookTab for LogTab {
+    fn get_widget(&self) -> Rc<gtk::Widget> {
+        let widget = self.widget.take().unwrap();
+        let copy = Rc::clone(&widget);
+        self.widget.set(Some(widget));
+        copy
+    }
+
+    fn label(&self) -> &'static str {
+        "Log"
+    }
+}
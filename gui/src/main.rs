@@ -56,17 +56,27 @@ macro_rules! popup_error {
     }};
 }
 
+mod bandwidth;
+mod bootstrap;
 mod build_circuit;
 mod circuit;
+mod events;
+mod guards;
+mod logs;
 mod nodes;
 mod notebook;
+mod onions;
+mod orconn;
+mod rows;
+mod rules;
 mod stream;
+mod timeout;
 
 use notebook::NotebookTab;
 
 static mut TOR_CONTROLLER: Option<Arc<Mutex<TorController>>> = None;
 
-fn build_ui(application: &gtk::Application) {
+fn build_ui(application: &gtk::Application, control: &str, config: &Rc<ConfigGuard>) {
     let window = gtk::ApplicationWindow::new(application);
 
     window.set_title("Tor Analyzer");
@@ -88,12 +98,19 @@ fn build_ui(application: &gtk::Application) {
 
     let mut notebook = notebook::Notebook::new();
 
-    window.add(&notebook.notebook);
+    let status_bar = bootstrap::StatusBar::new();
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, 4);
+    vbox.add(status_bar.widget());
+    vbox.pack_start(&notebook.notebook, true, true, 0);
+    window.add(&vbox);
 
     // notebook.create_tab("Circuits", circuit::create_tab(), false);
     let circuits = circuit::CircuitTab::new();
     notebook.create_tab(&*circuits);
 
+    let onions = onions::OnionTab::new();
+    notebook.create_tab(&*onions);
+
     let nodes = nodes::NodeTab::new();
     notebook.create_tab(&*nodes);
 
@@ -103,7 +120,42 @@ fn build_ui(application: &gtk::Application) {
     let streams = stream::StreamTab::new();
     notebook.create_tab(&*streams);
 
+    let rules = rules::RuleTab::new(Rc::clone(config));
+    notebook.create_tab(&*rules);
+
+    let bandwidth = bandwidth::BandwidthTab::new();
+    notebook.create_tab(&*bandwidth);
+
+    let build_timeout = timeout::BuildTimeoutTab::new();
+    notebook.create_tab(&*build_timeout);
+
+    let orconns = orconn::OrConnTab::new();
+    notebook.create_tab(&*orconns);
+
+    let guards = guards::GuardTab::new();
+    notebook.create_tab(&*guards);
+
+    let log = logs::LogTab::new();
+    notebook.create_tab(&*log);
+
     nodes.set_circuit_tab(Rc::clone(&builder));
+    circuits.set_bandwidth_tab(Rc::clone(&bandwidth));
+
+    events::spawn_listener(control.to_owned(), log.subscription()).attach(None, move |event| {
+        bandwidth.handle_event(&event);
+        build_timeout.handle_event(&event);
+        orconns.handle_event(&event);
+        onions.handle_event(&event);
+        guards.handle_event(&event);
+        streams.handle_event(&event);
+        rules.handle_event(&event);
+        status_bar.handle_event(&event);
+        log.handle_event(&event);
+        if let Event::Bandwidth(_) = event {
+            circuits.refresh_throughput();
+        }
+        glib::Continue(true)
+    });
 
     window.show_all();
 }
@@ -133,13 +185,24 @@ fn filter_func(filter: String, model: &gtk::TreeModel, iter: &gtk::TreeIter) ->
 
 fn main() -> Result<(), tor_analyzer_lib::error::Error> {
     env_logger::init();
+    // For a tor started for the application only, tor then exits with it
+    let take_ownership = std::env::args().any(|arg| arg == "--take-ownership");
     let first_arg = std::env::args()
-        .nth(1)
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
         .unwrap_or_else(|| "127.0.0.1:9051".into());
 
-    let mut ctrl = TorController::new(first_arg)?;
-    ctrl.set_conf("__LeaveStreamsUnattached", Some(1))
-        .expect("Cannot change config");
+    // Restored at the end of main, or if the GUI dies. The stream rules set
+    // __LeaveStreamsUnattached while they are enabled.
+    let config = Rc::new(ConfigGuard::new(&first_arg)?);
+    if take_ownership {
+        config.take_ownership()?;
+    }
+    #[cfg(unix)]
+    config.restore_on_signals()?;
+    config.restore_on_panic();
+
+    let ctrl = TorController::new(&first_arg)?;
     unsafe {
         TOR_CONTROLLER = Some(Arc::new(Mutex::new(ctrl)));
     }
@@ -148,13 +211,13 @@ fn main() -> Result<(), tor_analyzer_lib::error::Error> {
         Some("local.dev.tor-analyzer-gui"),
         gio::ApplicationFlags::FLAGS_NONE,
     );
-    application.connect_activate(build_ui);
+    let rules_config = Rc::clone(&config);
+    application
+        .connect_activate(move |application| build_ui(application, &first_arg, &rules_config));
 
     // popup_error!("hello world");
     application.run_with_args(&[""][..]);
-    let _ = get_tor_controller()
-        .lock()
-        .unwrap()
-        .set_conf("__LeaveStreamsUnattached", Some(0));
+    // The rule tab may outlive main, its guard is not dropped
+    config.restore()?;
     Ok(())
 }
@@ -1,10 +1,9 @@
-use std::cell::Cell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
 use std::rc::Rc;
 
 use gtk::prelude::*;
 
-use tor_analyzer_lib::country;
 use tor_analyzer_lib::error::Error;
 use tor_analyzer_lib::prelude::*;
 use tor_analyzer_lib::tor::ns::OnionRouterFlag;
@@ -34,34 +33,9 @@ pub(crate) const COLUMNS_TYPE: [glib::Type; FIELD_COUNT] = [
     glib::Type::BOOL,
 ];
 
-pub(crate) struct Node {
-    country: Option<&'static country::Country>,
-    or: OnionRouter,
-}
-
-impl Node {
-    pub(crate) fn target(&self) -> &Target {
-        &self.or.target
-    }
-    pub(crate) fn hex_identity(&self) -> String {
-        hex_encode(self.or.identity)
-    }
-    pub(crate) fn nickname(&self) -> &String {
-        &self.or.nickname
-    }
-    pub(crate) fn is_guard(&self) -> bool {
-        self.or.flags.is_set(OnionRouterFlag::Guard)
-    }
-    pub(crate) fn is_exit(&self) -> bool {
-        self.or.flags.is_set(OnionRouterFlag::Exit)
-    }
-    pub(crate) fn is_badexit(&self) -> bool {
-        self.or.flags.is_set(OnionRouterFlag::BadExit)
-    }
-}
-
 pub(super) struct NodeTab {
-    nodes: Cell<Option<HashMap<String, Node>>>,
+    relays: Cell<Option<RelayIndex>>,
+    visible: RefCell<Option<HashSet<RelayFingerprint>>>,
     circuit_tab: Cell<Option<Rc<CircuitTab>>>,
     widget: Cell<Option<Rc<gtk::Widget>>>,
     store: gtk::ListStore,
@@ -70,7 +44,8 @@ pub(super) struct NodeTab {
 impl NodeTab {
     pub(crate) fn new() -> Rc<Self> {
         let me = Self {
-            nodes: Cell::new(None),
+            relays: Cell::new(None),
+            visible: RefCell::new(None),
             circuit_tab: Cell::new(None),
             widget: Cell::new(None),
             store: gtk::ListStore::new(&COLUMNS_TYPE),
@@ -102,50 +77,54 @@ impl NodeTab {
         me
     }
 
-    fn get_nodes(&self) -> HashMap<String, Node> {
-        match self.nodes.take() {
-            Some(hm) => hm,
-            None => HashMap::with_capacity(4096),
-        }
+    fn get_relays(&self) -> RelayIndex {
+        self.relays.take().unwrap_or_default()
     }
 
-    fn set_nodes(&self, hm: HashMap<String, Node>) {
-        self.nodes.set(Some(hm));
+    fn set_relays(&self, relays: RelayIndex) {
+        self.relays.set(Some(relays));
     }
 
     fn refresh_data(&self) -> Result<(), Error> {
         #[cfg(debug_assertions)]
         log::warn!("Updating nodes (could take a while, your are in debug mode)");
-        let mut nodes = self.get_nodes();
-        nodes.clear();
         let mutex = crate::get_tor_controller();
         let mut ctrl = mutex.lock().unwrap();
-        let mut ors = ctrl.get_all_onion_router()?;
+        let ors = ctrl.get_all_onion_router()?;
         drop(ctrl);
 
         let gi = GeoIP::new();
-        for or in ors.drain(..) {
-            let country = match or.target.addr {
-                HostOrAddr::Addr(ref addr) => gi.lookup_ip(*addr).and_then(country::get_country),
-                _ => None,
-            };
-
-            let node = Node { country, or };
-            nodes.insert(node.hex_identity(), node);
-        }
-
-        self.set_nodes(nodes);
+        self.set_relays(RelayIndex::new(ors, Some(&gi)));
 
         Ok(())
     }
 
+    /// Computes the relays matching the query typed in the search entry.
+    fn update_filter(&self, entry: &gtk::Entry) {
+        let text = entry.text();
+        let (visible, error) = match text.as_str().parse::<Query>() {
+            Ok(query) if query.0.is_empty() => (None, None),
+            Ok(query) => {
+                let relays = self.get_relays();
+                let visible = relays.query(&query).iter().map(|r| *r.identity()).collect();
+                self.set_relays(relays);
+                (Some(visible), None)
+            }
+            Err(e) => (Some(HashSet::new()), Some(e.to_string())),
+        };
+        let icon = error.as_ref().map(|_| "dialog-warning");
+        entry.set_icon_from_icon_name(gtk::EntryIconPosition::Secondary, icon);
+        entry.set_icon_tooltip_text(gtk::EntryIconPosition::Secondary, error.as_deref());
+        self.visible.replace(visible);
+    }
+
     fn refresh_view(&self) {
         self.store.clear();
 
-        let nodes = self.get_nodes();
-        for d in nodes.values() {
-            let endpoint = format!("{}", d.target());
-            let country = if let Some(c) = d.country {
+        let relays = self.get_relays();
+        for r in relays.iter() {
+            let endpoint = format!("{}", r.or.target);
+            let country = if let Some(c) = r.country() {
                 format!("{} {}", c.flag, c.name)
             } else {
                 String::new()
@@ -153,15 +132,15 @@ impl NodeTab {
             let values: [(u32, &dyn ToValue); FIELD_COUNT] = [
                 (0, &endpoint),
                 (1, &country),
-                (2, &d.hex_identity()),
-                (3, &d.nickname()),
-                (4, &d.is_guard()),
-                (5, &d.is_exit()),
-                (6, &d.is_badexit()),
+                (2, &r.identity().to_string()),
+                (3, &r.or.nickname),
+                (4, &r.or.flags.is_set(OnionRouterFlag::Guard)),
+                (5, &r.or.flags.is_set(OnionRouterFlag::Exit)),
+                (6, &r.or.flags.is_set(OnionRouterFlag::BadExit)),
             ];
             self.store.set(&self.store.append(), &values);
         }
-        self.set_nodes(nodes);
+        self.set_relays(relays);
     }
 
     fn create_ui(self: Rc<Self>) {
@@ -175,7 +154,9 @@ impl NodeTab {
         vbox.set_homogeneous(false);
 
         let search_entry = Rc::new(gtk::Entry::new());
-        search_entry.set_placeholder_text(Some("Enter text here to filter results"));
+        search_entry.set_placeholder_text(Some(
+            "Filter results, e.g. flag:Guard&Fast !flag:BadExit country:de bw>5000 port:443",
+        ));
         vbox.add(&*Rc::clone(&search_entry));
 
         let sw = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
@@ -184,11 +165,18 @@ impl NodeTab {
         vbox.add(&sw);
 
         let treefilter = Rc::new(gtk::TreeModelFilter::new(&self.store, None));
-        let search_entry_copy = Rc::clone(&search_entry);
+        let me = Rc::clone(&self);
         treefilter.set_visible_func(move |model, iter| -> bool {
-            let filter = search_entry_copy.text().as_str().to_lowercase();
-
-            crate::filter_func(filter, model, iter)
+            match *me.visible.borrow() {
+                Some(ref visible) => model
+                    .value(iter, Columns::Identity as i32)
+                    .get::<String>()
+                    .ok()
+                    .and_then(|identity| identity.parse::<RelayFingerprint>().ok())
+                    .map(|identity| visible.contains(&identity))
+                    .unwrap_or(false),
+                None => true,
+            }
         });
         let treeview = gtk::TreeView::with_model(&*Rc::clone(&treefilter));
         treeview.set_vexpand(true);
@@ -205,7 +193,11 @@ impl NodeTab {
             circuit_tab.add_row(&row[..]);
         });
         sw.add(&treeview);
-        search_entry.connect_changed(move |_| treefilter.refilter());
+        let me = Rc::clone(&self);
+        search_entry.connect_changed(move |entry| {
+            me.update_filter(entry);
+            treefilter.refilter();
+        });
 
         add_column!(treeview, Columns::EndPoint, "End point");
         add_column!(treeview, Columns::Country, "Country");
@@ -218,7 +210,10 @@ impl NodeTab {
         let update_btn = gtk::Button::with_label("Update");
         let me = Rc::clone(&self);
         update_btn.connect_clicked(move |_| match me.refresh_data() {
-            Ok(_) => me.refresh_view(),
+            Ok(_) => {
+                me.update_filter(&search_entry);
+                me.refresh_view();
+            }
             Err(e) => log::warn!("Could not refresh data: {}", e),
         });
         vbox.add(&update_btn);
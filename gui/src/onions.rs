@@ -0,0 +1,509 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::time::Instant;
+
+use gtk::prelude::*;
+
+use tor_analyzer_lib::error::Error;
+use tor_analyzer_lib::prelude::*;
+use tor_analyzer_lib::tor::circuit::{CircuitPurpose, CircuitStatus, HsState};
+
+use crate::notebook::NotebookTab;
+use crate::rows::RowIndex;
+
+#[repr(i32)]
+enum Columns {
+    Name,
+    Purpose,
+    Status,
+    HsState,
+    Progress,
+    Phase,
+    Path,
+}
+const FIELD_COUNT: usize = Columns::Path as usize + 1;
+const COLUMNS_TYPE: [glib::Type; FIELD_COUNT] = [
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::I32,
+    glib::Type::STRING,
+    glib::Type::STRING,
+];
+
+#[repr(i32)]
+enum TimelineColumns {
+    Time,
+    Kind,
+    Relay,
+    Event,
+}
+const TIMELINE_FIELD_COUNT: usize = TimelineColumns::Event as usize + 1;
+const TIMELINE_COLUMNS_TYPE: [glib::Type; TIMELINE_FIELD_COUNT] = [glib::Type::STRING; 4];
+
+/// Group of the circuits tied to no service, HSDir ones seen before the
+/// descriptor request.
+const UNKNOWN_SERVICE: &str = "Unknown service";
+
+fn is_onion_circuit(purpose: Option<CircuitPurpose>) -> bool {
+    matches!(
+        purpose,
+        Some(
+            CircuitPurpose::HsClientHsDir
+                | CircuitPurpose::HsClientIntro
+                | CircuitPurpose::HsClientRend
+                | CircuitPurpose::HsServiceIntro
+                | CircuitPurpose::HsServiceRend
+        )
+    )
+}
+
+/// How far a connection to (or from) a service went, in percents.
+fn progress(state: HsState) -> (i32, &'static str) {
+    match state {
+        HsState::HSCRConnecting => (20, "Establishing rendezvous point"),
+        HsState::HSCIConnecting => (30, "Connecting to introduction point"),
+        HsState::HSCREstablishedIdle => (40, "Rendezvous point established"),
+        HsState::HSCIIntroSent => (60, "Introduction sent"),
+        HsState::HSCIDone | HsState::HSCREstablishedWaiting => (80, "Waiting for the service"),
+        HsState::HSCRJoined => (100, "Connected"),
+        HsState::HSSIConnecting => (30, "Connecting to introduction points"),
+        HsState::HSSIEstablished => (50, "Introduction points established"),
+        HsState::HSSRConnecting => (75, "Connecting to client rendezvous point"),
+        HsState::HSSRJoined => (100, "Client connected"),
+    }
+}
+
+/// Progress of a circuit, fetching the descriptor being the first step.
+fn circuit_progress(circuit: &Circuit) -> (i32, &'static str) {
+    match circuit.hs_state {
+        Some(state) => progress(state),
+        None if circuit.purpose == Some(CircuitPurpose::HsClientHsDir) => {
+            (10, "Fetching descriptor")
+        }
+        None => (0, ""),
+    }
+}
+
+fn is_closed(circuit: &Circuit) -> bool {
+    matches!(
+        circuit.status,
+        CircuitStatus::Closed | CircuitStatus::Failed
+    )
+}
+
+/// Progress of a service, from its furthest open circuit.
+fn group_progress(circuits: &[&Circuit]) -> (i32, &'static str) {
+    let progress = circuits
+        .iter()
+        .filter(|c| !is_closed(c))
+        .map(|c| circuit_progress(c))
+        .max_by_key(|(progress, _)| *progress);
+    match progress {
+        Some(progress) => progress,
+        None if circuits.iter().any(|c| c.status == CircuitStatus::Failed) => (0, "Failed"),
+        None => (0, "Closed"),
+    }
+}
+
+fn count(circuits: &[&Circuit], purposes: &[CircuitPurpose]) -> usize {
+    circuits
+        .iter()
+        .filter(|c| c.purpose.is_some_and(|p| purposes.contains(&p)))
+        .count()
+}
+
+/// Onion service circuits, grouped by the service they reach.
+pub(crate) struct OnionTab {
+    circuits: RefCell<HashMap<CircuitID, Circuit>>,
+
+    /// Services of the circuits, kept after they close
+    services: RefCell<HashMap<CircuitID, HsAddress>>,
+    timeline: RefCell<HsTimeline>,
+
+    /// Service whose timeline is shown
+    selected: RefCell<Option<HsAddress>>,
+
+    /// Service rows, by name
+    groups: RefCell<RowIndex<String>>,
+    rows: RefCell<RowIndex<CircuitID>>,
+    widget: Cell<Option<Rc<gtk::Widget>>>,
+    store: gtk::TreeStore,
+    treeview: gtk::TreeView,
+    timeline_store: gtk::ListStore,
+}
+
+impl OnionTab {
+    pub(crate) fn new() -> Rc<Self> {
+        let store = gtk::TreeStore::new(&COLUMNS_TYPE);
+        let treeview = gtk::TreeView::with_model(&store);
+        let me = Self {
+            circuits: RefCell::new(HashMap::new()),
+            services: RefCell::new(HashMap::new()),
+            timeline: RefCell::new(HsTimeline::default()),
+            selected: RefCell::new(None),
+            groups: RefCell::new(RowIndex::new()),
+            rows: RefCell::new(RowIndex::new()),
+            widget: Cell::new(None),
+            store,
+            treeview,
+            timeline_store: gtk::ListStore::new(&TIMELINE_COLUMNS_TYPE),
+        };
+        me.create()
+    }
+
+    fn create(self) -> Rc<Self> {
+        let me = Rc::new(self);
+        Rc::clone(&me).create_ui();
+        me
+    }
+
+    fn create_ui(self: Rc<Self>) {
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 8);
+        vbox.set_homogeneous(false);
+
+        let sw = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        sw.set_shadow_type(gtk::ShadowType::EtchedIn);
+        sw.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        vbox.add(&sw);
+
+        let treeview = &self.treeview;
+        treeview.set_vexpand(true);
+        sw.add(treeview);
+
+        add_column!(treeview, Columns::Name, "Service / circuit");
+        add_column!(treeview, Columns::Purpose, "Purpose");
+        add_column!(treeview, Columns::Status, "Status");
+        add_column!(treeview, Columns::HsState, "HS state");
+
+        let renderer = gtk::CellRendererProgress::new();
+        let column = gtk::TreeViewColumn::new();
+        TreeViewColumnExt::pack_start(&column, &renderer, true);
+        column.set_title("Progress");
+        TreeViewColumnExt::add_attribute(&column, &renderer, "value", Columns::Progress as i32);
+        TreeViewColumnExt::add_attribute(&column, &renderer, "text", Columns::Phase as i32);
+        column.set_min_width(220);
+        treeview.append_column(&column);
+
+        add_column!(treeview, Columns::Path, "Path");
+
+        let me = Rc::clone(&self);
+        treeview.selection().connect_changed(move |selection| {
+            if let Some((model, iter)) = selection.selected() {
+                let service = model.iter_parent(&iter).unwrap_or(iter);
+                if let Ok(name) = model.value(&service, Columns::Name as i32).get::<String>() {
+                    me.select_service(&name);
+                }
+            }
+        });
+
+        let sw = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        sw.set_shadow_type(gtk::ShadowType::EtchedIn);
+        sw.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        sw.set_min_content_height(200);
+        vbox.add(&sw);
+
+        let timeline = gtk::TreeView::with_model(&self.timeline_store);
+        sw.add(&timeline);
+        add_column!(timeline, TimelineColumns::Time, "Time");
+        add_column!(timeline, TimelineColumns::Kind, "Kind");
+        add_column!(timeline, TimelineColumns::Relay, "Relay");
+        add_column!(timeline, TimelineColumns::Event, "Event");
+
+        let update_btn = gtk::Button::with_label("Update onion circuits");
+        let me = Rc::clone(&self);
+        update_btn.connect_clicked(move |_| match me.refresh_data() {
+            Ok(_) => me.refresh_view(),
+            Err(e) => log::warn!("Could not refresh onion circuits: {}", e),
+        });
+        vbox.add(&update_btn);
+        update_btn.clicked();
+
+        let widget = Some(Rc::new(vbox.upcast()));
+        self.widget.set(widget);
+    }
+
+    /// Replaces the circuits with the open ones, closed circuits are dropped.
+    fn refresh_data(&self) -> Result<(), Error> {
+        let mutex = crate::get_tor_controller();
+        let mut ctrl = mutex.lock().unwrap();
+        let circuits = ctrl.get_circuits()?;
+        drop(ctrl);
+
+        let mut services = self.services.borrow_mut();
+        let circuits: HashMap<_, _> = circuits
+            .into_iter()
+            .filter(|c| is_onion_circuit(c.purpose))
+            .map(|c| {
+                if let Some(ref address) = c.rend_query {
+                    services.insert(c.id.clone(), address.clone());
+                }
+                (c.id.clone(), c)
+            })
+            .collect();
+        services.retain(|id, _| circuits.contains_key(id));
+        self.circuits.replace(circuits);
+        Ok(())
+    }
+
+    pub(crate) fn handle_event(&self, event: &Event) {
+        let address = self.timeline.borrow_mut().handle_event(event).cloned();
+        if address.is_some() && address == *self.selected.borrow() {
+            self.refresh_timeline();
+        }
+        match event {
+            Event::Circuit(circuit) if is_onion_circuit(circuit.purpose) => {
+                if let Some(address) = address {
+                    self.services
+                        .borrow_mut()
+                        .insert(circuit.id.clone(), address);
+                }
+                self.circuits
+                    .borrow_mut()
+                    .insert(circuit.id.clone(), circuit.clone());
+                self.update_circuit(&circuit.id);
+            }
+            Event::Bandwidth(_) => self.expire(),
+            _ => {}
+        }
+    }
+
+    fn group_name(&self, id: &CircuitID) -> String {
+        match self.services.borrow().get(id) {
+            Some(address) => address.hostname(),
+            None => UNKNOWN_SERVICE.into(),
+        }
+    }
+
+    /// Updates the row of a circuit, moving it to the group of its service.
+    fn update_circuit(&self, id: &CircuitID) {
+        let circuits = self.circuits.borrow();
+        let Some(circuit) = circuits.get(id) else {
+            return;
+        };
+        let name = self.group_name(id);
+        let mut moved_from = None;
+
+        let mut rows = self.rows.borrow_mut();
+        let iter = match rows.get(id) {
+            Some(iter) => {
+                let group = self
+                    .store
+                    .iter_parent(&iter)
+                    .and_then(|p| self.store.value(&p, Columns::Name as i32).get().ok());
+                if group.as_ref() == Some(&name) {
+                    Some(iter)
+                } else {
+                    self.store.remove(&iter);
+                    moved_from = group;
+                    None
+                }
+            }
+            None => None,
+        };
+        let iter = match iter {
+            Some(iter) => iter,
+            None => {
+                let mut groups = self.groups.borrow_mut();
+                let parent = match groups.get(&name) {
+                    Some(parent) => parent,
+                    None => {
+                        let parent = self.store.append(None);
+                        groups.insert(name.clone(), &self.store, &parent);
+                        parent
+                    }
+                };
+                let iter = self.store.append(Some(&parent));
+                rows.insert(id.clone(), &self.store, &iter);
+                if let Some(path) = self.store.path(&iter) {
+                    self.treeview.expand_to_path(&path);
+                }
+                iter
+            }
+        };
+        self.set_circuit_row(&iter, circuit);
+        rows.set_done(id, is_closed(circuit));
+        drop(rows);
+        drop(circuits);
+
+        self.update_group(&name);
+        if let Some(group) = moved_from {
+            self.update_group(&group);
+        }
+    }
+
+    /// Drops the circuits closed for a while, and the services left empty.
+    fn expire(&self) {
+        let expired = self.rows.borrow_mut().expire(Instant::now());
+        let mut groups = Vec::new();
+        for (id, iter) in expired {
+            let name = self.group_name(&id);
+            self.store.remove(&iter);
+            self.circuits.borrow_mut().remove(&id);
+            self.services.borrow_mut().remove(&id);
+            if !groups.contains(&name) {
+                groups.push(name);
+            }
+        }
+        for name in groups {
+            self.update_group(&name);
+        }
+    }
+
+    /// Updates the summary of a service, removes it once it has no circuits.
+    fn update_group(&self, name: &str) {
+        let name = name.to_string();
+        let Some(parent) = self.groups.borrow().get(&name) else {
+            return;
+        };
+        let circuits = self.circuits.borrow();
+        let group = circuits
+            .values()
+            .filter(|c| self.group_name(&c.id) == name)
+            .collect::<Vec<_>>();
+        if group.is_empty() {
+            self.groups.borrow_mut().remove(&name);
+            self.store.remove(&parent);
+        } else {
+            self.set_group_row(&parent, &name, &group);
+        }
+    }
+
+    /// Shows the timeline of the service named `name`, if any was recorded.
+    fn select_service(&self, name: &str) {
+        let address = self
+            .timeline
+            .borrow()
+            .services()
+            .find(|s| s.address.hostname() == name)
+            .map(|s| s.address.clone());
+        if address != *self.selected.borrow() {
+            self.selected.replace(address);
+            self.refresh_timeline();
+        }
+    }
+
+    fn refresh_timeline(&self) {
+        self.timeline_store.clear();
+
+        let timeline = self.timeline.borrow();
+        let selected = self.selected.borrow();
+        let Some(service) = selected.as_ref().and_then(|a| timeline.get(a)) else {
+            return;
+        };
+        for event in service.events() {
+            let relay = event
+                .entry
+                .relay()
+                .map(|s| s.to_string())
+                .unwrap_or_default();
+            let values: [(u32, &dyn ToValue); TIMELINE_FIELD_COUNT] = [
+                (TimelineColumns::Time as u32, &event.time.to_string()),
+                (TimelineColumns::Kind as u32, &event.entry.kind()),
+                (TimelineColumns::Relay as u32, &relay),
+                (TimelineColumns::Event as u32, &event.entry.to_string()),
+            ];
+            self.timeline_store
+                .set(&self.timeline_store.append(), &values);
+        }
+    }
+
+    fn refresh_view(&self) {
+        self.store.clear();
+        let mut groups = self.groups.borrow_mut();
+        let mut rows = self.rows.borrow_mut();
+        groups.clear();
+        rows.clear();
+
+        let circuits = self.circuits.borrow();
+        let mut by_name: BTreeMap<String, Vec<&Circuit>> = BTreeMap::new();
+        for circuit in circuits.values() {
+            by_name
+                .entry(self.group_name(&circuit.id))
+                .or_default()
+                .push(circuit);
+        }
+
+        for (name, mut group) in by_name {
+            group.sort_by_key(|c| c.id.0.parse::<u64>().unwrap_or_default());
+            let parent = self.store.append(None);
+            self.set_group_row(&parent, &name, &group);
+            groups.insert(name, &self.store, &parent);
+
+            for circuit in group {
+                let iter = self.store.append(Some(&parent));
+                self.set_circuit_row(&iter, circuit);
+                rows.insert(circuit.id.clone(), &self.store, &iter);
+                rows.set_done(&circuit.id, is_closed(circuit));
+            }
+        }
+        self.treeview.expand_all();
+    }
+
+    fn set_group_row(&self, parent: &gtk::TreeIter, name: &str, group: &[&Circuit]) {
+        let (progress, phase) = group_progress(group);
+        let summary = format!(
+            "{} HSDir, {} intro, {} rend",
+            count(group, &[CircuitPurpose::HsClientHsDir]),
+            count(
+                group,
+                &[
+                    CircuitPurpose::HsClientIntro,
+                    CircuitPurpose::HsServiceIntro
+                ]
+            ),
+            count(
+                group,
+                &[CircuitPurpose::HsClientRend, CircuitPurpose::HsServiceRend]
+            ),
+        );
+        let values: [(u32, &dyn ToValue); 4] = [
+            (Columns::Name as u32, &name),
+            (Columns::Purpose as u32, &summary),
+            (Columns::Progress as u32, &progress),
+            (Columns::Phase as u32, &phase),
+        ];
+        self.store.set(parent, &values);
+    }
+
+    fn set_circuit_row(&self, iter: &gtk::TreeIter, circuit: &Circuit) {
+        let (progress, phase) = circuit_progress(circuit);
+        let path = circuit
+            .path
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let values: [(u32, &dyn ToValue); FIELD_COUNT] = [
+            (Columns::Name as u32, &circuit.id.to_string()),
+            (
+                Columns::Purpose as u32,
+                &circuit.purpose.map(|p| p.to_string()).unwrap_or_default(),
+            ),
+            (Columns::Status as u32, &circuit.status.to_string()),
+            (
+                Columns::HsState as u32,
+                &circuit.hs_state.map(|s| s.to_string()).unwrap_or_default(),
+            ),
+            (Columns::Progress as u32, &progress),
+            (Columns::Phase as u32, &phase),
+            (Columns::Path as u32, &path),
+        ];
+        self.store.set(iter, &values);
+    }
+}
+
+impl NotebookTab for OnionTab {
+    fn get_widget(&self) -> Rc<gtk::Widget> {
+        let widget = self.widget.take().unwrap();
+        let copy = Rc::clone(&widget);
+        self.widget.set(Some(widget));
+        copy
+    }
+
+    fn label(&self) -> &'static str {
+        "Onion circuits"
+    }
+}
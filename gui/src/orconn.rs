@@ -0,0 +1,301 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::rc::Rc;
+use std::time::Instant;
+
+use gtk::prelude::*;
+
+use tor_analyzer_lib::country;
+use tor_analyzer_lib::error::Error;
+use tor_analyzer_lib::prelude::*;
+use tor_analyzer_lib::tor::guard::GuardEventStatus;
+use tor_analyzer_lib::tor::orconn::{OrConnStatus, OrConnTarget};
+
+use crate::notebook::NotebookTab;
+use crate::rows::RowIndex;
+
+#[repr(i32)]
+enum Columns {
+    Id,
+    Target,
+    Nickname,
+    Address,
+    Country,
+    Guard,
+    Status,
+    Reason,
+    Circuits,
+    Updated,
+
+    /// Relay of the connection, not shown
+    Fingerprint,
+}
+const FIELD_COUNT: usize = Columns::Fingerprint as usize + 1;
+const COLUMNS_TYPE: [glib::Type; FIELD_COUNT] = [
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::BOOL,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+];
+
+/// What the consensus says about the relay at the end of a connection.
+#[derive(Default)]
+struct RelayInfo {
+    nickname: String,
+    address: Option<Target>,
+}
+
+pub(crate) struct OrConnTab {
+    gi: GeoIP,
+    relays: RefCell<HashMap<RelayFingerprint, RelayInfo>>,
+
+    /// Our entry guards, from `entry-guards` and `GUARD` events
+    guards: RefCell<HashSet<RelayFingerprint>>,
+
+    /// Rows by connection ID, or by target for the ones from `orconn-status`
+    rows: RefCell<RowIndex<String>>,
+    widget: Cell<Option<Rc<gtk::Widget>>>,
+    store: gtk::ListStore,
+}
+
+impl OrConnTab {
+    pub(crate) fn new() -> Rc<Self> {
+        let me = Self {
+            gi: GeoIP::new(),
+            relays: RefCell::new(HashMap::new()),
+            guards: RefCell::new(HashSet::new()),
+            rows: RefCell::new(RowIndex::new()),
+            widget: Cell::new(None),
+            store: gtk::ListStore::new(&COLUMNS_TYPE),
+        };
+        me.create()
+    }
+
+    fn create(self) -> Rc<Self> {
+        let me = Rc::new(self);
+        Rc::clone(&me).create_ui();
+        me
+    }
+
+    fn create_ui(self: Rc<Self>) {
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 8);
+        vbox.set_homogeneous(false);
+
+        let sw = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        sw.set_shadow_type(gtk::ShadowType::EtchedIn);
+        sw.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        vbox.add(&sw);
+
+        let treeview = gtk::TreeView::with_model(&self.store);
+        treeview.set_vexpand(true);
+        sw.add(&treeview);
+
+        add_column!(treeview, Columns::Id, "ID");
+        add_column!(treeview, Columns::Nickname, "Nickname");
+        add_column!(treeview, Columns::Target, "Relay");
+        add_column!(treeview, Columns::Address, "Address");
+        add_column!(treeview, Columns::Country, "Country");
+        add_column!(bool treeview, Columns::Guard, "Entry guard");
+        add_column!(treeview, Columns::Status, "Status");
+        add_column!(treeview, Columns::Reason, "Reason");
+        add_column!(treeview, Columns::Circuits, "Circuits");
+        add_column!(treeview, Columns::Updated, "Updated");
+
+        let update_btn = gtk::Button::with_label("Update OR connections");
+        let me = Rc::clone(&self);
+        update_btn.connect_clicked(move |_| {
+            if let Err(e) = me.refresh() {
+                log::warn!("Could not refresh OR connections: {}", e);
+            }
+        });
+        vbox.add(&update_btn);
+        update_btn.clicked();
+
+        let widget = Some(Rc::new(vbox.upcast()));
+        self.widget.set(widget);
+    }
+
+    /// Replaces the rows with the connections tor currently has.
+    fn refresh(&self) -> Result<(), Error> {
+        let mutex = crate::get_tor_controller();
+        let mut ctrl = mutex.lock().unwrap();
+        let orconns = ctrl.get_or_connections()?;
+        let guards = ctrl.get_entry_guards()?;
+        drop(ctrl);
+
+        self.guards
+            .replace(guards.iter().map(|g| g.relay.fingerprint).collect());
+        self.store.clear();
+        self.rows.borrow_mut().clear();
+        for orconn in &orconns {
+            self.update(orconn);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_event(&self, event: &Event) {
+        match event {
+            Event::OrConnection(orconn) => self.update(orconn),
+            Event::Guard(guard) => {
+                let fingerprint = guard.relay.fingerprint;
+                let is_guard = guard.status != GuardEventStatus::Dropped;
+                let mut guards = self.guards.borrow_mut();
+                let changed = if is_guard {
+                    guards.insert(fingerprint)
+                } else {
+                    guards.remove(&fingerprint)
+                };
+                if changed {
+                    self.update_guard_column(&fingerprint, is_guard);
+                }
+            }
+            Event::Bandwidth(_) => {
+                for (_, iter) in self.rows.borrow_mut().expire(Instant::now()) {
+                    self.store.remove(&iter);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn update_guard_column(&self, fingerprint: &RelayFingerprint, is_guard: bool) {
+        let fingerprint = fingerprint.to_string();
+        self.store.foreach(|model, _path, iter| {
+            let row_fingerprint = model
+                .value(iter, Columns::Fingerprint as i32)
+                .get::<String>()
+                .unwrap_or_default();
+            if row_fingerprint == fingerprint {
+                self.store
+                    .set_value(iter, Columns::Guard as u32, &is_guard.to_value());
+            }
+            false
+        });
+    }
+
+    /// Finds the row of `orconn`, by its ID or else by its target, adding one
+    /// when missing.
+    fn find_row(&self, orconn: &OrConnection) -> (String, gtk::TreeIter) {
+        let mut rows = self.rows.borrow_mut();
+        let target = orconn.target.to_string();
+        let key = match orconn.id {
+            Some(ref id) => {
+                let id = id.to_string();
+                // Connections listed by `orconn-status` have no ID
+                if rows.get(&id).is_none() && rows.get(&target).is_some() {
+                    rows.rekey(&target, id.clone());
+                }
+                id
+            }
+            None => target,
+        };
+        let iter = match rows.get(&key) {
+            Some(iter) => iter,
+            None => {
+                let iter = self.store.append();
+                rows.insert(key.clone(), &self.store, &iter);
+                iter
+            }
+        };
+        (key, iter)
+    }
+
+    fn update(&self, orconn: &OrConnection) {
+        let (key, iter) = self.find_row(orconn);
+        let done = matches!(orconn.status, OrConnStatus::Closed | OrConnStatus::Failed);
+        self.rows.borrow_mut().set_done(&key, done);
+
+        let (nickname, address, fingerprint) = match orconn.target {
+            OrConnTarget::Relay(ref step) => {
+                let mut relays = self.relays.borrow_mut();
+                let relay = relays
+                    .entry(step.fingerprint)
+                    .or_insert_with(|| lookup_relay(&step.fingerprint));
+                let nickname = step
+                    .nickname
+                    .clone()
+                    .unwrap_or_else(|| relay.nickname.clone());
+                (nickname, relay.address.clone(), Some(step.fingerprint))
+            }
+            OrConnTarget::Address(ref target) => (String::new(), Some(target.clone()), None),
+        };
+        let guard = fingerprint.is_some_and(|f| self.guards.borrow().contains(&f));
+        let country = match address.as_ref() {
+            Some(Target {
+                addr: HostOrAddr::Addr(ip),
+                ..
+            }) => self.country(*ip),
+            _ => String::new(),
+        };
+
+        let id = orconn.id.as_ref().map(|id| id.to_string());
+        let reason = orconn.reason.map(|r| r.to_string()).unwrap_or_default();
+        let circuits = orconn.ncircs.map(|n| n.to_string()).unwrap_or_default();
+        let values: [(u32, &dyn ToValue); FIELD_COUNT - 1] = [
+            (Columns::Target as u32, &orconn.target.to_string()),
+            (Columns::Nickname as u32, &nickname),
+            (
+                Columns::Address as u32,
+                &address.map(|a| a.to_string()).unwrap_or_default(),
+            ),
+            (Columns::Country as u32, &country),
+            (Columns::Guard as u32, &guard),
+            (Columns::Status as u32, &orconn.status.to_string()),
+            (Columns::Reason as u32, &reason),
+            (Columns::Circuits as u32, &circuits),
+            (Columns::Updated as u32, &Time::now().to_string()),
+            (
+                Columns::Fingerprint as u32,
+                &fingerprint.map(|f| f.to_string()).unwrap_or_default(),
+            ),
+        ];
+        self.store.set(&iter, &values);
+        if let Some(id) = id {
+            self.store
+                .set_value(&iter, Columns::Id as u32, &id.to_value());
+        }
+    }
+
+    fn country(&self, ip: IpAddr) -> String {
+        match self.gi.lookup_ip(ip).and_then(country::get_country) {
+            Some(c) => format!("{} {}", c.flag, c.name),
+            None => String::new(),
+        }
+    }
+}
+
+fn lookup_relay(fingerprint: &RelayFingerprint) -> RelayInfo {
+    let mutex = crate::get_tor_controller();
+    let mut ctrl = mutex.lock().unwrap();
+    match ctrl.get_onion_router(fingerprint) {
+        Ok(or) => RelayInfo {
+            nickname: or.nickname,
+            address: Some(or.target),
+        },
+        Err(e) => {
+            log::debug!("Relay {} not in the consensus: {}", fingerprint, e);
+            RelayInfo::default()
+        }
+    }
+}
+
+impl NotebookTab for OrConnTab {
+    fn get_widget(&self) -> Rc<gtk::Widget> {
+        let widget = self.widget.take().unwrap();
+        let copy = Rc::clone(&widget);
+        self.widget.set(Some(widget));
+        copy
+    }
+
+    fn label(&self) -> &'static str {
+        "OR connections"
+    }
+}
@@ -0,0 +1,91 @@
+//! Rows of a tree model found by key, removed a while after they are done.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use gtk::prelude::*;
+
+/// How long closed or failed rows stay visible.
+pub(crate) const LINGER: Duration = Duration::from_secs(60);
+
+pub(crate) struct RowIndex<K> {
+    rows: HashMap<K, gtk::TreeRowReference>,
+
+    /// When the rows were done, they are removed `LINGER` later
+    done: HashMap<K, Instant>,
+}
+
+impl<K: Eq + Hash + Clone> RowIndex<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            rows: HashMap::new(),
+            done: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<gtk::TreeIter> {
+        let row = self.rows.get(key)?;
+        row.model().iter(&row.path()?)
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        key: K,
+        model: &impl IsA<gtk::TreeModel>,
+        iter: &gtk::TreeIter,
+    ) {
+        let row = model
+            .path(iter)
+            .and_then(|path| gtk::TreeRowReference::new(model, &path));
+        if let Some(row) = row {
+            self.rows.insert(key, row);
+        }
+    }
+
+    /// Finds the row of `key` under a new key.
+    pub(crate) fn rekey(&mut self, old: &K, new: K) {
+        if let Some(row) = self.rows.remove(old) {
+            self.rows.insert(new.clone(), row);
+        }
+        if let Some(since) = self.done.remove(old) {
+            self.done.insert(new, since);
+        }
+    }
+
+    /// Marks the row as done, to be removed later, or as active again.
+    pub(crate) fn set_done(&mut self, key: &K, done: bool) {
+        if done {
+            self.done.entry(key.clone()).or_insert_with(Instant::now);
+        } else {
+            self.done.remove(key);
+        }
+    }
+
+    /// Forgets a row, returning it to be removed from the model.
+    pub(crate) fn remove(&mut self, key: &K) -> Option<gtk::TreeIter> {
+        self.done.remove(key);
+        let row = self.rows.remove(key)?;
+        row.model().iter(&row.path()?)
+    }
+
+    /// Forgets the rows done for longer than [`LINGER`], returning their keys
+    /// and rows to be removed from the model.
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<(K, gtk::TreeIter)> {
+        let expired: Vec<K> = self
+            .done
+            .iter()
+            .filter(|(_, since)| now.duration_since(**since) >= LINGER)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|key| self.remove(&key).map(|iter| (key, iter)))
+            .collect()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.rows.clear();
+        self.done.clear();
+    }
+}
@@ -0,0 +1,416 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use gtk::prelude::*;
+
+use tor_analyzer_lib::attach::{self, AttachCommand};
+use tor_analyzer_lib::error::Error;
+use tor_analyzer_lib::prelude::*;
+use tor_analyzer_lib::tor::guard::GuardStatus;
+
+use crate::notebook::NotebookTab;
+
+/// The consensus is reloaded after this delay to build circuits.
+const INDEX_LIFETIME: Duration = Duration::from_secs(3600);
+
+#[repr(i32)]
+enum Columns {
+    Position,
+    Rule,
+}
+const FIELD_COUNT: usize = Columns::Rule as usize + 1;
+const COLUMNS_TYPE: [glib::Type; FIELD_COUNT] = [glib::Type::U32, glib::Type::STRING];
+
+pub(crate) struct RuleTab {
+    gi: GeoIP,
+
+    /// Sets `__LeaveStreamsUnattached` while the rules are enabled
+    config: Rc<ConfigGuard>,
+    attacher: RefCell<StreamAttacher>,
+    enabled: Cell<bool>,
+
+    /// Relays to pick paths from, loaded on the first circuit to build
+    index: RefCell<Option<(RelayIndex, Instant)>>,
+
+    /// Exit policies of the relays of the index, read when first needed
+    policies: RefCell<HashMap<RelayFingerprint, Option<ExitPolicy>>>,
+    widget: Cell<Option<Rc<gtk::Widget>>>,
+    store: gtk::ListStore,
+    treeview: gtk::TreeView,
+    rule_entry: gtk::Entry,
+    status: gtk::Label,
+}
+
+impl RuleTab {
+    pub(crate) fn new(config: Rc<ConfigGuard>) -> Rc<Self> {
+        let me = Self {
+            gi: GeoIP::new(),
+            config,
+            attacher: RefCell::new(StreamAttacher::default()),
+            enabled: Cell::new(false),
+            index: RefCell::new(None),
+            policies: RefCell::new(HashMap::new()),
+            widget: Cell::new(None),
+            store: gtk::ListStore::new(&COLUMNS_TYPE),
+            treeview: gtk::TreeView::new(),
+            rule_entry: gtk::Entry::new(),
+            status: gtk::Label::new(None),
+        };
+        me.create()
+    }
+
+    fn create(self) -> Rc<Self> {
+        let me = Rc::new(self);
+        Rc::clone(&me).create_ui();
+        me
+    }
+
+    fn create_ui(self: Rc<Self>) {
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 8);
+        vbox.set_homogeneous(false);
+
+        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        let enable_btn = gtk::CheckButton::with_label("Attach new streams following the rules");
+        let me = Rc::clone(&self);
+        enable_btn.connect_toggled(move |btn| {
+            if let Err(e) = me.set_enabled(btn.is_active()) {
+                popup_error!("Could not change __LeaveStreamsUnattached: {}", e);
+                if btn.is_active() {
+                    btn.set_active(false);
+                }
+            }
+        });
+        hbox.add(&enable_btn);
+
+        hbox.pack_end(&self.status, false, false, 0);
+        let timeout = gtk::SpinButton::with_range(1.0, 120.0, 1.0);
+        timeout.set_value(attach::DEFAULT_TIMEOUT.as_secs() as f64);
+        let me = Rc::clone(&self);
+        timeout.connect_value_changed(move |spin| {
+            let timeout = Duration::from_secs(spin.value_as_int() as u64);
+            me.attacher.borrow_mut().set_timeout(timeout);
+        });
+        hbox.pack_end(&timeout, false, false, 0);
+        hbox.pack_end(
+            &gtk::Label::new(Some("Left to tor after (s)")),
+            false,
+            false,
+            0,
+        );
+        vbox.add(&hbox);
+
+        let sw = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        sw.set_shadow_type(gtk::ShadowType::EtchedIn);
+        sw.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        vbox.add(&sw);
+
+        self.treeview.set_model(Some(&self.store));
+        self.treeview.set_vexpand(true);
+        sw.add(&self.treeview);
+
+        add_column!(self.treeview, Columns::Position, "#");
+        add_column!(self.treeview, Columns::Rule, "Rule");
+
+        let me = Rc::clone(&self);
+        self.treeview.selection().connect_changed(move |selection| {
+            if let Some((model, iter)) = selection.selected() {
+                if let Ok(rule) = model.value(&iter, Columns::Rule as i32).get::<String>() {
+                    me.rule_entry.set_text(&rule);
+                }
+            }
+        });
+
+        self.rule_entry.set_placeholder_text(Some(
+            "host:*.example.com port:443 user:alice source:127.0.0.1/32 -> country DE,FR | build <query> | tor",
+        ));
+        vbox.add(&self.rule_entry);
+
+        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        hbox.set_homogeneous(true);
+
+        let add_btn = gtk::Button::with_label("Add");
+        let me = Rc::clone(&self);
+        add_btn.connect_clicked(move |_| me.add_rule(false));
+        hbox.add(&add_btn);
+
+        let replace_btn = gtk::Button::with_label("Replace");
+        let me = Rc::clone(&self);
+        replace_btn.connect_clicked(move |_| me.add_rule(true));
+        hbox.add(&replace_btn);
+
+        let remove_btn = gtk::Button::with_label("Remove");
+        let me = Rc::clone(&self);
+        remove_btn.connect_clicked(move |_| {
+            if let Some(i) = me.selected() {
+                me.edit_rules(|rules| {
+                    rules.remove(i);
+                    None
+                });
+            }
+        });
+        hbox.add(&remove_btn);
+
+        let up_btn = gtk::Button::with_label("Move up");
+        let me = Rc::clone(&self);
+        up_btn.connect_clicked(move |_| {
+            if let Some(i) = me.selected().filter(|i| *i > 0) {
+                me.edit_rules(|rules| {
+                    rules.swap(i - 1, i);
+                    Some(i - 1)
+                });
+            }
+        });
+        hbox.add(&up_btn);
+
+        let down_btn = gtk::Button::with_label("Move down");
+        let me = Rc::clone(&self);
+        down_btn.connect_clicked(move |_| {
+            if let Some(i) = me.selected() {
+                me.edit_rules(|rules| {
+                    if i + 1 < rules.len() {
+                        rules.swap(i, i + 1);
+                        Some(i + 1)
+                    } else {
+                        Some(i)
+                    }
+                });
+            }
+        });
+        hbox.add(&down_btn);
+        vbox.add(&hbox);
+
+        self.update_status();
+
+        let widget = Some(Rc::new(vbox.upcast()));
+        self.widget.set(widget);
+    }
+
+    fn selected(&self) -> Option<usize> {
+        let (model, iter) = self.treeview.selection().selected()?;
+        let position = model
+            .value(&iter, Columns::Position as i32)
+            .get::<u32>()
+            .ok()?;
+        Some(position as usize - 1)
+    }
+
+    /// Adds the rule of the entry after the selected one, or replaces it.
+    fn add_rule(&self, replace: bool) {
+        let rule: AttachRule = match self.rule_entry.text().parse() {
+            Ok(rule) => rule,
+            Err(e) => {
+                popup_error!("Invalid rule {:?}: {}", self.rule_entry.text(), e);
+                return;
+            }
+        };
+        let selected = self.selected();
+        self.edit_rules(|rules| match selected {
+            Some(i) if replace => {
+                rules[i] = rule;
+                Some(i)
+            }
+            Some(i) => {
+                rules.insert(i + 1, rule);
+                Some(i + 1)
+            }
+            None => {
+                rules.push(rule);
+                Some(rules.len() - 1)
+            }
+        });
+    }
+
+    /// Changes the rules, `edit` returning the rule to select.
+    fn edit_rules<F>(&self, edit: F)
+    where
+        F: FnOnce(&mut Vec<AttachRule>) -> Option<usize>,
+    {
+        let mut rules = self.attacher.borrow().rules().to_vec();
+        let selected = edit(&mut rules);
+        self.attacher.borrow_mut().set_rules(rules);
+
+        self.store.clear();
+        for (i, rule) in self.attacher.borrow().rules().iter().enumerate() {
+            let values: [(u32, &dyn ToValue); FIELD_COUNT] = [
+                (Columns::Position as u32, &(i as u32 + 1)),
+                (Columns::Rule as u32, &rule.to_string()),
+            ];
+            let iter = self.store.append();
+            self.store.set(&iter, &values);
+            if selected == Some(i) {
+                self.treeview.selection().select_iter(&iter);
+            }
+        }
+    }
+
+    /// Leaves the new streams to the rules, or to tor again once disabled.
+    fn set_enabled(&self, enabled: bool) -> Result<(), Error> {
+        if enabled {
+            self.config.set("__LeaveStreamsUnattached", Some(1))?;
+            self.load_circuits();
+        } else {
+            self.config.restore_option("__LeaveStreamsUnattached")?;
+        }
+        self.enabled.set(enabled);
+        self.update_status();
+        Ok(())
+    }
+
+    fn update_status(&self) {
+        let text = if self.enabled.get() {
+            format!("{} streams waiting", self.attacher.borrow().pending())
+        } else {
+            "Disabled".to_string()
+        };
+        self.status.set_text(&text);
+    }
+
+    fn exit_country(&self, fingerprint: &RelayFingerprint) -> Option<&'static str> {
+        let mutex = crate::get_tor_controller();
+        let mut ctrl = mutex.lock().unwrap();
+        let or = ctrl.get_onion_router(fingerprint).ok()?;
+        match or.target.addr {
+            HostOrAddr::Addr(addr) => self.gi.lookup_ip(addr),
+            HostOrAddr::Host(_) => None,
+        }
+    }
+
+    /// Reads the circuits built while the events were not followed.
+    fn load_circuits(&self) {
+        let result = crate::get_tor_controller().lock().unwrap().get_circuits();
+        match result {
+            Ok(circuits) => self
+                .attacher
+                .borrow_mut()
+                .set_circuits(&circuits, |fingerprint| self.exit_country(fingerprint)),
+            Err(e) => log::warn!("Could not read the circuits: {}", e),
+        }
+    }
+
+    pub(crate) fn handle_event(&self, event: &Event) {
+        if !self.enabled.get() {
+            return;
+        }
+        let commands = match event {
+            Event::Bandwidth(_) => self.attacher.borrow_mut().poll(Time::now()),
+            _ => self
+                .attacher
+                .borrow_mut()
+                .handle_event(event, |fingerprint| self.exit_country(fingerprint)),
+        };
+        for command in commands {
+            self.execute(command);
+        }
+        if let Event::Bandwidth(_) = event {
+            self.update_status();
+        }
+    }
+
+    fn execute(&self, command: AttachCommand) {
+        match command {
+            AttachCommand::Attach { stream, circuit } => {
+                let result = crate::get_tor_controller()
+                    .lock()
+                    .unwrap()
+                    .attach_stream(stream.clone(), circuit.clone());
+                // The stream may have been closed, or the circuit meanwhile
+                if let Err(e) = result {
+                    log::warn!("Could not attach stream {} to {}: {}", stream, circuit, e);
+                    if circuit.0 == "0" {
+                        self.attacher.borrow_mut().attach_failed(&stream);
+                    } else {
+                        self.execute(AttachCommand::Attach {
+                            stream,
+                            circuit: CircuitID("0".into()),
+                        });
+                    }
+                }
+            }
+            AttachCommand::Build {
+                stream,
+                exit,
+                target,
+            } => {
+                match self.build_circuit(&exit, &target) {
+                    Ok(Some(circuit)) => {
+                        log::info!("Building circuit {} for stream {}", circuit, stream);
+                        self.attacher
+                            .borrow_mut()
+                            .circuit_launched(&stream, circuit);
+                        return;
+                    }
+                    Ok(None) => log::warn!(
+                        "No path exiting at {:?} to {} for stream {}",
+                        exit.to_string(),
+                        target,
+                        stream
+                    ),
+                    Err(e) => log::warn!("Could not build a circuit for stream {}: {}", stream, e),
+                }
+                self.execute(AttachCommand::Attach {
+                    stream,
+                    circuit: CircuitID("0".into()),
+                });
+            }
+        }
+    }
+
+    /// Launches a circuit through one of tor's usable guards, exiting at a
+    /// relay matching `exit` and allowing `target`, `None` when there is no
+    /// such path.
+    fn build_circuit(&self, exit: &Query, target: &Target) -> Result<Option<CircuitID>, Error> {
+        let mutex = crate::get_tor_controller();
+        let mut ctrl = mutex.lock().unwrap();
+
+        let mut index = self.index.borrow_mut();
+        if index
+            .as_ref()
+            .is_none_or(|(_, loaded)| loaded.elapsed() > INDEX_LIFETIME)
+        {
+            let ors = ctrl.get_all_onion_router()?;
+            *index = Some((RelayIndex::new(ors, Some(&self.gi)), Instant::now()));
+            self.policies.borrow_mut().clear();
+        }
+        let (index, _) = index.as_ref().unwrap();
+
+        let guards: Vec<RelayFingerprint> = ctrl
+            .get_entry_guards()?
+            .into_iter()
+            .filter(|g| g.status == GuardStatus::Up)
+            .map(|g| g.relay.fingerprint)
+            .collect();
+        let mut policies = self.policies.borrow_mut();
+        let path = attach::pick_path(index, &guards, exit, target, |fingerprint| {
+            policies
+                .entry(*fingerprint)
+                .or_insert_with(|| ctrl.get_exit_policy(fingerprint).ok())
+                .clone()
+        });
+        drop(policies);
+        let Some(path) = path else {
+            return Ok(None);
+        };
+
+        let response = ctrl.extend_circuit(CircuitID("0".into()), &path)?;
+        let circuit = response
+            .trim_end()
+            .strip_prefix("EXTENDED ")
+            .ok_or_else(|| Error::Protocol(format!("Unexpected reply {response:?}")))?;
+        Ok(Some(CircuitID(circuit.into())))
+    }
+}
+
+impl NotebookTab for RuleTab {
+    fn get_widget(&self) -> Rc<gtk::Widget> {
+        let widget = self.widget.take().unwrap();
+        let copy = Rc::clone(&widget);
+        self.widget.set(Some(widget));
+        copy
+    }
+
+    fn label(&self) -> &'static str {
+        "Stream rules"
+    }
+}
@@ -1,235 +1,462 @@
-use std::cell::Cell;
-use std::collections::HashSet;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use gtk::prelude::*;
 
-use tor_analyzer_lib::country;
+use tor_analyzer_lib::country::{self, Country};
 use tor_analyzer_lib::error::Error;
 use tor_analyzer_lib::prelude::*;
+use tor_analyzer_lib::tor::addrmap::MappingSource;
 use tor_analyzer_lib::tor::circuit::CircuitStatus;
-use tor_analyzer_lib::tor::stream::StreamStatus;
+use tor_analyzer_lib::tor::stream::StreamReason;
 
+use crate::rows::RowIndex;
 use crate::NotebookTab;
 
 #[repr(i32)]
 enum Columns {
-    StreamID,
-    StreamEndpoint,
-    CircuitIds,
-    AttachButton,
+    Id,
+    Status,
+    Target,
+    MappedFrom,
+    Circuit,
+    ExitCountry,
+    Source,
+    Purpose,
+    Age,
+
+    /// Hidden, sorts the age column
+    Created,
+}
+const FIELD_COUNT: usize = Columns::Created as usize + 1;
+const COLUMNS_TYPE: [glib::Type; FIELD_COUNT] = [
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::STRING,
+    glib::Type::I64,
+];
+
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
 }
 
-struct Circuit {
-    circuit: tor_analyzer_lib::prelude::Circuit,
-    out_country: Option<&'static country::Country>,
+fn format_country(country: Option<&Country>) -> String {
+    country
+        .map(|c| format!("{} {}", c.flag, c.name))
+        .unwrap_or_default()
 }
 
 pub(crate) struct StreamTab {
-    grid: Rc<gtk::Grid>,
-    row_count: Cell<i32>,
-    widget: Rc<gtk::Widget>,
+    gi: GeoIP,
+    addresses: RefCell<AddressMap>,
+
+    /// Streams, with when they were first seen
+    streams: RefCell<HashMap<StreamID, (Stream, Time)>>,
+
+    /// Exit country of the built circuits
+    circuits: RefCell<HashMap<CircuitID, Option<&'static Country>>>,
+    rows: RefCell<RowIndex<StreamID>>,
+    widget: Cell<Option<Rc<gtk::Widget>>>,
+    store: gtk::ListStore,
+    treeview: gtk::TreeView,
+    circuit_box: gtk::ComboBoxText,
+    target_entry: gtk::Entry,
+    attach_btn: gtk::Button,
+    redirect_btn: gtk::Button,
+    close_btn: gtk::Button,
 }
 
 impl StreamTab {
     pub(crate) fn new() -> Rc<Self> {
-        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 8);
-        vbox.set_homogeneous(false);
-        let widget: gtk::Widget = vbox.upcast();
-
-        let grid = gtk::Grid::new();
+        let store = gtk::ListStore::new(&COLUMNS_TYPE);
+        let treeview = gtk::TreeView::with_model(&store);
+        let me = Self {
+            gi: GeoIP::new(),
+            addresses: RefCell::new(AddressMap::default()),
+            streams: RefCell::new(HashMap::new()),
+            circuits: RefCell::new(HashMap::new()),
+            rows: RefCell::new(RowIndex::new()),
+            widget: Cell::new(None),
+            store,
+            treeview,
+            circuit_box: gtk::ComboBoxText::new(),
+            target_entry: gtk::Entry::new(),
+            attach_btn: gtk::Button::with_label("Attach"),
+            redirect_btn: gtk::Button::with_label("Redirect"),
+            close_btn: gtk::Button::with_label("Close stream"),
+        };
+        me.create()
+    }
 
-        let me = Rc::new(Self {
-            grid: Rc::new(grid),
-            row_count: Cell::new(0),
-            widget: Rc::new(widget),
-        });
+    fn create(self) -> Rc<Self> {
+        let me = Rc::new(self);
         Rc::clone(&me).create_ui();
         me
     }
 
-    pub(crate) fn create_ui(self: Rc<Self>) {
-        let vbox = self.widget.downcast_ref::<gtk::Box>().unwrap();
-        vbox.add(&*Rc::clone(&self.grid));
-
-        self.grid.set_hexpand(true);
-        self.grid.set_vexpand(false);
-        self.grid.set_row_homogeneous(true);
-        self.grid.set_column_homogeneous(false);
-
-        let label = gtk::Label::new(Some("ID"));
-        label.set_vexpand(true);
-        label.set_hexpand(true);
-        label.set_halign(gtk::Align::Center);
-        self.grid.attach(&label, Columns::StreamID as i32, 0, 1, 1);
-
-        let label = gtk::Label::new(Some("End point"));
-        label.set_vexpand(true);
-        label.set_hexpand(true);
-        label.set_halign(gtk::Align::Center);
-        self.grid
-            .attach(&label, Columns::StreamEndpoint as i32, 0, 1, 1);
-
-        let label = gtk::Label::new(Some("Circuit"));
-        label.set_vexpand(true);
-        label.set_hexpand(true);
-        label.set_halign(gtk::Align::Center);
-        self.grid
-            .attach(&label, Columns::CircuitIds as i32, 0, 1, 1);
-
-        let label = gtk::Label::new(Some("Button"));
-        label.set_vexpand(true);
-        label.set_hexpand(true);
-        label.set_halign(gtk::Align::Center);
-        self.grid
-            .attach(&label, Columns::AttachButton as i32, 0, 1, 1);
-        self.row_count.set(1);
+    fn create_ui(self: Rc<Self>) {
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 8);
+        vbox.set_homogeneous(false);
+
+        let sw = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        sw.set_shadow_type(gtk::ShadowType::EtchedIn);
+        sw.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        vbox.add(&sw);
+
+        let treeview = &self.treeview;
+        treeview.set_vexpand(true);
+        sw.add(treeview);
+
+        add_column!(treeview, Columns::Id, "ID");
+        add_column!(treeview, Columns::Status, "Status");
+        add_column!(treeview, Columns::Target, "Target");
+        add_column!(treeview, Columns::MappedFrom, "Mapped from");
+        add_column!(treeview, Columns::Circuit, "Circuit");
+        add_column!(treeview, Columns::ExitCountry, "Exit country");
+        add_column!(treeview, Columns::Source, "Source");
+        add_column!(treeview, Columns::Purpose, "Purpose");
+        let age = add_column!(treeview, Columns::Age, "Age");
+        age.set_sort_column_id(Columns::Created as i32);
+
+        let me = Rc::clone(&self);
+        treeview
+            .selection()
+            .connect_changed(move |_| me.update_actions());
+
+        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        hbox.add(&self.circuit_box);
+        let me = Rc::clone(&self);
+        self.attach_btn.connect_clicked(move |_| me.attach());
+        hbox.add(&self.attach_btn);
+
+        self.target_entry.set_placeholder_text(Some("host:port"));
+        hbox.add(&self.target_entry);
+        let me = Rc::clone(&self);
+        self.redirect_btn.connect_clicked(move |_| me.redirect());
+        hbox.add(&self.redirect_btn);
+
+        let me = Rc::clone(&self);
+        self.close_btn.connect_clicked(move |_| me.close());
+        hbox.add(&self.close_btn);
+        vbox.add(&hbox);
 
         let update_btn = gtk::Button::with_label("Update streams");
         let me = Rc::clone(&self);
-        update_btn.connect_clicked(move |_| {
-            let row_count = me.row_count.get();
-            for _ in 1..row_count {
-                me.grid.remove_row(1);
-            }
-            me.row_count.set(1);
+        update_btn.connect_clicked(move |_| match me.refresh_data() {
+            Ok(_) => me.refresh_view(),
+            Err(e) => log::warn!("Could not refresh streams: {}", e),
+        });
+        vbox.add(&update_btn);
+        update_btn.clicked();
 
-            let (circuits, streams) = match me.refresh_data() {
-                Ok(v) => v,
-                Err(e) => {
-                    log::warn!("Could not refresh data: {}", e);
-                    return;
-                }
-            };
+        let widget = Some(Rc::new(vbox.upcast()));
+        self.widget.set(widget);
+    }
 
-            let occupied_circuits = streams
-                .iter()
-                .filter_map(|s| {
-                    if s.circuit_id.0 != "0" {
-                        Some(&s.circuit_id)
-                    } else {
-                        None
-                    }
-                })
-                .collect::<HashSet<_>>();
-
-            for stream in streams.iter().filter(|s| s.status == StreamStatus::New) {
-                let row_count = me.row_count.get();
-                me.grid.insert_row(row_count);
-                me.row_count.set(row_count + 1);
-
-                let label = gtk::Label::new(Some(format!("{}", stream.id).as_str()));
-                me.grid
-                    .attach(&label, Columns::StreamID as i32, row_count, 1, 1);
-
-                let label = gtk::Label::new(Some(format!("{}", stream.target).as_str()));
-                me.grid
-                    .attach(&label, Columns::StreamEndpoint as i32, row_count, 1, 1);
-
-                let combobox = gtk::ComboBoxText::new();
-                for circuit in circuits.iter().filter(|c| {
-                    c.circuit.status == CircuitStatus::Built
-                        && !occupied_circuits.contains(&&c.circuit.id)
-                }) {
-                    if let Some(out_country) = circuit.out_country {
-                        combobox.append(
-                            Some(circuit.circuit.id.0.as_str()),
-                            format!(
-                                "{} {} ({})",
-                                out_country.flag, out_country.name, circuit.circuit.id
-                            )
-                            .as_str(),
-                        );
-                    } else {
-                        combobox.append_text(format!("{}", circuit.circuit.id).as_str());
+    fn exit_country(
+        &self,
+        ctrl: &mut TorController,
+        circuit: &Circuit,
+    ) -> Option<&'static Country> {
+        let exit = circuit.path.last()?;
+        let or = ctrl.get_onion_router(&exit.fingerprint).ok()?;
+        match or.target.addr {
+            HostOrAddr::Host(_) => None,
+            HostOrAddr::Addr(ref addr) => self.gi.lookup_ip(*addr).and_then(country::get_country),
+        }
+    }
+
+    /// Replaces the streams with the open ones, closed streams are dropped.
+    fn refresh_data(&self) -> Result<(), Error> {
+        let mutex = crate::get_tor_controller();
+        let mut ctrl = mutex.lock().unwrap();
+        let circuits = ctrl.get_circuits()?;
+        let circuits: HashMap<_, _> = circuits
+            .iter()
+            .filter(|c| c.status == CircuitStatus::Built)
+            .map(|c| (c.id.clone(), self.exit_country(&mut ctrl, c)))
+            .collect();
+        let streams = ctrl.get_streams()?;
+        let mappings = ctrl.get_address_mappings(MappingSource::All)?;
+        drop(ctrl);
+
+        // Mappings learnt from events are kept, tor forgets the expired ones
+        let mut addresses = self.addresses.borrow_mut();
+        for mapping in mappings {
+            addresses.insert(mapping);
+        }
+
+        let now = Time::now();
+        let mut known = self.streams.borrow_mut();
+        let streams = streams
+            .into_iter()
+            .map(|s| {
+                let created = known.get(&s.id).map_or(now, |(_, created)| *created);
+                (s.id.clone(), (s, created))
+            })
+            .collect();
+        *known = streams;
+        self.circuits.replace(circuits);
+        Ok(())
+    }
+
+    pub(crate) fn handle_event(&self, event: &Event) {
+        match event {
+            Event::AddrMap(_) => {
+                self.addresses.borrow_mut().handle_event(event);
+            }
+            Event::Circuit(circuit) => match circuit.status {
+                CircuitStatus::Built => {
+                    let mutex = crate::get_tor_controller();
+                    let mut ctrl = mutex.lock().unwrap();
+                    let country = self.exit_country(&mut ctrl, circuit);
+                    drop(ctrl);
+                    self.circuits
+                        .borrow_mut()
+                        .insert(circuit.id.clone(), country);
+                    self.update_circuit_box();
+                }
+                CircuitStatus::Closed | CircuitStatus::Failed => {
+                    let removed = self.circuits.borrow_mut().remove(&circuit.id);
+                    if removed.is_some() {
+                        self.update_circuit_box();
                     }
                 }
-                me.grid
-                    .attach(&combobox, Columns::CircuitIds as i32, row_count, 1, 1);
-
-                let assign_btn = gtk::Button::with_label("Assign");
-                me.grid
-                    .attach(&assign_btn, Columns::AttachButton as i32, row_count, 1, 1);
-
-                let me_btn = Rc::clone(&me);
-                assign_btn.connect_clicked(move |b| {
-                    let idx = me_btn
-                        .grid
-                        .children()
-                        .iter()
-                        .enumerate()
-                        .find_map(|(idx, widget)| if widget == b { Some(idx + 1) } else { None })
-                        .unwrap();
-
-                    let idx = idx as i32;
-                    let stream_id = me_btn
-                        .grid
-                        .child_at(Columns::StreamID as i32, idx)
-                        .unwrap()
-                        .downcast_ref::<gtk::Label>()
-                        .unwrap()
-                        .text();
-                    let opt_circuit_id = me_btn
-                        .grid
-                        .child_at(Columns::CircuitIds as i32, idx)
-                        .unwrap()
-                        .downcast_ref::<gtk::ComboBoxText>()
-                        .unwrap()
-                        .active_id();
-
-                    if let Some(circuit_id) = opt_circuit_id {
-                        let mutex = crate::get_tor_controller();
-                        let mut ctrl = mutex.lock().unwrap();
-                        match ctrl.attach_stream(
-                            StreamID(stream_id.as_str().into()),
-                            CircuitID(circuit_id.as_str().into()),
-                        ) {
-                            Ok(text) => log::debug!("OK: {}", text),
-                            Err(e) => log::warn!("Error: {}", e),
-                        }
-                    } else {
-                        log::info!("No circuit selected");
+                _ => {}
+            },
+            Event::Stream(stream) => {
+                let created = {
+                    let mut streams = self.streams.borrow_mut();
+                    let created = streams
+                        .get(&stream.id)
+                        .map_or_else(Time::now, |(_, created)| *created);
+                    streams.insert(stream.id.clone(), (stream.clone(), created));
+                    created
+                };
+                let mut rows = self.rows.borrow_mut();
+                let iter = match rows.get(&stream.id) {
+                    Some(iter) => iter,
+                    None => {
+                        let iter = self.store.append();
+                        rows.insert(stream.id.clone(), &self.store, &iter);
+                        iter
                     }
-                });
+                };
+                rows.set_done(&stream.id, stream.status.is_closed());
+                drop(rows);
+                self.set_row(&iter, stream, created);
+                self.update_actions();
+            }
+            // Once per second, as good as any timer for the ages
+            Event::Bandwidth(_) => {
+                self.addresses.borrow_mut().expire(Time::now());
+                self.expire();
+                self.refresh_ages();
             }
+            _ => {}
+        }
+    }
 
-            me.grid.show_all();
-        });
-        vbox.add(&*Rc::clone(&self.grid));
-        vbox.add(&update_btn);
+    /// Drops the streams closed for a while.
+    fn expire(&self) {
+        let expired = self.rows.borrow_mut().expire(Instant::now());
+        if expired.is_empty() {
+            return;
+        }
+        let mut streams = self.streams.borrow_mut();
+        for (id, iter) in expired {
+            self.store.remove(&iter);
+            streams.remove(&id);
+        }
+        drop(streams);
+        self.update_actions();
+    }
 
-        // Fill table
-        update_btn.clicked();
+    fn set_row(&self, iter: &gtk::TreeIter, stream: &Stream, created: Time) {
+        let mapped_from = self
+            .addresses
+            .borrow()
+            .sources(&stream.target.addr)
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (circuit, exit_country) = if stream.circuit_id.0 == "0" {
+            (String::new(), String::new())
+        } else {
+            let country = self.circuits.borrow().get(&stream.circuit_id).copied();
+            (
+                stream.circuit_id.to_string(),
+                format_country(country.flatten()),
+            )
+        };
+        let source = match (&stream.source_addr, stream.purpose) {
+            (Some(source_addr), _) => source_addr.to_string(),
+            (None, Some(_)) => "Tor".into(),
+            (None, None) => String::new(),
+        };
+        let values: [(u32, &dyn ToValue); FIELD_COUNT] = [
+            (Columns::Id as u32, &stream.id.to_string()),
+            (Columns::Status as u32, &stream.status.to_string()),
+            (Columns::Target as u32, &stream.target.to_string()),
+            (Columns::MappedFrom as u32, &mapped_from),
+            (Columns::Circuit as u32, &circuit),
+            (Columns::ExitCountry as u32, &exit_country),
+            (Columns::Source as u32, &source),
+            (
+                Columns::Purpose as u32,
+                &stream.purpose.map(|p| p.to_string()).unwrap_or_default(),
+            ),
+            (Columns::Age as u32, &format_age(created.elapsed())),
+            (Columns::Created as u32, &created.unix_timestamp()),
+        ];
+        self.store.set(iter, &values);
     }
 
-    fn refresh_data(&self) -> Result<(Vec<Circuit>, Vec<Stream>), Error> {
-        let gi = GeoIP::new();
-        let mutex = crate::get_tor_controller();
-        let mut ctrl = mutex.lock().unwrap();
-        let mut circuits = ctrl.get_circuits()?;
-        let mut circuits_with_country = Vec::with_capacity(circuits.len());
-        for c in circuits.drain(..) {
-            let last_node = c.path.iter().last().unwrap();
-            let or = ctrl.get_onion_router(hex_encode(last_node.fingerprint))?;
-            let out_country = match or.target.addr {
-                HostOrAddr::Host(_) => None,
-                HostOrAddr::Addr(ref addr) => gi.lookup_ip(*addr).and_then(country::get_country),
+    fn refresh_view(&self) {
+        self.store.clear();
+        let mut rows = self.rows.borrow_mut();
+        rows.clear();
+        let streams = self.streams.borrow();
+        let mut streams: Vec<_> = streams.values().collect();
+        streams.sort_by_key(|(s, _)| s.id.0.parse::<u64>().unwrap_or(u64::MAX));
+        for (stream, created) in streams {
+            let iter = self.store.append();
+            self.set_row(&iter, stream, *created);
+            rows.insert(stream.id.clone(), &self.store, &iter);
+            rows.set_done(&stream.id, stream.status.is_closed());
+        }
+        drop(rows);
+        self.update_actions();
+        self.update_circuit_box();
+    }
+
+    /// Updates the age of the open streams, closed ones keep their last age.
+    fn refresh_ages(&self) {
+        let rows = self.rows.borrow();
+        let streams = self.streams.borrow();
+        for (id, (stream, created)) in streams.iter() {
+            if stream.status.is_closed() {
+                continue;
+            }
+            if let Some(iter) = rows.get(id) {
+                let age = format_age(created.elapsed());
+                self.store.set(&iter, &[(Columns::Age as u32, &age)]);
+            }
+        }
+    }
+
+    fn selected_stream(&self) -> Option<Stream> {
+        let (model, iter) = self.treeview.selection().selected()?;
+        let id = model
+            .value(&iter, Columns::Id as i32)
+            .get::<String>()
+            .ok()?;
+        self.streams
+            .borrow()
+            .get(&StreamID(id))
+            .map(|(stream, _)| stream.clone())
+    }
+
+    /// Enables the actions possible on the selected stream.
+    fn update_actions(&self) {
+        let status = self.selected_stream().map(|s| s.status);
+        let pending = status.is_some_and(|s| s.is_pending());
+        self.attach_btn.set_sensitive(pending);
+        self.redirect_btn.set_sensitive(pending);
+        self.close_btn
+            .set_sensitive(status.is_some_and(|s| !s.is_closed()));
+    }
+
+    /// Lists the circuits streams can be attached to.
+    fn update_circuit_box(&self) {
+        let active = self.circuit_box.active_id();
+        self.circuit_box.remove_all();
+        let circuits = self.circuits.borrow();
+        let mut circuits: Vec<_> = circuits.iter().collect();
+        circuits.sort_by_key(|(id, _)| id.0.parse::<u64>().unwrap_or(u64::MAX));
+        for (id, country) in circuits {
+            let label = match country {
+                Some(country) => format!("{} {} ({id})", country.flag, country.name),
+                None => id.to_string(),
             };
-            circuits_with_country.push(Circuit {
-                circuit: c,
-                out_country,
-            });
+            self.circuit_box.append(Some(id.0.as_str()), &label);
         }
-        let streams = ctrl.get_streams()?;
-        drop(ctrl);
+        if let Some(active) = active {
+            self.circuit_box.set_active_id(Some(active.as_str()));
+        }
+    }
 
-        Ok((circuits_with_country, streams))
+    fn attach(&self) {
+        let Some(stream) = self.selected_stream() else {
+            return;
+        };
+        let Some(circuit_id) = self.circuit_box.active_id() else {
+            log::info!("No circuit selected");
+            return;
+        };
+        let result = crate::get_tor_controller()
+            .lock()
+            .unwrap()
+            .attach_stream(stream.id.clone(), CircuitID(circuit_id.as_str().into()));
+        if let Err(e) = result {
+            popup_error!("Could not attach stream {}: {}", stream.id, e);
+        }
+    }
+
+    fn redirect(&self) {
+        let Some(stream) = self.selected_stream() else {
+            return;
+        };
+        let target: Target = match self.target_entry.text().parse() {
+            Ok(target) => target,
+            Err(e) => {
+                popup_error!("Invalid target {:?}: {}", self.target_entry.text(), e);
+                return;
+            }
+        };
+        let result = crate::get_tor_controller()
+            .lock()
+            .unwrap()
+            .redirect_stream(stream.id.clone(), &target);
+        if let Err(e) = result {
+            popup_error!("Could not redirect stream {}: {}", stream.id, e);
+        }
+    }
+
+    fn close(&self) {
+        let Some(stream) = self.selected_stream() else {
+            return;
+        };
+        let result = crate::get_tor_controller()
+            .lock()
+            .unwrap()
+            .close_stream(stream.id.clone(), StreamReason::Done);
+        if let Err(e) = result {
+            popup_error!("Could not close stream {}: {}", stream.id, e);
+        }
     }
 }
 
 impl NotebookTab for StreamTab {
     fn get_widget(&self) -> Rc<gtk::Widget> {
-        Rc::clone(&self.widget)
+        let widget = self.widget.take().unwrap();
+        let copy = Rc::clone(&widget);
+        self.widget.set(Some(widget));
+        copy
     }
 
     fn label(&self) -> &'static str {
@@ -0,0 +1,231 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk::cairo;
+use gtk::prelude::*;
+
+use tor_analyzer_lib::prelude::*;
+
+use crate::notebook::NotebookTab;
+
+/// Number of bars of the build-time histogram.
+const HISTOGRAM_BINS: u64 = 50;
+
+/// `BUILDTIMEOUT_SET` events kept in the history, the newest first.
+const HISTORY_LENGTH: i32 = 200;
+
+#[repr(i32)]
+enum Columns {
+    Time,
+    Type,
+    Timeout,
+    TimeoutRate,
+    TotalTimes,
+    Xm,
+    Alpha,
+    CutoffQuantile,
+}
+const FIELD_COUNT: usize = Columns::CutoffQuantile as usize + 1;
+
+pub(crate) struct BuildTimeoutTab {
+    tracker: RefCell<CircuitTracker>,
+    current: RefCell<Option<BuildTimeout>>,
+    configured: RefCell<String>,
+    widget: Cell<Option<Rc<gtk::Widget>>>,
+    summary: gtk::Label,
+    histogram: gtk::DrawingArea,
+    store: gtk::ListStore,
+}
+
+impl BuildTimeoutTab {
+    pub(crate) fn new() -> Rc<Self> {
+        let col_types = [glib::Type::STRING; FIELD_COUNT];
+        let me = Self {
+            tracker: RefCell::new(CircuitTracker::default()),
+            current: RefCell::new(None),
+            configured: RefCell::new(String::new()),
+            widget: Cell::new(None),
+            summary: gtk::Label::new(None),
+            histogram: gtk::DrawingArea::new(),
+            store: gtk::ListStore::new(&col_types),
+        };
+        me.create()
+    }
+
+    fn create(self) -> Rc<Self> {
+        let me = Rc::new(self);
+        Rc::clone(&me).create_ui();
+        me
+    }
+
+    fn create_ui(self: Rc<Self>) {
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 8);
+        vbox.set_homogeneous(false);
+
+        self.summary.set_xalign(0.);
+        vbox.add(&self.summary);
+
+        self.histogram.set_size_request(-1, 200);
+        let me = Rc::clone(&self);
+        self.histogram.connect_draw(move |area, cr| {
+            if let Err(e) = me.draw(area, cr) {
+                log::warn!("Could not draw build time histogram: {}", e);
+            }
+            gtk::Inhibit(false)
+        });
+        vbox.add(&self.histogram);
+
+        let sw = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        sw.set_shadow_type(gtk::ShadowType::EtchedIn);
+        sw.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        vbox.add(&sw);
+
+        let treeview = gtk::TreeView::with_model(&self.store);
+        treeview.set_vexpand(true);
+        sw.add(&treeview);
+
+        add_column!(treeview, Columns::Time, "Time");
+        add_column!(treeview, Columns::Type, "Type");
+        add_column!(treeview, Columns::Timeout, "Timeout");
+        add_column!(treeview, Columns::TimeoutRate, "Timeout rate");
+        add_column!(treeview, Columns::TotalTimes, "Build times");
+        add_column!(treeview, Columns::Xm, "Xm");
+        add_column!(treeview, Columns::Alpha, "Alpha");
+        add_column!(treeview, Columns::CutoffQuantile, "Quantile");
+
+        let mutex = crate::get_tor_controller();
+        let mut ctrl = mutex.lock().unwrap();
+        self.configured
+            .replace(match ctrl.get_circuit_build_timeout() {
+                Ok(Some(timeout)) => format!("Fixed timeout {} s", timeout.as_secs()),
+                Ok(None) => "Learning the timeout, waiting for tor to set it".into(),
+                Err(e) => format!("Could not read the timeout: {e}"),
+            });
+        drop(ctrl);
+        self.refresh_summary();
+
+        let widget = Some(Rc::new(vbox.upcast()));
+        self.widget.set(widget);
+    }
+
+    pub(crate) fn handle_event(&self, event: &Event) {
+        match event {
+            Event::Circuit(_) => {
+                self.tracker.borrow_mut().handle_event(event);
+                self.refresh_summary();
+                self.histogram.queue_draw();
+            }
+            Event::BuildTimeoutSet(build_timeout) => {
+                let values: [(u32, &dyn ToValue); FIELD_COUNT] = [
+                    (0, &Time::now().to_string()),
+                    (1, &build_timeout.kind.to_string()),
+                    (2, &format!("{} ms", build_timeout.timeout_ms)),
+                    (3, &format!("{:.1} %", build_timeout.timeout_rate * 100.)),
+                    (4, &build_timeout.total_times.to_string()),
+                    (5, &format!("{} ms", build_timeout.xm)),
+                    (6, &format!("{:.3}", build_timeout.alpha)),
+                    (7, &format!("{:.2}", build_timeout.cutoff_quantile)),
+                ];
+                self.store.set(&self.store.insert(0), &values);
+                if self.store.iter_n_children(None) > HISTORY_LENGTH {
+                    if let Some(oldest) = self.store.iter_nth_child(None, HISTORY_LENGTH) {
+                        self.store.remove(&oldest);
+                    }
+                }
+                self.current.replace(Some(build_timeout.clone()));
+                self.refresh_summary();
+                self.histogram.queue_draw();
+            }
+            _ => {}
+        }
+    }
+
+    fn refresh_summary(&self) {
+        let tracker = self.tracker.borrow();
+        let (built, failed) = tracker.build_counts();
+        let median = tracker
+            .build_time_quantile(0.5)
+            .map(|d| format!("{} ms", d.as_millis()))
+            .unwrap_or_else(|| "-".into());
+        let timeout = match *self.current.borrow() {
+            Some(ref current) => format!(
+                "Timeout {} ms ({}, {:.1} % timing out)",
+                current.timeout_ms,
+                current.kind,
+                current.timeout_rate * 100.
+            ),
+            None => self.configured.borrow().clone(),
+        };
+        self.summary.set_text(&format!(
+            "{timeout}\nCircuits built: {built}, failed: {failed}, median build time: {median}"
+        ));
+    }
+
+    fn draw(&self, area: &gtk::DrawingArea, cr: &cairo::Context) -> Result<(), cairo::Error> {
+        let width = area.allocated_width() as f64;
+        let height = area.allocated_height() as f64;
+        cr.set_source_rgb(1., 1., 1.);
+        cr.paint()?;
+        cr.set_source_rgb(0., 0., 0.);
+        cr.set_font_size(12.);
+        cr.move_to(5., 15.);
+
+        let tracker = self.tracker.borrow();
+        let timeout_ms = self.current.borrow().as_ref().map(|c| c.timeout_ms);
+        let slowest = match tracker.build_time_quantile(0.99) {
+            Some(slowest) => slowest.as_millis() as u64,
+            None => {
+                cr.show_text("No circuit built yet")?;
+                return Ok(());
+            }
+        };
+        let range_ms = slowest.max(timeout_ms.unwrap_or_default() * 5 / 4).max(1);
+        let bin_ms = range_ms.div_ceil(HISTOGRAM_BINS).max(1);
+        let histogram = tracker.build_time_histogram(Duration::from_millis(bin_ms));
+        let peak = histogram.iter().copied().max().unwrap_or(1).max(1) as f64;
+        cr.show_text(&format!(
+            "Build times, {} ms per bar, up to {} ms",
+            bin_ms,
+            bin_ms * HISTOGRAM_BINS
+        ))?;
+
+        let bar_width = width / HISTOGRAM_BINS as f64;
+        let plot_height = height - 25.;
+        cr.set_source_rgb(0.2, 0.4, 0.8);
+        for (i, count) in histogram.iter().take(HISTOGRAM_BINS as usize).enumerate() {
+            let bar_height = *count as f64 / peak * plot_height;
+            cr.rectangle(
+                i as f64 * bar_width,
+                height - bar_height,
+                bar_width - 1.,
+                bar_height,
+            );
+        }
+        cr.fill()?;
+
+        if let Some(timeout_ms) = timeout_ms {
+            let x = timeout_ms as f64 / (bin_ms * HISTOGRAM_BINS) as f64 * width;
+            cr.set_source_rgb(0.8, 0.1, 0.1);
+            cr.move_to(x, 20.);
+            cr.line_to(x, height);
+            cr.stroke()?;
+            cr.move_to(x + 3., 30.);
+            cr.show_text(&format!("timeout {timeout_ms} ms"))?;
+        }
+        Ok(())
+    }
+}
+
+impl NotebookTab for BuildTimeoutTab {
+    fn get_widget(&self) -> Rc<gtk::Widget> {
+        let widget = self.widget.take().unwrap();
+        let copy = Rc::clone(&widget);
+        self.widget.set(Some(widget));
+        copy
+    }
+
+    fn label(&self) -> &'static str {
+        "Build timeout"
+    }
+}
@@ -0,0 +1,120 @@
+//! Tor's address map, kept up to date with `ADDRMAP` events.
+
+use std::collections::HashMap;
+
+use crate::tor::addrmap::AddressMapping;
+use crate::tor::common::{HostOrAddr, Time};
+use crate::tor::event::Event;
+
+#[derive(Debug, Clone, Default)]
+pub struct AddressMap {
+    /// Indexed by the address mapped
+    mappings: HashMap<HostOrAddr, AddressMapping>,
+}
+
+impl AddressMap {
+    pub fn new(mappings: Vec<AddressMapping>) -> Self {
+        Self {
+            mappings: mappings
+                .into_iter()
+                .map(|m| (m.address.clone(), m))
+                .collect(),
+        }
+    }
+
+    /// Records `ADDRMAP` events, returns the new mapping.
+    ///
+    /// Reverse lookups are left out, they map an address to a name.
+    pub fn handle_event(&mut self, event: &Event) -> Option<&AddressMapping> {
+        let Event::AddrMap(addrmap) = event else {
+            return None;
+        };
+        if addrmap.reverse {
+            return None;
+        }
+        let Some(mapping) = addrmap.mapping() else {
+            self.mappings.remove(&addrmap.address);
+            return None;
+        };
+        self.insert(mapping);
+        self.mappings.get(&addrmap.address)
+    }
+
+    /// Adds `mapping`, replacing the one of the same address.
+    pub fn insert(&mut self, mapping: AddressMapping) {
+        self.mappings.insert(mapping.address.clone(), mapping);
+    }
+
+    pub fn get(&self, address: &HostOrAddr) -> Option<&AddressMapping> {
+        self.mappings.get(address)
+    }
+
+    /// Addresses mapped to `address`, directly or through other mappings.
+    pub fn sources(&self, address: &HostOrAddr) -> Vec<&HostOrAddr> {
+        let mut sources: Vec<&HostOrAddr> = Vec::new();
+        let mut targets = vec![address];
+        while let Some(target) = targets.pop() {
+            for mapping in self.mappings.values() {
+                if mapping.new_address == *target
+                    && mapping.address != *address
+                    && !sources.contains(&&mapping.address)
+                {
+                    sources.push(&mapping.address);
+                    targets.push(&mapping.address);
+                }
+            }
+        }
+        sources.sort_by_key(|a| a.to_string());
+        sources
+    }
+
+    /// Forgets the mappings expired at `now`.
+    pub fn expire(&mut self, now: Time) {
+        self.mappings
+            .retain(|_, m| m.expires.is_none_or(|expires| expires > now));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &AddressMapping> {
+        self.mappings.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.mappings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_map() {
+        let mapping: AddressMapping = "example.com www.example.com NEVER".parse().unwrap();
+        let mut map = AddressMap::new(vec![mapping]);
+
+        let event = Event::from_raw(
+            "ADDRMAP",
+            "www.example.com 93.184.216.34 \"2023-04-11 15:37:42\" \
+             EXPIRES=\"2023-04-11 13:37:42\" CACHED=\"YES\"",
+        )
+        .unwrap();
+        assert!(map.handle_event(&event).is_some());
+        assert_eq!(map.len(), 2);
+
+        let ip = HostOrAddr::Addr("93.184.216.34".parse().unwrap());
+        let sources: Vec<String> = map.sources(&ip).iter().map(|a| a.to_string()).collect();
+        assert_eq!(sources, ["example.com", "www.example.com"]);
+
+        map.expire("2023-04-11 14:00:00".parse().unwrap());
+        assert_eq!(map.len(), 1);
+        assert!(map.sources(&ip).is_empty());
+
+        let event = Event::from_raw("ADDRMAP", "example.com <error> NEVER error=yes").unwrap();
+        assert!(map.handle_event(&event).is_none());
+        assert!(map.is_empty());
+    }
+}
@@ -0,0 +1,809 @@
+//! Rule-based stream attachment, for controllers setting `__LeaveStreamsUnattached`.
+//!
+//! The engine does not talk to tor, it tells what to do with [`AttachCommand`]s
+//! so the controller can be shared with the rest of the application.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, tag_no_case, take_while1};
+use nom::character::complete::{alpha1, space0, space1, u16 as parse_u16};
+use nom::combinator::{all_consuming, cut, map, opt, verify};
+use nom::error::{context, ContextError, ParseError};
+use nom::multi::{separated_list0, separated_list1};
+use nom::sequence::{preceded, tuple};
+use rand::seq::SliceRandom;
+
+use crate::index::{Relay, RelayIndex};
+use crate::query::{Predicate, Query, Term};
+use crate::tor::circuit::{Circuit, CircuitBuildFlag, CircuitPurpose, CircuitStatus};
+use crate::tor::common::{Cidr, CircuitID, HostOrAddr, StreamID, Target, Time};
+use crate::tor::event::Event;
+use crate::tor::identity::RelayFingerprint;
+use crate::tor::ns::OnionRouterFlag;
+use crate::tor::policy::ExitPolicy;
+use crate::tor::stream::{Stream, StreamStatus};
+use crate::tor::NomParse;
+
+/// Streams no rule matches are left to tor after this delay.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn not_space<'a, E>(s: &'a str) -> nom::IResult<&'a str, &'a str, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    take_while1(|c: char| !c.is_ascii_whitespace())(s)
+}
+
+/// Target host: a name, `*.domain` for the domain and its subdomains, or `*`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum HostPattern {
+    Any,
+    Exact(String),
+    Domain(String),
+}
+
+impl HostPattern {
+    pub fn matches(&self, host: &HostOrAddr) -> bool {
+        let host = match host {
+            HostOrAddr::Host(host) => host.to_lowercase(),
+            HostOrAddr::Addr(addr) => addr.to_string(),
+        };
+        match self {
+            Self::Any => true,
+            Self::Exact(name) => host == *name,
+            Self::Domain(domain) => {
+                host == *domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            }
+        }
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => f.write_str("*"),
+            Self::Exact(name) => f.write_str(name),
+            Self::Domain(domain) => write!(f, "*.{domain}"),
+        }
+    }
+}
+
+/// A condition on a stream.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Matcher {
+    Host(HostPattern),
+
+    /// Target port in this inclusive range
+    Port(u16, u16),
+
+    /// SOCKS username, tor isolates streams by username
+    User(String),
+
+    /// Client address is in this network
+    Source(Cidr),
+}
+
+impl Matcher {
+    pub fn matches(&self, stream: &Stream) -> bool {
+        match self {
+            Self::Host(pattern) => pattern.matches(&stream.target.addr),
+            Self::Port(low, high) => (*low..=*high).contains(&stream.target.port),
+            Self::User(user) => stream.socks_username.as_deref() == Some(user.as_str()),
+            Self::Source(cidr) => match stream.source_addr {
+                Some(ref source) => match source.addr {
+                    HostOrAddr::Addr(ref addr) => cidr.contains(addr),
+                    HostOrAddr::Host(_) => false,
+                },
+                None => false,
+            },
+        }
+    }
+}
+
+impl NomParse for Matcher {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Stream matcher",
+            alt((
+                map(
+                    preceded(tag_no_case("host:"), cut(not_space)),
+                    |host: &str| {
+                        let host = host.to_lowercase();
+                        Self::Host(if host == "*" {
+                            HostPattern::Any
+                        } else if let Some(domain) = host.strip_prefix("*.") {
+                            HostPattern::Domain(domain.into())
+                        } else {
+                            HostPattern::Exact(host)
+                        })
+                    },
+                ),
+                map(
+                    preceded(
+                        tag_no_case("port:"),
+                        cut(verify(
+                            tuple((parse_u16, opt(preceded(tag("-"), parse_u16)))),
+                            |(low, high)| high.is_none_or(|high| *low <= high),
+                        )),
+                    ),
+                    |(low, high)| Self::Port(low, high.unwrap_or(low)),
+                ),
+                map(
+                    preceded(tag_no_case("user:"), cut(not_space)),
+                    |user: &str| Self::User(user.into()),
+                ),
+                map(
+                    preceded(tag_no_case("source:"), cut(Cidr::parse)),
+                    Self::Source,
+                ),
+            )),
+        )(s)
+    }
+}
+
+impl FromStr for Matcher {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(all_consuming(Matcher::parse::<nom::error::VerboseError<&str>>)(s)?.1)
+    }
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Host(pattern) => write!(f, "host:{pattern}"),
+            Self::Port(low, high) if low == high => write!(f, "port:{low}"),
+            Self::Port(low, high) => write!(f, "port:{low}-{high}"),
+            Self::User(user) => write!(f, "user:{user}"),
+            Self::Source(cidr) => write!(f, "source:{cidr}"),
+        }
+    }
+}
+
+/// What to do with the streams a rule matches.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum AttachAction {
+    /// Attach to a circuit exiting in one of these countries (upper case
+    /// codes), built if there is none
+    Country(Vec<String>),
+
+    /// Attach to a new circuit, its exit matching the query
+    Build(Query),
+
+    /// Let tor pick a circuit
+    Tor,
+}
+
+impl NomParse for AttachAction {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Attach action",
+            alt((
+                map(
+                    preceded(
+                        tuple((tag_no_case("country"), space1)),
+                        cut(separated_list1(tag(","), alpha1)),
+                    ),
+                    |countries: Vec<&str>| {
+                        Self::Country(countries.iter().map(|c| c.to_uppercase()).collect())
+                    },
+                ),
+                map(preceded(tag_no_case("build"), Query::parse), Self::Build),
+                map(tag_no_case("tor"), |_| Self::Tor),
+            )),
+        )(s)
+    }
+}
+
+impl FromStr for AttachAction {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(all_consuming(AttachAction::parse::<nom::error::VerboseError<&str>>)(s)?.1)
+    }
+}
+
+impl fmt::Display for AttachAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Country(countries) => write!(f, "country {}", countries.join(",")),
+            Self::Build(query) if query.0.is_empty() => f.write_str("build"),
+            Self::Build(query) => write!(f, "build {query}"),
+            Self::Tor => f.write_str("tor"),
+        }
+    }
+}
+
+/// `matchers -> action`, no matcher matching every stream.
+///
+/// For instance `host:*.example.com port:443 -> country DE,FR`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct AttachRule {
+    pub matchers: Vec<Matcher>,
+    pub action: AttachAction,
+}
+
+impl AttachRule {
+    pub fn matches(&self, stream: &Stream) -> bool {
+        self.matchers.iter().all(|m| m.matches(stream))
+    }
+}
+
+impl NomParse for AttachRule {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (_, matchers, _, _, _, action)) = context(
+            "Attach rule",
+            tuple((
+                space0,
+                separated_list0(space1, Matcher::parse),
+                space0,
+                tag("->"),
+                space0,
+                AttachAction::parse,
+            )),
+        )(s)?;
+
+        Ok((rest, Self { matchers, action }))
+    }
+}
+
+impl FromStr for AttachRule {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(all_consuming(AttachRule::parse::<nom::error::VerboseError<&str>>)(s.trim_end())?.1)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for AttachRule {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for AttachRule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for AttachRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for matcher in &self.matchers {
+            write!(f, "{matcher} ")?;
+        }
+        write!(f, "-> {}", self.action)
+    }
+}
+
+/// What the controller should do for a stream.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum AttachCommand {
+    /// `ATTACHSTREAM`, circuit `0` letting tor pick one
+    Attach {
+        stream: StreamID,
+        circuit: CircuitID,
+    },
+
+    /// Build a circuit with an exit matching `exit` and allowing `target`,
+    /// and report it with [`StreamAttacher::circuit_launched`], or attach the
+    /// stream to circuit `0` if it cannot be built
+    Build {
+        stream: StreamID,
+        exit: Query,
+        target: Target,
+    },
+}
+
+impl AttachCommand {
+    fn let_tor_decide(stream: &StreamID) -> Self {
+        Self::Attach {
+            stream: stream.clone(),
+            circuit: CircuitID("0".into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PendingStream {
+    since: Time,
+
+    /// A command was given, waiting for tor to act on it
+    handled: bool,
+
+    /// Circuit being built for the stream
+    circuit: Option<CircuitID>,
+}
+
+/// Attaches new streams following the first rule matching them.
+///
+/// Streams without a rule, and streams whose circuit takes too long to be
+/// built, are left to tor after the timeout; unless attached by hand in the
+/// meantime.
+#[derive(Debug, Clone)]
+pub struct StreamAttacher {
+    rules: Vec<AttachRule>,
+    timeout: Duration,
+    pending: HashMap<StreamID, PendingStream>,
+
+    /// Exit country of the built circuits which can carry streams
+    exits: HashMap<CircuitID, Option<&'static str>>,
+}
+
+impl Default for StreamAttacher {
+    fn default() -> Self {
+        Self::new(Vec::new(), DEFAULT_TIMEOUT)
+    }
+}
+
+fn carries_streams(circuit: &Circuit) -> bool {
+    matches!(
+        circuit.purpose,
+        None | Some(CircuitPurpose::General) | Some(CircuitPurpose::Controller)
+    ) && !circuit.build_flags.contains(CircuitBuildFlag::IsInternal)
+        && !circuit.build_flags.contains(CircuitBuildFlag::OneHopTunnel)
+}
+
+impl StreamAttacher {
+    pub fn new(rules: Vec<AttachRule>, timeout: Duration) -> Self {
+        Self {
+            rules,
+            timeout,
+            pending: HashMap::new(),
+            exits: HashMap::new(),
+        }
+    }
+
+    pub fn rules(&self) -> &[AttachRule] {
+        &self.rules
+    }
+
+    /// Replaces the rules, streams already handled are left alone.
+    pub fn set_rules(&mut self, rules: Vec<AttachRule>) {
+        self.rules = rules;
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Follows `CIRC` and `STREAM` events, returns what to do with the streams.
+    ///
+    /// `exit_country` gives the country code of the exit relays.
+    pub fn handle_event<F>(&mut self, event: &Event, exit_country: F) -> Vec<AttachCommand>
+    where
+        F: FnOnce(&RelayFingerprint) -> Option<&'static str>,
+    {
+        match event {
+            Event::Circuit(circuit) => self.update_circuit(circuit, exit_country),
+            Event::Stream(stream) => self.update_stream(stream).into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn update_circuit<F>(&mut self, circuit: &Circuit, exit_country: F) -> Vec<AttachCommand>
+    where
+        F: FnOnce(&RelayFingerprint) -> Option<&'static str>,
+    {
+        let waiting = self
+            .pending
+            .iter()
+            .filter(|(_, p)| p.circuit.as_ref() == Some(&circuit.id))
+            .map(|(id, _)| id.clone());
+        let commands = match circuit.status {
+            CircuitStatus::Built => waiting
+                .map(|stream| AttachCommand::Attach {
+                    stream,
+                    circuit: circuit.id.clone(),
+                })
+                .collect(),
+            CircuitStatus::Closed | CircuitStatus::Failed => waiting
+                .map(|stream| AttachCommand::let_tor_decide(&stream))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for command in &commands {
+            if let AttachCommand::Attach { stream, .. } = command {
+                if let Some(pending) = self.pending.get_mut(stream) {
+                    pending.circuit = None;
+                }
+            }
+        }
+
+        match circuit.status {
+            CircuitStatus::Built if carries_streams(circuit) => {
+                let country = circuit
+                    .path
+                    .last()
+                    .and_then(|s| exit_country(&s.fingerprint));
+                self.exits.insert(circuit.id.clone(), country);
+            }
+            CircuitStatus::Closed | CircuitStatus::Failed => {
+                self.exits.remove(&circuit.id);
+            }
+            _ => {}
+        }
+        commands
+    }
+
+    fn update_stream(&mut self, stream: &Stream) -> Option<AttachCommand> {
+        if !stream.status.is_pending() {
+            self.pending.remove(&stream.id);
+            return None;
+        }
+        let now = Time::now();
+        let pending = self
+            .pending
+            .entry(stream.id.clone())
+            .or_insert_with(|| PendingStream {
+                since: now,
+                handled: false,
+                circuit: None,
+            });
+        // Detached streams are to be attached again
+        if pending.handled && stream.status != StreamStatus::Detached {
+            return None;
+        }
+        pending.circuit = None;
+
+        let rule = self.rules.iter().find(|r| r.matches(stream))?;
+        let command = match rule.action {
+            AttachAction::Tor => AttachCommand::let_tor_decide(&stream.id),
+            AttachAction::Country(ref countries) => {
+                let circuit = self
+                    .exits
+                    .iter()
+                    .filter(|(_, cc)| {
+                        cc.is_some_and(|cc| countries.iter().any(|c| c.eq_ignore_ascii_case(cc)))
+                    })
+                    .map(|(id, _)| id)
+                    .min_by_key(|id| id.0.parse::<u64>().unwrap_or(u64::MAX));
+                match circuit {
+                    Some(circuit) => AttachCommand::Attach {
+                        stream: stream.id.clone(),
+                        circuit: circuit.clone(),
+                    },
+                    None => AttachCommand::Build {
+                        stream: stream.id.clone(),
+                        exit: Query(vec![Term {
+                            negated: false,
+                            predicate: Predicate::Country(countries.clone()),
+                        }]),
+                        target: stream.target.clone(),
+                    },
+                }
+            }
+            AttachAction::Build(ref query) => AttachCommand::Build {
+                stream: stream.id.clone(),
+                exit: query.clone(),
+                target: stream.target.clone(),
+            },
+        };
+        let pending = self.pending.get_mut(&stream.id).unwrap();
+        pending.handled = true;
+        Some(command)
+    }
+
+    /// Replaces the known circuits with `circuits`, as from
+    /// [`TorController::get_circuits`], missed when events were not followed.
+    ///
+    /// [`TorController::get_circuits`]: crate::TorController::get_circuits
+    pub fn set_circuits<F>(&mut self, circuits: &[Circuit], mut exit_country: F)
+    where
+        F: FnMut(&RelayFingerprint) -> Option<&'static str>,
+    {
+        self.exits.clear();
+        for circuit in circuits {
+            self.update_circuit(circuit, &mut exit_country);
+        }
+    }
+
+    /// Records that a command for `stream` failed, so that tor decides once
+    /// the timeout expires.
+    pub fn attach_failed(&mut self, stream: &StreamID) {
+        if let Some(pending) = self.pending.get_mut(stream) {
+            pending.handled = false;
+            pending.circuit = None;
+        }
+    }
+
+    /// Records the circuit launched for a [`AttachCommand::Build`].
+    pub fn circuit_launched(&mut self, stream: &StreamID, circuit: CircuitID) {
+        if let Some(pending) = self.pending.get_mut(stream) {
+            pending.circuit = Some(circuit);
+        }
+    }
+
+    /// Lets tor decide for the streams pending for longer than the timeout.
+    pub fn poll(&mut self, now: Time) -> Vec<AttachCommand> {
+        let mut commands = Vec::new();
+        for (id, pending) in self.pending.iter_mut() {
+            let expired = pending
+                .since
+                .checked_add(self.timeout)
+                .is_some_and(|deadline| deadline <= now);
+            if expired && (!pending.handled || pending.circuit.is_some()) {
+                pending.handled = true;
+                pending.circuit = None;
+                commands.push(AttachCommand::let_tor_decide(id));
+            }
+        }
+        commands
+    }
+
+    /// Number of streams waiting for a circuit.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+fn pick<'a>(relays: &[&'a Relay]) -> Option<&'a Relay> {
+    relays
+        .choose_weighted(&mut rand::thread_rng(), |r| {
+            r.or.bandwidth.unwrap_or_default().max(1)
+        })
+        .ok()
+        .copied()
+}
+
+/// Picks a path for `EXTENDCIRCUIT` to `target`: one of `guards`, then a
+/// middle and an exit relay matching `exit` weighted by bandwidth, `None` when
+/// no exit policy allows `target`.
+///
+/// The guards are to be tor's usable entry guards, picking other ones makes
+/// some attacks easier. `exit_policy` looks up the policy of the candidate
+/// exits, those without one are skipped.
+pub fn pick_path<F>(
+    index: &RelayIndex,
+    guards: &[RelayFingerprint],
+    exit: &Query,
+    target: &Target,
+    mut exit_policy: F,
+) -> Option<Vec<RelayFingerprint>>
+where
+    F: FnMut(&RelayFingerprint) -> Option<ExitPolicy>,
+{
+    use OnionRouterFlag::*;
+
+    let guard = guards.choose(&mut rand::thread_rng())?;
+    let exits: Vec<&Relay> = index
+        .with_flags([Exit, Running, Valid].into_iter().collect())
+        .into_iter()
+        .filter(|r| !r.or.flags.is_set(BadExit) && r.identity() != guard && exit.matches(r))
+        .filter(|r| exit_policy(r.identity()).is_some_and(|policy| policy.allows(target)))
+        .collect();
+    let exit = pick(&exits)?;
+
+    let same_network = |r: &Relay| match (r.ip(), exit.ip()) {
+        (Some(IpAddr::V4(a)), Some(IpAddr::V4(b))) => a.octets()[..2] == b.octets()[..2],
+        _ => false,
+    };
+    let middles: Vec<&Relay> = index
+        .with_flags([Fast, Running, Valid].into_iter().collect())
+        .into_iter()
+        .filter(|r| r.identity() != guard && r.identity() != exit.identity() && !same_network(r))
+        .collect();
+    let middle = pick(&middles)?;
+
+    Some(vec![*guard, *middle.identity(), *exit.identity()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(data: &str) -> Event {
+        Event::from_raw("STREAM", &format!("{data}\r\n")).unwrap()
+    }
+
+    fn circuit(data: &str) -> Event {
+        Event::from_raw("CIRC", &format!("{data}\r\n")).unwrap()
+    }
+
+    #[test]
+    fn attach_rules() {
+        let rule: AttachRule = "host:*.example.com port:443 user:alice -> country de,fr"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rule.to_string(),
+            "host:*.example.com port:443 user:alice -> country DE,FR"
+        );
+        let rule: AttachRule = "source:10.0.0.0/8 port:80-8080 -> build flag:Fast"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.matchers[1], Matcher::Port(80, 8080));
+        assert!(" -> tor".parse::<AttachRule>().unwrap().matchers.is_empty());
+        assert!("port:443 -> somewhere".parse::<AttachRule>().is_err());
+
+        let rules = vec![
+            "host:*.example.com -> country DE".parse().unwrap(),
+            "port:22 -> build".parse().unwrap(),
+            "host:*.onion -> tor".parse().unwrap(),
+        ];
+        let mut attacher = StreamAttacher::new(rules, Duration::from_secs(10));
+        let exit = |_: &RelayFingerprint| Some("DE");
+
+        let commands = attacher.handle_event(&stream("1 NEW 0 www.example.com:443"), exit);
+        assert!(matches!(commands[..], [AttachCommand::Build { .. }]));
+        attacher.circuit_launched(&StreamID("1".into()), CircuitID("12".into()));
+        let commands = attacher.handle_event(
+            &circuit("12 BUILT $8737307DE84C2621E6399E99123967A9590297F2~Tor0x800 PURPOSE=GENERAL"),
+            exit,
+        );
+        assert_eq!(
+            commands,
+            [AttachCommand::Attach {
+                stream: StreamID("1".into()),
+                circuit: CircuitID("12".into()),
+            }]
+        );
+
+        // The circuit exiting in Germany is reused
+        let commands = attacher.handle_event(&stream("2 NEW 0 example.com:80"), exit);
+        assert_eq!(
+            commands,
+            [AttachCommand::Attach {
+                stream: StreamID("2".into()),
+                circuit: CircuitID("12".into()),
+            }]
+        );
+
+        let commands = attacher.handle_event(&stream("3 NEW 0 duckduckgo.com:443"), exit);
+        assert!(commands.is_empty());
+        assert_eq!(attacher.pending(), 3);
+        attacher.handle_event(&stream("1 SUCCEEDED 12 www.example.com:443"), exit);
+        assert_eq!(attacher.pending(), 2);
+
+        let later = Time::now() + Duration::from_secs(11);
+        let commands = attacher.poll(later);
+        assert_eq!(
+            commands,
+            [AttachCommand::Attach {
+                stream: StreamID("3".into()),
+                circuit: CircuitID("0".into()),
+            }]
+        );
+        assert!(attacher.poll(later).is_empty());
+
+        // A stream whose attachment failed is left to tor after the timeout
+        attacher.attach_failed(&StreamID("2".into()));
+        assert_eq!(
+            attacher.poll(later),
+            [AttachCommand::Attach {
+                stream: StreamID("2".into()),
+                circuit: CircuitID("0".into()),
+            }]
+        );
+    }
+
+    #[test]
+    fn path_to_target() {
+        use crate::tor::ns::OnionRouter;
+        use std::net::Ipv4Addr;
+        use OnionRouterFlag::*;
+
+        let relay = |n: u8, flags: &[OnionRouterFlag]| OnionRouter {
+            nickname: format!("relay{n}"),
+            identity: RelayFingerprint([n; 20]),
+            digest: [0; 20],
+            publication: Time::now(),
+            target: Target {
+                addr: HostOrAddr::Addr(IpAddr::V4(Ipv4Addr::new(n, n, n, n))),
+                port: 9001,
+            },
+            directory_port: None,
+            advertise_ipv6: None,
+            flags: flags.iter().copied().collect(),
+            bandwidth: Some(1000),
+        };
+        let index = RelayIndex::new(
+            vec![
+                relay(1, &[Guard, Fast, Running, Valid]),
+                relay(2, &[Fast, Running, Valid]),
+                relay(3, &[Exit, Fast, Running, Valid]),
+                relay(4, &[Exit, Running, Valid]),
+            ],
+            None,
+        );
+        let exit_policy = |fingerprint: &RelayFingerprint| {
+            let summary = match fingerprint.0[0] {
+                3 => "accept 443",
+                4 => "accept 22",
+                _ => return None,
+            };
+            Some(ExitPolicy {
+                ipv4: summary.parse().ok(),
+                ipv6: None,
+            })
+        };
+        let guards = [RelayFingerprint([1; 20])];
+        let any = Query(Vec::new());
+        let target = |s: &str| s.parse::<Target>().unwrap();
+
+        let path = pick_path(
+            &index,
+            &guards,
+            &any,
+            &target("example.com:443"),
+            exit_policy,
+        );
+        assert_eq!(
+            path,
+            Some(vec![
+                RelayFingerprint([1; 20]),
+                RelayFingerprint([2; 20]),
+                RelayFingerprint([3; 20]),
+            ])
+        );
+        let path = pick_path(
+            &index,
+            &guards,
+            &any,
+            &target("example.com:22"),
+            exit_policy,
+        );
+        assert_eq!(path.unwrap()[2], RelayFingerprint([4; 20]));
+        assert!(pick_path(
+            &index,
+            &guards,
+            &any,
+            &target("example.com:80"),
+            exit_policy
+        )
+        .is_none());
+        assert!(pick_path(&index, &[], &any, &target("example.com:443"), exit_policy).is_none());
+    }
+
+    #[test]
+    fn known_circuits() {
+        let rules = vec!["port:443 -> country FR".parse().unwrap()];
+        let mut attacher = StreamAttacher::new(rules, Duration::from_secs(10));
+        let Event::Circuit(built) =
+            circuit("7 BUILT $8737307DE84C2621E6399E99123967A9590297F2~Tor0x800 PURPOSE=GENERAL")
+        else {
+            unreachable!()
+        };
+        attacher.set_circuits(&[built], |_| Some("FR"));
+
+        let commands = attacher.handle_event(&stream("1 NEW 0 example.com:443"), |_| None);
+        assert_eq!(
+            commands,
+            [AttachCommand::Attach {
+                stream: StreamID("1".into()),
+                circuit: CircuitID("7".into()),
+            }]
+        );
+    }
+}
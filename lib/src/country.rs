@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::sync::atomic::{AtomicPtr, Ordering};
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Country {
     pub name: &'static str,
     pub flag: &'static str,
@@ -3245,3 +3246,11 @@ pub fn get_country(name: &str) -> Option<&'static Country> {
 
     countries.get(name)
 }
+
+/// Returns the static country code equal to `code`, if it is known.
+pub fn get_country_code(code: &str) -> Option<&'static str> {
+    init_countries();
+    let countries = unsafe { &*COUNTRIES.load(Ordering::Acquire) };
+
+    countries.get_key_value(code).map(|(code, _)| *code)
+}
@@ -13,6 +13,7 @@ pub enum Error {
         trace: Option<String>,
     },
     Base64(base64::DecodeError),
+    InvalidTime(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -150,6 +151,17 @@ impl fmt::Display for Error {
                 Ok(())
             }
             Self::Base64(be) => write!(f, "Base64: {be}"),
+            Self::InvalidTime(ref reason) => write!(f, "Invalid time: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(ref io) => Some(io),
+            Self::Base64(ref be) => Some(be),
+            _ => None,
         }
     }
 }
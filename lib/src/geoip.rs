@@ -26,9 +26,15 @@ fn get_ipv4_mapped(ip6: &Ipv6Addr) -> Option<Ipv4Addr> {
     }
 }
 
+extern "C" {
+    fn free(ptr: *mut ffi::c_void);
+}
+
 pub struct GeoIP {
     ip4: NonNull<bindings::GeoIP>,
     ip6: NonNull<bindings::GeoIP>,
+    asn4: Option<NonNull<bindings::GeoIP>>,
+    asn6: Option<NonNull<bindings::GeoIP>>,
 }
 
 impl Default for GeoIP {
@@ -39,8 +45,23 @@ impl Default for GeoIP {
 
 impl GeoIP {
     pub fn new() -> Self {
-        Self::open("/usr/share/GeoIP/GeoIP.dat", "/usr/share/GeoIP/GeoIPv6.dat")
-            .expect("No GeoIP dat file?!")
+        Self::open_default().expect("No GeoIP dat file?!")
+    }
+
+    /// Opens the databases installed in `/usr/share/GeoIP`, AS ones included
+    /// when present.
+    pub fn open_default() -> io::Result<Self> {
+        let mut gi = Self::open("/usr/share/GeoIP/GeoIP.dat", "/usr/share/GeoIP/GeoIPv6.dat")?;
+        // AS databases are optional, most distributions ship them in a separate package
+        if std::path::Path::new("/usr/share/GeoIP/GeoIPASNum.dat").exists() {
+            if let Err(e) = gi.open_asn(
+                "/usr/share/GeoIP/GeoIPASNum.dat",
+                "/usr/share/GeoIP/GeoIPASNumv6.dat",
+            ) {
+                log::warn!("Could not open GeoIP AS database: {}", e);
+            }
+        }
+        Ok(gi)
     }
 
     fn geoip_open<P: AsRef<str>>(path: P) -> io::Result<NonNull<bindings::GeoIP>> {
@@ -59,7 +80,31 @@ impl GeoIP {
         let ip4 = Self::geoip_open(ip4_path)?;
         let ip6 = Self::geoip_open(ip6_path)?;
 
-        Ok(Self { ip4, ip6 })
+        Ok(Self {
+            ip4,
+            ip6,
+            asn4: None,
+            asn6: None,
+        })
+    }
+
+    /// Opens the AS number databases used by [`GeoIP::lookup_asn`]. The IPv6 one is optional.
+    pub fn open_asn<P: AsRef<str>, Q: AsRef<str>>(
+        &mut self,
+        asn4_path: P,
+        asn6_path: Q,
+    ) -> io::Result<()> {
+        let asn4 = Self::geoip_open(asn4_path)?;
+        if let Some(old) = self.asn4.replace(asn4) {
+            unsafe { bindings::GeoIP_delete(old.as_ptr()) }
+        }
+        if std::path::Path::new(asn6_path.as_ref()).exists() {
+            let asn6 = Self::geoip_open(asn6_path)?;
+            if let Some(old) = self.asn6.replace(asn6) {
+                unsafe { bindings::GeoIP_delete(old.as_ptr()) }
+            }
+        }
+        Ok(())
     }
 
     pub fn lookup_str<S: AsRef<str>>(&self, ip: S) -> Option<&'static str> {
@@ -105,11 +150,53 @@ impl GeoIP {
         };
         Self::country_ptr_to_option(res)
     }
+
+    /// Returns the AS of `ip` as `(number, organization)`, if an AS database was opened.
+    pub fn lookup_asn<I: Into<IpAddr>>(&self, ip: I) -> Option<(u32, String)> {
+        let ptr = match ip.into() {
+            IpAddr::V4(ip4) => {
+                let num = u32::from_be_bytes(ip4.octets());
+                let asn4 = self.asn4?;
+                unsafe { bindings::GeoIP_name_by_ipnum(asn4.as_ptr(), num as u64) }
+            }
+            IpAddr::V6(ip6) => {
+                if let Some(ip4) = get_ipv4_mapped(&ip6) {
+                    return self.lookup_asn(IpAddr::V4(ip4));
+                }
+                let asn6 = self.asn6?;
+                let bytes = ip6.octets();
+                let addr = bindings::in6_addr {
+                    __in6_u: bindings::in6_addr__bindgen_ty_1 { __u6_addr8: bytes },
+                };
+                unsafe { bindings::GeoIP_name_by_ipnum_v6(asn6.as_ptr(), addr) }
+            }
+        };
+        if ptr.is_null() {
+            return None;
+        }
+
+        // SAFETY: ptr is not null and was allocated by GeoIP for us
+        let name = unsafe { ffi::CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { free(ptr as *mut ffi::c_void) };
+
+        // Names look like "AS24940 Hetzner Online GmbH"
+        let name = name.strip_prefix("AS")?;
+        let (number, organization) = name.split_once(' ').unwrap_or((name, ""));
+        Some((number.parse().ok()?, organization.into()))
+    }
 }
 
 impl Drop for GeoIP {
     fn drop(&mut self) {
         unsafe { bindings::GeoIP_delete(self.ip4.as_ptr()) }
         unsafe { bindings::GeoIP_delete(self.ip6.as_ptr()) }
+        if let Some(asn4) = self.asn4.take() {
+            unsafe { bindings::GeoIP_delete(asn4.as_ptr()) }
+        }
+        if let Some(asn6) = self.asn6.take() {
+            unsafe { bindings::GeoIP_delete(asn6.as_ptr()) }
+        }
     }
 }
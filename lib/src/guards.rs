@@ -0,0 +1,167 @@
+//! Entry guards, joined with the consensus and GeoIP, and kept up to date
+//! with `GUARD` events.
+
+use crate::index::Relay;
+use crate::tor::common::Time;
+use crate::tor::event::Event;
+use crate::tor::guard::{EntryGuard, GuardEventStatus, GuardStatus};
+use crate::tor::identity::RelayFingerprint;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Guard {
+    pub entry: EntryGuard,
+
+    /// `None` when the guard is not in the consensus
+    pub relay: Option<Relay>,
+
+    /// Last `GUARD` event received about it
+    pub last_event: Option<(Time, GuardEventStatus)>,
+}
+
+impl Guard {
+    pub fn new(entry: EntryGuard, relay: Option<Relay>) -> Self {
+        Self {
+            entry,
+            relay,
+            last_event: None,
+        }
+    }
+
+    pub fn fingerprint(&self) -> &RelayFingerprint {
+        &self.entry.relay.fingerprint
+    }
+
+    pub fn nickname(&self) -> Option<&str> {
+        match self.relay {
+            Some(ref relay) => Some(relay.or.nickname.as_str()),
+            None => self.entry.relay.nickname.as_deref(),
+        }
+    }
+}
+
+/// Entry guards in tor's order, the first ones being preferred.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GuardSet {
+    guards: Vec<Guard>,
+}
+
+impl GuardSet {
+    pub fn new(guards: Vec<Guard>) -> Self {
+        Self { guards }
+    }
+
+    /// Records `GUARD` events, returns the fingerprint of the guard changed.
+    ///
+    /// New guards have no relay information, `lookup` is called to find it.
+    pub fn handle_event<F>(&mut self, event: &Event, lookup: F) -> Option<RelayFingerprint>
+    where
+        F: FnOnce(&RelayFingerprint) -> Option<Relay>,
+    {
+        let Event::Guard(event) = event else {
+            return None;
+        };
+        let fingerprint = event.relay.fingerprint;
+        let index = self
+            .guards
+            .iter()
+            .position(|g| *g.fingerprint() == fingerprint);
+        let status = match event.status {
+            GuardEventStatus::Dropped => {
+                if let Some(index) = index {
+                    self.guards.remove(index);
+                }
+                return Some(fingerprint);
+            }
+            GuardEventStatus::New => Some(GuardStatus::NeverConnected),
+            GuardEventStatus::Up => Some(GuardStatus::Up),
+            GuardEventStatus::Down => Some(GuardStatus::Down),
+            GuardEventStatus::Bad => Some(GuardStatus::Unusable),
+            // Back to the status it had before being bad, which tor does not tell
+            GuardEventStatus::Good => None,
+        };
+        let guard = match index {
+            Some(index) => &mut self.guards[index],
+            None => {
+                let entry = EntryGuard {
+                    relay: event.relay.clone(),
+                    status: status.unwrap_or(GuardStatus::NeverConnected),
+                    since: None,
+                };
+                self.guards.push(Guard::new(entry, lookup(&fingerprint)));
+                self.guards.last_mut().unwrap()
+            }
+        };
+        let now = Time::now();
+        if let Some(status) = status {
+            if guard.entry.status != status {
+                guard.entry.since = match status {
+                    GuardStatus::Down | GuardStatus::Unusable => Some(now),
+                    _ => None,
+                };
+            }
+            guard.entry.status = status;
+        }
+        guard.last_event = Some((now, event.status));
+        Some(fingerprint)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Guard> {
+        self.guards.iter()
+    }
+
+    pub fn get(&self, fingerprint: &RelayFingerprint) -> Option<&Guard> {
+        self.guards.iter().find(|g| g.fingerprint() == fingerprint)
+    }
+
+    pub fn len(&self) -> usize {
+        self.guards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.guards.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guard_set() {
+        let entry: EntryGuard = "$8737307DE84C2621E6399E99123967A9590297F2~Tor0x800 up"
+            .parse()
+            .unwrap();
+        let mut guards = GuardSet::new(vec![Guard::new(entry, None)]);
+        let fingerprint = *guards.iter().next().unwrap().fingerprint();
+
+        let down = Event::from_raw(
+            "GUARD",
+            "ENTRY $8737307DE84C2621E6399E99123967A9590297F2~Tor0x800 DOWN",
+        )
+        .unwrap();
+        assert_eq!(guards.handle_event(&down, |_| None), Some(fingerprint));
+        let guard = guards.get(&fingerprint).unwrap();
+        assert_eq!(guard.entry.status, GuardStatus::Down);
+        assert!(guard.entry.since.is_some());
+        assert_eq!(guard.nickname(), Some("Tor0x800"));
+
+        let new = Event::from_raw(
+            "GUARD",
+            "ENTRY $243996E46218666C1CADDE17B430EA7F95124F96~GoofyRooster NEW",
+        )
+        .unwrap();
+        guards.handle_event(&new, |_| None);
+        assert_eq!(guards.len(), 2);
+
+        let dropped = Event::from_raw(
+            "GUARD",
+            "ENTRY $8737307DE84C2621E6399E99123967A9590297F2~Tor0x800 DROPPED",
+        )
+        .unwrap();
+        guards.handle_event(&dropped, |_| None);
+        assert_eq!(guards.len(), 1);
+        assert!(guards.get(&fingerprint).is_none());
+    }
+}
@@ -0,0 +1,403 @@
+//! Onion service descriptor fetches, rebuilt per service from `HS_DESC`,
+//! `HS_DESC_CONTENT` and `CIRC` events.
+//!
+//! Circuits are tied to a service by their `REND_QUERY`, or for the HSDir
+//! circuits missing it, by their last hop being a HSDir still being queried.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+
+use crate::tor::circuit::{Circuit, CircuitPurpose, CircuitStatus, HsAddress, HsState, Step};
+use crate::tor::common::{CircuitID, Time};
+use crate::tor::event::Event;
+use crate::tor::hsdesc::{HsDesc, HsDescAction, HsDescContent, HsDescReason};
+
+/// Something that happened to a service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(tag = "kind", rename_all = "snake_case")
+)]
+pub enum TimelineEntry {
+    Descriptor(HsDesc),
+    Content {
+        hs_dir: Option<Step>,
+
+        /// Size of the descriptor, 0 when not found
+        length: usize,
+    },
+    Circuit {
+        id: CircuitID,
+        status: CircuitStatus,
+        purpose: Option<CircuitPurpose>,
+        hs_state: Option<HsState>,
+
+        /// Last hop, the HSDir for a descriptor fetch
+        last_hop: Option<Step>,
+    },
+}
+
+impl TimelineEntry {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Descriptor(_) => "descriptor",
+            Self::Content { .. } => "content",
+            Self::Circuit { .. } => "circuit",
+        }
+    }
+
+    /// Relay involved: the HSDir or the last hop of the circuit.
+    pub fn relay(&self) -> Option<&Step> {
+        match self {
+            Self::Descriptor(desc) => desc.hs_dir.as_ref(),
+            Self::Content { hs_dir, .. } => hs_dir.as_ref(),
+            Self::Circuit { last_hop, .. } => last_hop.as_ref(),
+        }
+    }
+}
+
+impl fmt::Display for TimelineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Descriptor(desc) => {
+                write!(f, "{}", desc.action)?;
+                if let Some(reason) = desc.reason {
+                    write!(f, " {reason}")?;
+                }
+                Ok(())
+            }
+            Self::Content { length: 0, .. } => f.write_str("no descriptor"),
+            Self::Content { length, .. } => write!(f, "descriptor of {length} bytes"),
+            Self::Circuit {
+                id,
+                status,
+                purpose,
+                hs_state,
+                ..
+            } => {
+                write!(f, "circuit {id} {status}")?;
+                if let Some(purpose) = purpose {
+                    write!(f, " {purpose}")?;
+                }
+                if let Some(hs_state) = hs_state {
+                    write!(f, " {hs_state}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TimelineEvent {
+    pub time: Time,
+    pub entry: TimelineEntry,
+}
+
+/// A descriptor request to a HSDir, and its answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HsDirQuery {
+    pub hs_dir: Step,
+    pub requested: Time,
+    pub answered: Option<Time>,
+
+    /// `RECEIVED`, `IGNORE` or `FAILED`
+    pub outcome: Option<HsDescAction>,
+    pub reason: Option<HsDescReason>,
+}
+
+impl HsDirQuery {
+    pub fn duration(&self) -> Option<Duration> {
+        self.answered?.duration_since(&self.requested)
+    }
+}
+
+/// Everything seen about a service, oldest first.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ServiceTimeline {
+    pub address: HsAddress,
+    events: VecDeque<TimelineEvent>,
+    queries: Vec<HsDirQuery>,
+
+    /// Set by a `FAILED` event without HSDir, when none could be queried
+    no_hs_dir: bool,
+}
+
+impl ServiceTimeline {
+    fn new(address: HsAddress) -> Self {
+        Self {
+            address,
+            events: VecDeque::new(),
+            queries: Vec::new(),
+            no_hs_dir: false,
+        }
+    }
+
+    pub fn events(&self) -> impl DoubleEndedIterator<Item = &TimelineEvent> {
+        self.events.iter()
+    }
+
+    /// The last HSDirs queried, in the order of the requests.
+    pub fn queries(&self) -> &[HsDirQuery] {
+        &self.queries
+    }
+
+    /// Queries still waiting for an answer.
+    pub fn pending(&self) -> impl Iterator<Item = &HsDirQuery> {
+        self.queries.iter().filter(|q| q.answered.is_none())
+    }
+
+    /// Whether a HSDir gave the descriptor.
+    pub fn has_descriptor(&self) -> bool {
+        self.queries
+            .iter()
+            .filter_map(|q| q.outcome)
+            .any(|outcome| matches!(outcome, HsDescAction::Received | HsDescAction::Ignore))
+    }
+
+    /// Whether every query was answered, or none could be made.
+    pub fn is_settled(&self) -> bool {
+        self.no_hs_dir || (!self.queries.is_empty() && self.pending().next().is_none())
+    }
+
+    fn is_querying(&self, hs_dir: &Step) -> bool {
+        self.pending()
+            .any(|q| q.hs_dir.fingerprint == hs_dir.fingerprint)
+    }
+
+    fn push(&mut self, time: Time, entry: TimelineEntry, capacity: usize) {
+        if self.events.len() == capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(TimelineEvent { time, entry });
+    }
+
+    fn update_query(&mut self, desc: &HsDesc, time: Time, capacity: usize) {
+        let Some(ref hs_dir) = desc.hs_dir else {
+            if desc.action == HsDescAction::Failed {
+                self.no_hs_dir = true;
+            }
+            return;
+        };
+        match desc.action {
+            HsDescAction::Requested => {
+                self.no_hs_dir = false;
+                if self.queries.len() == capacity {
+                    self.queries.remove(0);
+                }
+                self.queries.push(HsDirQuery {
+                    hs_dir: hs_dir.clone(),
+                    requested: time,
+                    answered: None,
+                    outcome: None,
+                    reason: None,
+                });
+            }
+            HsDescAction::Received | HsDescAction::Ignore | HsDescAction::Failed => {
+                if let Some(query) =
+                    self.queries.iter_mut().rev().find(|q| {
+                        q.answered.is_none() && q.hs_dir.fingerprint == hs_dir.fingerprint
+                    })
+                {
+                    query.answered = Some(time);
+                    query.outcome = Some(desc.action);
+                    query.reason = desc.reason;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Follows the descriptor fetches and the circuits of onion services.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HsTimeline {
+    capacity: usize,
+    services: HashMap<HsAddress, ServiceTimeline>,
+    circuits: HashMap<CircuitID, HsAddress>,
+}
+
+impl Default for HsTimeline {
+    fn default() -> Self {
+        Self::new(200)
+    }
+}
+
+impl HsTimeline {
+    /// Keeps the last `capacity` events and HSDir queries of each service.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            services: HashMap::new(),
+            circuits: HashMap::new(),
+        }
+    }
+
+    /// Records `HS_DESC`, `HS_DESC_CONTENT` and `CIRC` events, returns the
+    /// service the event was about.
+    pub fn handle_event(&mut self, event: &Event) -> Option<&HsAddress> {
+        let time = Time::now();
+        let address = match event {
+            Event::HsDesc(desc) => self.update_descriptor(desc, time)?,
+            Event::HsDescContent(content) => self.update_content(content, time)?,
+            Event::Circuit(circuit) => self.update_circuit(circuit, time)?,
+            _ => return None,
+        };
+        Some(&self.services[&address].address)
+    }
+
+    fn service(&mut self, address: &HsAddress) -> &mut ServiceTimeline {
+        self.services
+            .entry(address.clone())
+            .or_insert_with(|| ServiceTimeline::new(address.clone()))
+    }
+
+    pub fn update_descriptor(&mut self, desc: &HsDesc, time: Time) -> Option<HsAddress> {
+        let address = desc.address.clone()?;
+        let capacity = self.capacity;
+        let service = self.service(&address);
+        service.update_query(desc, time, capacity);
+        service.push(time, TimelineEntry::Descriptor(desc.clone()), capacity);
+        Some(address)
+    }
+
+    pub fn update_content(&mut self, content: &HsDescContent, time: Time) -> Option<HsAddress> {
+        let address = content.address.clone()?;
+        let capacity = self.capacity;
+        let entry = TimelineEntry::Content {
+            hs_dir: content.hs_dir.clone(),
+            length: content.descriptor.len(),
+        };
+        self.service(&address).push(time, entry, capacity);
+        Some(address)
+    }
+
+    pub fn update_circuit(&mut self, circuit: &Circuit, time: Time) -> Option<HsAddress> {
+        let last_hop = circuit.path.last().cloned();
+        let address = match circuit.rend_query {
+            Some(ref address) => address.clone(),
+            None => match self.circuits.get(&circuit.id) {
+                Some(address) => address.clone(),
+                None if circuit.purpose == Some(CircuitPurpose::HsClientHsDir) => {
+                    let hs_dir = last_hop.as_ref()?;
+                    self.services
+                        .values()
+                        .find(|s| s.is_querying(hs_dir))?
+                        .address
+                        .clone()
+                }
+                None => return None,
+            },
+        };
+        if matches!(
+            circuit.status,
+            CircuitStatus::Closed | CircuitStatus::Failed
+        ) {
+            self.circuits.remove(&circuit.id);
+        } else {
+            self.circuits.insert(circuit.id.clone(), address.clone());
+        }
+
+        let entry = TimelineEntry::Circuit {
+            id: circuit.id.clone(),
+            status: circuit.status,
+            purpose: circuit.purpose,
+            hs_state: circuit.hs_state,
+            last_hop,
+        };
+        let capacity = self.capacity;
+        self.service(&address).push(time, entry, capacity);
+        Some(address)
+    }
+
+    pub fn get(&self, address: &HsAddress) -> Option<&ServiceTimeline> {
+        self.services.get(address)
+    }
+
+    pub fn services(&self) -> impl Iterator<Item = &ServiceTimeline> {
+        self.services.values()
+    }
+
+    pub fn remove(&mut self, address: &HsAddress) -> Option<ServiceTimeline> {
+        self.circuits.retain(|_, a| a != address);
+        self.services.remove(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE_ID: &str = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad";
+    const HS_DIR: &str = "$14AE2154A26F1D42510F7D5F4E4A9E6C7B8D9E0F~hsdir";
+
+    fn event(name: &str, data: &str) -> Event {
+        Event::from_raw(name, data).unwrap()
+    }
+
+    #[test]
+    fn service_timeline() {
+        let address: HsAddress = SERVICE_ID.parse().unwrap();
+        let mut timeline = HsTimeline::default();
+
+        let requested = event(
+            "HS_DESC",
+            &format!("REQUESTED {SERVICE_ID} NO_AUTH {HS_DIR} ZkvlA2G3F2Hf5hzs4jYc7nwdJVIxj34Xv0vAuVjuFQI"),
+        );
+        assert_eq!(timeline.handle_event(&requested), Some(&address));
+        let service = timeline.get(&address).unwrap();
+        assert_eq!(service.pending().count(), 1);
+        assert!(!service.is_settled());
+
+        let circuit = event(
+            "CIRC",
+            &format!("7 BUILT $0000000000000000000000000000000000000001~guard,{HS_DIR} PURPOSE=HS_CLIENT_HSDIR\r\n"),
+        );
+        assert_eq!(timeline.handle_event(&circuit), Some(&address));
+        let other = event(
+            "CIRC",
+            "8 BUILT $0000000000000000000000000000000000000001~guard PURPOSE=GENERAL\r\n",
+        );
+        assert_eq!(timeline.handle_event(&other), None);
+
+        let failed = event(
+            "HS_DESC",
+            &format!("FAILED {SERVICE_ID} NO_AUTH {HS_DIR} ZkvlA2G3F2Hf5hzs4jYc7nwdJVIxj34Xv0vAuVjuFQI REASON=NOT_FOUND"),
+        );
+        timeline.handle_event(&failed);
+        let service = timeline.get(&address).unwrap();
+        assert!(service.is_settled());
+        assert!(!service.has_descriptor());
+        assert_eq!(service.queries()[0].reason, Some(HsDescReason::NotFound));
+        assert_eq!(
+            service.events().map(|e| e.entry.kind()).collect::<Vec<_>>(),
+            ["descriptor", "circuit", "descriptor"]
+        );
+    }
+
+    #[test]
+    fn capacity() {
+        let address: HsAddress = SERVICE_ID.parse().unwrap();
+        let mut timeline = HsTimeline::new(2);
+        for i in 1..=3 {
+            let requested = event(
+                "HS_DESC",
+                &format!("REQUESTED {SERVICE_ID} NO_AUTH $000000000000000000000000000000000000000{i}~hsdir ZkvlA2G3F2Hf5hzs4jYc7nwdJVIxj34Xv0vAuVjuFQI"),
+            );
+            timeline.handle_event(&requested);
+        }
+        let service = timeline.get(&address).unwrap();
+        assert_eq!(service.events().count(), 2);
+        assert_eq!(service.queries().len(), 2);
+        assert_eq!(
+            service.queries()[0].hs_dir.fingerprint.to_string(),
+            "0000000000000000000000000000000000000002"
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+
+use crate::country;
+use crate::geoip::GeoIP;
+use crate::query::Query;
+use crate::tor::common::{Cidr, HostOrAddr};
+use crate::tor::identity::RelayFingerprint;
+use crate::tor::ns::{OnionRouter, OnionRouterFlags};
+
+/// An onion router enriched with its GeoIP information.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Relay {
+    pub or: OnionRouter,
+    pub country: Option<&'static str>,
+    pub asn: Option<(u32, String)>,
+}
+
+impl Relay {
+    pub fn new(or: OnionRouter, gi: Option<&GeoIP>) -> Self {
+        let (country, asn) = match (gi, &or.target.addr) {
+            (Some(gi), HostOrAddr::Addr(ref addr)) => (gi.lookup_ip(*addr), gi.lookup_asn(*addr)),
+            _ => (None, None),
+        };
+        Self { or, country, asn }
+    }
+
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self.or.target.addr {
+            HostOrAddr::Addr(addr) => Some(addr),
+            HostOrAddr::Host(_) => None,
+        }
+    }
+
+    pub fn identity(&self) -> &RelayFingerprint {
+        &self.or.identity
+    }
+
+    pub fn country(&self) -> Option<&'static country::Country> {
+        self.country.and_then(country::get_country)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Relay {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        struct RelayData {
+            or: OnionRouter,
+            country: Option<String>,
+            asn: Option<(u32, String)>,
+        }
+
+        let relay = RelayData::deserialize(deserializer)?;
+        Ok(Self {
+            or: relay.or,
+            country: relay
+                .country
+                .and_then(|cc| country::get_country_code(&cc.to_uppercase())),
+            asn: relay.asn,
+        })
+    }
+}
+
+/// In-memory index over the relays of the consensus.
+#[derive(Debug, Default)]
+pub struct RelayIndex {
+    relays: Vec<Relay>,
+    by_identity: BTreeMap<RelayFingerprint, usize>,
+    by_nickname: HashMap<String, Vec<usize>>,
+    by_country: HashMap<String, Vec<usize>>,
+    by_asn: HashMap<u32, Vec<usize>>,
+}
+
+impl RelayIndex {
+    pub fn new(ors: Vec<OnionRouter>, gi: Option<&GeoIP>) -> Self {
+        let mut me = Self::default();
+        me.relays.reserve(ors.len());
+        for or in ors {
+            me.insert(Relay::new(or, gi));
+        }
+        me
+    }
+
+    pub fn insert(&mut self, relay: Relay) {
+        let identity = relay.or.identity;
+        if let Some(&idx) = self.by_identity.get(&identity) {
+            log::debug!("Replacing relay {}", identity);
+            self.relays[idx] = relay;
+            self.rebuild();
+            return;
+        }
+
+        let idx = self.relays.len();
+        self.by_identity.insert(identity, idx);
+        self.index(idx, &relay);
+        self.relays.push(relay);
+    }
+
+    fn index(&mut self, idx: usize, relay: &Relay) {
+        self.by_nickname
+            .entry(relay.or.nickname.to_lowercase())
+            .or_default()
+            .push(idx);
+        if let Some(country) = relay.country {
+            self.by_country
+                .entry(country.to_uppercase())
+                .or_default()
+                .push(idx);
+        }
+        if let Some((asn, _)) = relay.asn {
+            self.by_asn.entry(asn).or_default().push(idx);
+        }
+    }
+
+    fn rebuild(&mut self) {
+        self.by_nickname.clear();
+        self.by_country.clear();
+        self.by_asn.clear();
+        let relays = std::mem::take(&mut self.relays);
+        for (idx, relay) in relays.iter().enumerate() {
+            self.index(idx, relay);
+        }
+        self.relays = relays;
+    }
+
+    pub fn len(&self) -> usize {
+        self.relays.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.relays.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Relay> {
+        self.relays.iter()
+    }
+
+    fn collect<'a, I: IntoIterator<Item = &'a usize>>(&'a self, indexes: I) -> Vec<&'a Relay> {
+        indexes.into_iter().map(|idx| &self.relays[*idx]).collect()
+    }
+
+    pub fn get(&self, identity: &RelayFingerprint) -> Option<&Relay> {
+        self.by_identity.get(identity).map(|idx| &self.relays[*idx])
+    }
+
+    /// Relays whose hexadecimal fingerprint starts with `prefix` (an optional `$` is ignored).
+    pub fn by_fingerprint_prefix(&self, prefix: &str) -> Vec<&Relay> {
+        let prefix = prefix.strip_prefix('$').unwrap_or(prefix);
+        if prefix.len() > 40 {
+            return Vec::new();
+        }
+        let lowest = format!("{prefix:0<40}").parse::<RelayFingerprint>();
+        let highest = format!("{prefix:F<40}").parse::<RelayFingerprint>();
+        match (lowest, highest) {
+            (Ok(lowest), Ok(highest)) => {
+                self.collect(self.by_identity.range(lowest..=highest).map(|(_, idx)| idx))
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Relays named `nickname`, compared case-insensitively as tor does.
+    pub fn by_nickname(&self, nickname: &str) -> Vec<&Relay> {
+        self.collect(
+            self.by_nickname
+                .get(&nickname.to_lowercase())
+                .into_iter()
+                .flatten(),
+        )
+    }
+
+    pub fn by_addr(&self, addr: &IpAddr) -> Vec<&Relay> {
+        self.by_cidr(&Cidr::from(*addr))
+    }
+
+    pub fn by_cidr(&self, cidr: &Cidr) -> Vec<&Relay> {
+        self.relays
+            .iter()
+            .filter(|r| r.ip().map(|ip| cidr.contains(&ip)).unwrap_or(false))
+            .collect()
+    }
+
+    /// Relays located in `country` (ISO 3166 code, case-insensitive).
+    pub fn by_country(&self, country: &str) -> Vec<&Relay> {
+        self.collect(
+            self.by_country
+                .get(&country.to_uppercase())
+                .into_iter()
+                .flatten(),
+        )
+    }
+
+    pub fn by_asn(&self, asn: u32) -> Vec<&Relay> {
+        self.collect(self.by_asn.get(&asn).into_iter().flatten())
+    }
+
+    /// Relays having at least all the `flags`.
+    pub fn with_flags(&self, flags: OnionRouterFlags) -> Vec<&Relay> {
+        self.relays
+            .iter()
+            .filter(|r| r.or.flags.contains(flags))
+            .collect()
+    }
+
+    pub fn query(&self, query: &Query) -> Vec<&Relay> {
+        self.relays.iter().filter(|r| query.matches(r)).collect()
+    }
+}
+
+/// Serialized as the list of relays.
+#[cfg(feature = "serde")]
+impl serde::Serialize for RelayIndex {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.relays.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for RelayIndex {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let relays = <Vec<Relay> as serde::Deserialize>::deserialize(deserializer)?;
+        let mut index = Self::default();
+        for relay in relays {
+            index.insert(relay);
+        }
+        Ok(index)
+    }
+}
@@ -1,36 +1,100 @@
+pub mod addresses;
+pub mod attach;
 #[allow(clippy::all)]
 mod bindings;
 pub mod country;
 pub mod error;
 pub mod geoip;
+pub mod guards;
+pub mod hstimeline;
+pub mod index;
+pub mod logs;
+pub mod ownership;
+pub mod query;
 pub mod socket;
 pub mod tor;
+pub mod tracker;
+pub mod traffic;
 
 use std::fmt;
+use std::time::Duration;
 
-use error::Result;
+use error::{Error, Result};
+use geoip::GeoIP;
+use guards::Guard;
+use index::Relay;
 use socket::Socket;
-use tor::circuit::Circuit;
-use tor::common::{CircuitID, StreamID};
-use tor::conn::Connection;
+use tor::circuit::{Circuit, HsAddress};
+use tor::common::{CircuitID, HostOrAddr, StreamID, Target};
+use tor::conn::{Connection, Response};
 use tor::NomParse;
 
+use crate::tor::addrmap::{AddressMapping, MappingSource};
+use crate::tor::buildtimeout::BuildTimeout;
+use crate::tor::clientauth::ClientAuth;
+use crate::tor::event::Event;
+use crate::tor::guard::EntryGuard;
+use crate::tor::identity::{Ed25519Identity, RelayFingerprint};
 use crate::tor::ns::OnionRouter;
-use crate::tor::stream::Stream;
+use crate::tor::onion::{AddOnion, OnionService};
+use crate::tor::orconn::OrConnection;
+use crate::tor::policy::ExitPolicy;
+use crate::tor::routerset::RouterSet;
+use crate::tor::signal::Signal;
+use crate::tor::status::{Bootstrap, Status, StatusAction};
+use crate::tor::stream::{Stream, StreamReason};
+use crate::tor::utils::parse_single_key_value;
 pub mod prelude {
+    pub use crate::addresses::AddressMap;
+    pub use crate::attach::{AttachAction, AttachCommand, AttachRule, StreamAttacher};
     pub use crate::geoip::GeoIP;
+    pub use crate::guards::{Guard, GuardSet};
+    pub use crate::hstimeline::HsTimeline;
+    pub use crate::index::{Relay, RelayIndex};
+    pub use crate::logs::{LogBuffer, LogSink};
+    pub use crate::ownership::ConfigGuard;
+    pub use crate::query::Query;
     pub use crate::socket::Socket;
-    pub use crate::tor::circuit::Circuit;
-    pub use crate::tor::common::{CircuitID, HostOrAddr, StreamID, Target, Time};
+    pub use crate::tor::addrmap::{AddrMap, AddressMapping, MappingSource};
+    pub use crate::tor::bandwidth::Bandwidth;
+    pub use crate::tor::buildtimeout::BuildTimeout;
+    pub use crate::tor::circuit::{Circuit, HsAddress};
+    pub use crate::tor::clientauth::{ClientAuth, X25519PrivateKey, X25519PublicKey};
+    pub use crate::tor::common::{Cidr, CircuitID, HostOrAddr, StreamID, Target, Time};
+    pub use crate::tor::event::Event;
+    pub use crate::tor::guard::{EntryGuard, GuardEvent};
+    pub use crate::tor::hsdesc::{HsDesc, HsDescContent};
+    pub use crate::tor::identity::{Ed25519Identity, IdentityMap, RelayFingerprint};
+    pub use crate::tor::logmessage::{LogMessage, LogSeverity};
     pub use crate::tor::ns::OnionRouter;
+    pub use crate::tor::onion::{AddOnion, OnionKey, OnionService};
+    pub use crate::tor::orconn::OrConnection;
+    pub use crate::tor::policy::ExitPolicy;
+    pub use crate::tor::routerset::RouterSet;
+    pub use crate::tor::signal::Signal;
+    pub use crate::tor::status::{Bootstrap, Status};
     pub use crate::tor::stream::Stream;
     pub use crate::tor::utils::hex_encode;
     pub use crate::tor::NomParse;
+    pub use crate::tracker::CircuitTracker;
+    pub use crate::traffic::TrafficMonitor;
     pub use crate::TorController;
 }
 
+/// How often `wait_until_bootstrapped` reads the bootstrap phase.
+const BOOTSTRAP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Parses one value per line of a `GETINFO` reply, skipping the final `OK`.
+fn parse_info_lines<T: std::str::FromStr<Err = Error>>(info: &str) -> Result<Vec<T>> {
+    info.lines()
+        .filter(|line| !line.is_empty() && *line != "OK")
+        .map(str::parse)
+        .collect()
+}
+
 pub struct TorController {
     ctrl: crate::tor::conn::Connection<Socket>,
+    build_timeout: Option<BuildTimeout>,
 }
 
 impl TorController {
@@ -42,7 +106,29 @@ impl TorController {
 
         ctrl.authenticate()?;
 
-        Ok(Self { ctrl })
+        Ok(Self {
+            ctrl,
+            build_timeout: None,
+        })
+    }
+
+    /// Makes waiting for a reply or an event fail after `timeout`, `None`
+    /// waits indefinitely.
+    ///
+    /// A reply cut by the timeout leaves the connection out of sync, it is
+    /// then to be dropped.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.ctrl.get_ref().set_read_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Sends a command, any other reply than `250 OK` is an error.
+    fn command<S: AsRef<str>>(&mut self, cmd: S) -> Result<Response> {
+        let response = self.ctrl.send_command(cmd)?;
+        if response.code != 250 {
+            return Err(response.into());
+        }
+        Ok(response)
     }
 
     pub fn get_circuits(&mut self) -> Result<Vec<Circuit>> {
@@ -67,13 +153,37 @@ impl TorController {
         Ok(streams)
     }
 
-    pub fn get_onion_router<D: fmt::Display>(&mut self, hash: D) -> Result<OnionRouter> {
-        let or_str = self.ctrl.get_info(&format!("ns/id/{hash}"))?;
+    /// Connections to relays, only those being launched or connected.
+    pub fn get_or_connections(&mut self) -> Result<Vec<OrConnection>> {
+        let orconns_string = self.ctrl.get_info("orconn-status")?;
+        parse_info_lines(&orconns_string)
+    }
+
+    pub fn get_onion_router(&mut self, fingerprint: &RelayFingerprint) -> Result<OnionRouter> {
+        let or_str = self.ctrl.get_info(format!("ns/id/{fingerprint}"))?;
         let (_rest, or) = OnionRouter::parse::<nom::error::VerboseError<&str>>(or_str.as_str())?;
 
         Ok(or)
     }
 
+    /// Looks up the Ed25519 identity of a relay in its microdescriptor, if tor has one.
+    pub fn get_ed25519_identity(
+        &mut self,
+        fingerprint: &RelayFingerprint,
+    ) -> Result<Option<Ed25519Identity>> {
+        let md = self.ctrl.get_info(format!("md/id/{fingerprint}"))?;
+        md.lines()
+            .find_map(|line| line.strip_prefix("id ed25519 "))
+            .map(|ed25519| ed25519.trim().parse())
+            .transpose()
+    }
+
+    /// Reads the exit policy summaries of a relay in its microdescriptor.
+    pub fn get_exit_policy(&mut self, fingerprint: &RelayFingerprint) -> Result<ExitPolicy> {
+        let md = self.ctrl.get_info(format!("md/id/{fingerprint}"))?;
+        ExitPolicy::from_microdescriptor(&md)
+    }
+
     pub fn get_all_onion_router(&mut self) -> Result<Vec<OnionRouter>> {
         let or_str = self.ctrl.get_info("ns/all")?;
         let (_rest, ors) = nom::multi::many0(OnionRouter::parse::<nom::error::VerboseError<&str>>)(
@@ -83,28 +193,299 @@ impl TorController {
         Ok(ors)
     }
 
-    pub fn extend_circuit(&mut self, id: CircuitID, path: Vec<String>) -> Result<String> {
-        let mut path_str =
-            String::with_capacity(path.iter().map(|s| s.len()).sum::<usize>() + path.len() - 1);
+    /// Entry guards, the preferred ones first.
+    pub fn get_entry_guards(&mut self) -> Result<Vec<EntryGuard>> {
+        let guards = self.ctrl.get_info("entry-guards")?;
+        parse_info_lines(&guards)
+    }
+
+    /// Entry guards, joined with the consensus.
+    pub fn get_guards(&mut self, gi: Option<&GeoIP>) -> Result<Vec<Guard>> {
+        let entries = self.get_entry_guards()?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| {
+                let relay = self
+                    .get_onion_router(&entry.relay.fingerprint)
+                    .ok()
+                    .map(|or| Relay::new(or, gi));
+                Guard::new(entry, relay)
+            })
+            .collect())
+    }
+
+    /// Forgets every entry guard, tor picks new ones.
+    ///
+    /// New guards make some attacks easier, this is not to be done lightly.
+    pub fn drop_guards(&mut self) -> Result<()> {
+        self.command("DROPGUARDS")?;
+        Ok(())
+    }
+
+    pub fn extend_circuit(&mut self, id: CircuitID, path: &[RelayFingerprint]) -> Result<String> {
+        let mut path_str = String::with_capacity(path.len() * 42);
         let mut first = true;
         for p in path.iter() {
             if !first {
                 path_str.push(',');
             }
             first = false;
-            path_str.push_str(p.as_str());
+            path_str.push_str(&format!("${p}"));
         }
-        let response = self
-            .ctrl
-            .send_command(format!("EXTENDCIRCUIT {id} {path_str}"))?;
+        let response = self.command(format!("EXTENDCIRCUIT {id} {path_str}"))?;
         Ok(response.data)
     }
 
     pub fn attach_stream(&mut self, stream_id: StreamID, circuit_id: CircuitID) -> Result<String> {
+        let response = self.command(format!("ATTACHSTREAM {stream_id} {circuit_id}"))?;
+        Ok(response.data)
+    }
+
+    /// Closes a stream, `END` and `PRIVATE_ADDR` are sent as `MISC`.
+    pub fn close_stream(&mut self, stream_id: StreamID, reason: StreamReason) -> Result<()> {
+        let code = reason.code().unwrap_or(1);
+        self.command(format!("CLOSESTREAM {stream_id} {code}"))?;
+        Ok(())
+    }
+
+    /// Changes the target of a stream, before it is attached.
+    pub fn redirect_stream(&mut self, stream_id: StreamID, target: &Target) -> Result<()> {
+        self.command(format!(
+            "REDIRECTSTREAM {stream_id} {} {}",
+            target.addr, target.port
+        ))?;
+        Ok(())
+    }
+
+    /// Closes a circuit, or only if no stream uses it when `if_unused` is set.
+    pub fn close_circuit(&mut self, id: CircuitID, if_unused: bool) -> Result<()> {
+        let cmd = if if_unused {
+            format!("CLOSECIRCUIT {id} IfUnused")
+        } else {
+            format!("CLOSECIRCUIT {id}")
+        };
+        self.command(cmd)?;
+        Ok(())
+    }
+
+    /// Creates an onion service, it is removed with the control connection
+    /// unless `Detach` is set.
+    pub fn add_onion(&mut self, request: &AddOnion) -> Result<OnionService> {
+        let response = self.command(request.to_string())?;
+        OnionService::from_reply(&response.data)
+    }
+
+    pub fn del_onion(&mut self, service_id: &HsAddress) -> Result<()> {
+        self.command(format!("DEL_ONION {service_id}"))?;
+        Ok(())
+    }
+
+    /// Onion services created by this control connection.
+    pub fn get_onions_current(&mut self) -> Result<Vec<HsAddress>> {
+        self.get_onions("onions/current")
+    }
+
+    /// Onion services created with `Detach`, by any control connection.
+    pub fn get_onions_detached(&mut self) -> Result<Vec<HsAddress>> {
+        self.get_onions("onions/detached")
+    }
+
+    fn get_onions(&mut self, key: &str) -> Result<Vec<HsAddress>> {
+        let onions = self.ctrl.get_info(key)?;
+        parse_info_lines(&onions)
+    }
+
+    /// Fetches the descriptor of an onion service, from `servers` or from
+    /// the HSDirs tor picks.
+    ///
+    /// The outcome is only known from the `HS_DESC` and `HS_DESC_CONTENT` events.
+    pub fn hs_fetch(&mut self, service_id: &HsAddress, servers: &[RelayFingerprint]) -> Result<()> {
+        let mut cmd = format!("HSFETCH {service_id}");
+        for server in servers {
+            cmd.push_str(&format!(" SERVER=${server}"));
+        }
+        self.command(cmd)?;
+        Ok(())
+    }
+
+    /// Installs credentials for an onion service, replacing the previous ones.
+    pub fn onion_client_auth_add(&mut self, auth: &ClientAuth) -> Result<()> {
         let response = self
             .ctrl
-            .send_command(format!("ATTACHSTREAM {stream_id} {circuit_id}"))?;
-        Ok(response.data)
+            .send_command(format!("ONION_CLIENT_AUTH_ADD {auth}"))?;
+        // 251 and 252: the previous credentials were replaced
+        if !(250..=252).contains(&response.code) {
+            return Err(response.into());
+        }
+        Ok(())
+    }
+
+    /// Removes the credentials of an onion service, returns whether there were some.
+    pub fn onion_client_auth_remove(&mut self, service_id: &HsAddress) -> Result<bool> {
+        let response = self
+            .ctrl
+            .send_command(format!("ONION_CLIENT_AUTH_REMOVE {service_id}"))?;
+        match response.code {
+            250 => Ok(true),
+            251 => Ok(false),
+            _ => Err(response.into()),
+        }
+    }
+
+    /// Lists the credentials tor has, for all onion services or only `service_id`.
+    pub fn onion_client_auth_view(
+        &mut self,
+        service_id: Option<&HsAddress>,
+    ) -> Result<Vec<ClientAuth>> {
+        let cmd = match service_id {
+            Some(service_id) => format!("ONION_CLIENT_AUTH_VIEW {service_id}"),
+            None => "ONION_CLIENT_AUTH_VIEW".into(),
+        };
+        let response = self.command(cmd)?;
+        response
+            .data
+            .lines()
+            .filter_map(|line| line.strip_prefix("CLIENT "))
+            .map(str::parse)
+            .collect()
+    }
+
+    /// Maps `address` to `new_address` for every client, until tor restarts.
+    ///
+    /// Returns the address mapped, tor picks a free one when `address` is an
+    /// unspecified IP, [`Ipv4Addr::UNSPECIFIED`](std::net::Ipv4Addr::UNSPECIFIED)
+    /// or [`Ipv6Addr::UNSPECIFIED`](std::net::Ipv6Addr::UNSPECIFIED).
+    pub fn map_address(
+        &mut self,
+        address: &HostOrAddr,
+        new_address: &HostOrAddr,
+    ) -> Result<HostOrAddr> {
+        let response = self.command(format!("MAPADDRESS {address}={new_address}"))?;
+        let line = response.data.lines().next().unwrap_or_default();
+        match parse_single_key_value(line) {
+            Some((address, _)) => address.parse(),
+            None => Err(Error::Protocol(format!(
+                "Invalid MAPADDRESS reply {line:?}"
+            ))),
+        }
+    }
+
+    /// Asks tor to resolve `address`, or the name of an IP when `reverse` is set.
+    ///
+    /// The answer is only known from the `ADDRMAP` event.
+    pub fn resolve(&mut self, address: &HostOrAddr, reverse: bool) -> Result<()> {
+        let cmd = if reverse {
+            format!("RESOLVE mode=reverse {address}")
+        } else {
+            format!("RESOLVE {address}")
+        };
+        self.command(cmd)?;
+        Ok(())
+    }
+
+    pub fn get_address_mappings(&mut self, source: MappingSource) -> Result<Vec<AddressMapping>> {
+        let mappings = self.ctrl.get_info(format!("address-mappings/{source}"))?;
+        parse_info_lines(&mappings)
+    }
+
+    pub fn signal(&mut self, signal: Signal) -> Result<()> {
+        self.command(format!("SIGNAL {signal}"))?;
+        Ok(())
+    }
+
+    /// Subscribes to the asynchronous `events`, replacing the previous subscription.
+    pub fn set_events<S: AsRef<str>>(&mut self, events: &[S]) -> Result<()> {
+        let events: Vec<&dyn AsRef<str>> = events.iter().map(|e| e as &dyn AsRef<str>).collect();
+        self.ctrl.handle_async_event(&events[..])
+    }
+
+    /// Blocks until one of the subscribed events is received.
+    pub fn wait_event(&mut self) -> Result<Event> {
+        let (name, data) = self.ctrl.wait_async_event()?;
+        let event = Event::from_raw(&name, &data)?;
+        if let Event::BuildTimeoutSet(ref build_timeout) = event {
+            self.build_timeout = Some(build_timeout.clone());
+        }
+        Ok(event)
+    }
+
+    /// Current circuit build timeout parameters.
+    ///
+    /// Tor has no command to read them, they are taken from the last
+    /// `BUILDTIMEOUT_SET` event received with `wait_event`.
+    pub fn build_timeout(&self) -> Option<&BuildTimeout> {
+        self.build_timeout.as_ref()
+    }
+
+    /// Configured circuit build timeout, `None` when tor learns it.
+    pub fn get_circuit_build_timeout(&mut self) -> Result<Option<Duration>> {
+        let values = self.get_conf_values(&["LearnCircuitBuildTimeout", "CircuitBuildTimeout"])?;
+        let value = |keyword: &str| {
+            values
+                .iter()
+                .find(|(k, _)| k == keyword)
+                .and_then(|(_, v)| v.as_deref())
+        };
+        if value("LearnCircuitBuildTimeout") != Some("0") {
+            return Ok(None);
+        }
+        let timeout = value("CircuitBuildTimeout").unwrap_or_default();
+        let seconds = timeout
+            .parse()
+            .map_err(|_| Error::Protocol(format!("Invalid CircuitBuildTimeout {timeout:?}")))?;
+        Ok(Some(Duration::from_secs(seconds)))
+    }
+
+    /// Where tor is in its bootstrap.
+    pub fn get_bootstrap_phase(&mut self) -> Result<Bootstrap> {
+        let phase = self.ctrl.get_info("status/bootstrap-phase")?;
+        let (_rest, status) = Status::parse::<nom::error::VerboseError<&str>>(phase.as_str())?;
+        match status.action {
+            StatusAction::Bootstrap(bootstrap) => Ok(bootstrap),
+            action => Err(Error::Protocol(format!(
+                "Expected a bootstrap phase, received {action}"
+            ))),
+        }
+    }
+
+    /// Blocks until tor is bootstrapped, logging its progress and warnings
+    /// meanwhile, failing with [`io::ErrorKind::TimedOut`] after `timeout`.
+    ///
+    /// The bootstrap phase is polled, the event subscription is left as is.
+    ///
+    /// [`io::ErrorKind::TimedOut`]: std::io::ErrorKind::TimedOut
+    pub fn wait_until_bootstrapped(&mut self, timeout: Duration) -> Result<Bootstrap> {
+        let deadline = std::time::Instant::now() + timeout;
+        let mut last: Option<Bootstrap> = None;
+        loop {
+            let bootstrap = self.get_bootstrap_phase()?;
+            if bootstrap.is_done() {
+                return Ok(bootstrap);
+            }
+            if last.as_ref() != Some(&bootstrap) {
+                if let Some(ref warning) = bootstrap.warning {
+                    log::warn!("Bootstrap stuck at {}%: {}", bootstrap.progress, warning);
+                } else {
+                    log::info!(
+                        "Bootstrapped {}%: {}",
+                        bootstrap.progress,
+                        bootstrap.summary
+                    );
+                }
+            }
+            let now = std::time::Instant::now();
+            if now >= deadline {
+                return Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!(
+                        "tor not bootstrapped after {timeout:?}, stuck at {}%",
+                        bootstrap.progress
+                    ),
+                )));
+            }
+            std::thread::sleep(BOOTSTRAP_POLL_INTERVAL.min(deadline - now));
+            last = Some(bootstrap);
+        }
     }
 
     pub fn set_conf<D1: fmt::Display, D2: fmt::Display>(
@@ -118,7 +499,7 @@ impl TorController {
             format!("SETCONF {keyword}")
         };
 
-        self.ctrl.send_command(cmd)?;
+        self.command(cmd)?;
         Ok(())
     }
 
@@ -126,4 +507,118 @@ impl TorController {
         let response = self.ctrl.send_command(format!("GETCONF {keyword}"))?;
         Ok(response.data)
     }
+
+    /// Reads configuration values, `None` stands for an option left to its default.
+    pub fn get_conf_values<D: fmt::Display>(
+        &mut self,
+        keywords: &[D],
+    ) -> Result<Vec<(String, Option<String>)>> {
+        let mut cmd = String::from("GETCONF");
+        for keyword in keywords {
+            cmd.push_str(&format!(" {keyword}"));
+        }
+        let response = self.command(cmd)?;
+        let values = response
+            .data
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| match parse_single_key_value(line) {
+                Some((key, value)) => (key.to_owned(), Some(value.to_owned())),
+                None => (line.to_owned(), None),
+            })
+            .collect();
+        Ok(values)
+    }
+
+    /// Sets configuration values in a single `SETCONF`, `None` resetting an
+    /// option to its default. Repeated keywords set all the lines of an option.
+    pub fn set_conf_values<K: fmt::Display, V: fmt::Display>(
+        &mut self,
+        values: &[(K, Option<V>)],
+    ) -> Result<()> {
+        let mut cmd = String::from("SETCONF");
+        for (keyword, value) in values {
+            match value {
+                Some(value) => {
+                    let value = value.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+                    cmd.push_str(&format!(" {keyword}=\"{value}\""));
+                }
+                None => cmd.push_str(&format!(" {keyword}")),
+            }
+        }
+        self.command(cmd)?;
+        Ok(())
+    }
+
+    /// Makes tor exit when this connection closes, for a tor launched by the
+    /// controller.
+    pub fn take_ownership(&mut self) -> Result<()> {
+        self.command("TAKEOWNERSHIP")?;
+        Ok(())
+    }
+
+    /// Undoes [`TorController::take_ownership`], tor keeps running after this
+    /// connection is closed.
+    pub fn drop_ownership(&mut self) -> Result<()> {
+        self.command("DROPOWNERSHIP")?;
+        Ok(())
+    }
+
+    /// Reads a routerset option such as `ExcludeNodes` or `ExitNodes`.
+    pub fn get_router_set<D: fmt::Display>(&mut self, keyword: D) -> Result<RouterSet> {
+        let conf = self.get_conf(keyword)?;
+        let value = parse_single_key_value(conf.trim_end())
+            .map(|(_key, value)| value)
+            .unwrap_or_default();
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Write};
+
+    use super::*;
+    use tor::orconn::OrConnStatus;
+
+    /// Replays a canned reply, recording the commands sent.
+    struct MockSocket {
+        reply: Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl Read for MockSocket {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reply.read(buf)
+        }
+    }
+
+    impl Write for MockSocket {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn get_or_connections() {
+        let reply = "250+orconn-status=\r\n\
+            $8737307DE84C2621E6399E99123967A9590297F2~relay CONNECTED\r\n\
+            $0011BD2485AD45D984EC4159C88FC066E5E3300E~other LAUNCHED\r\n\
+            .\r\n\
+            250 OK\r\n";
+        let mut conn = Connection::new(MockSocket {
+            reply: Cursor::new(reply.into()),
+            sent: Vec::new(),
+        });
+
+        let info = conn.get_info("orconn-status").unwrap();
+        let orconns: Vec<OrConnection> = parse_info_lines(&info).unwrap();
+        assert_eq!(orconns.len(), 2);
+        assert_eq!(orconns[0].status, OrConnStatus::Connected);
+        assert_eq!(orconns[1].status, OrConnStatus::Launched);
+    }
 }
@@ -0,0 +1,164 @@
+//! Tor log messages, received as events.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::tor::common::Time;
+use crate::tor::event::Event;
+use crate::tor::logmessage::{LogMessage, LogSeverity};
+
+/// Receives the log messages as they arrive.
+pub trait LogSink {
+    fn log(&mut self, time: &Time, message: &LogMessage);
+}
+
+impl<F> LogSink for F
+where
+    F: FnMut(&Time, &LogMessage),
+{
+    fn log(&mut self, time: &Time, message: &LogMessage) {
+        self(time, message)
+    }
+}
+
+/// Forwards tor messages to the `log` crate, with the `tor` target.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogForwarder;
+
+impl LogSink for LogForwarder {
+    fn log(&mut self, _time: &Time, message: &LogMessage) {
+        let level = match message.severity {
+            LogSeverity::Debug => log::Level::Debug,
+            LogSeverity::Info | LogSeverity::Notice => log::Level::Info,
+            LogSeverity::Warn => log::Level::Warn,
+            LogSeverity::Error => log::Level::Error,
+        };
+        log::log!(target: "tor", level, "{}", message.message);
+    }
+}
+
+/// Keeps the last log messages, and hands them over to the sinks.
+pub struct LogBuffer {
+    capacity: usize,
+    messages: VecDeque<(Time, LogMessage)>,
+    sinks: Vec<Box<dyn LogSink>>,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(1000)
+    }
+}
+
+impl fmt::Debug for LogBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogBuffer")
+            .field("capacity", &self.capacity)
+            .field("messages", &self.messages)
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
+}
+
+impl LogBuffer {
+    /// Keeps the last `capacity` messages.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            messages: VecDeque::with_capacity(capacity),
+            sinks: Vec::new(),
+        }
+    }
+
+    pub fn add_sink<S: LogSink + 'static>(&mut self, sink: S) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Records log events, returns whether the event was one.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::Log(message) => {
+                self.push(Time::now(), message.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn push(&mut self, time: Time, message: LogMessage) {
+        for sink in &mut self.sinks {
+            sink.log(&time, &message);
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((time, message));
+    }
+
+    /// Messages kept, oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(Time, LogMessage)> {
+        self.messages.iter()
+    }
+
+    /// Messages kept of at least `severity`, oldest first.
+    pub fn at_least(
+        &self,
+        severity: LogSeverity,
+    ) -> impl DoubleEndedIterator<Item = &(Time, LogMessage)> {
+        self.messages
+            .iter()
+            .filter(move |(_, message)| message.severity >= severity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn ring_buffer() {
+        let mut buffer = LogBuffer::new(2);
+        let warnings = Rc::new(RefCell::new(0));
+        let counter = Rc::clone(&warnings);
+        buffer.add_sink(move |_time: &Time, message: &LogMessage| {
+            if message.severity == LogSeverity::Warn {
+                *counter.borrow_mut() += 1;
+            }
+        });
+
+        for (severity, data) in [
+            ("NOTICE", "Bootstrapped 100% (done): Done"),
+            ("WARN", "Problem bootstrapping."),
+            ("INFO", "circuit_build_failed(): Our circuit died."),
+        ] {
+            let event = Event::from_raw(severity, data).unwrap();
+            assert!(buffer.handle_event(&event));
+        }
+
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(*warnings.borrow(), 1);
+        assert_eq!(buffer.at_least(LogSeverity::Notice).count(), 1);
+        assert_eq!(
+            buffer.iter().last().unwrap().1.message,
+            "circuit_build_failed(): Our circuit died."
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Configuration changed for the time the application runs.
+//!
+//! Options such as `__LeaveStreamsUnattached` break tor for other users when
+//! left behind: with it, every new stream hangs until a controller attaches
+//! it. [`ConfigGuard`] records the original values and restores them when
+//! dropped, on `SIGINT` and `SIGTERM`, and on panics ending the process.
+//!
+//! Nothing runs when the process is killed or the connection to tor lost. Tor
+//! only reverts `__` options when restarted, unless it is owned: it then exits
+//! with the controller, which suits a tor launched by the application only.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+
+use crate::error::Result;
+use crate::TorController;
+
+struct Owned {
+    ctrl: TorController,
+
+    /// Values before the first change, `None` for options left to their default
+    original: Vec<(String, Option<String>)>,
+    owner: bool,
+}
+
+impl Owned {
+    fn restore(&mut self) -> Result<()> {
+        if !self.original.is_empty() {
+            self.ctrl.set_conf_values(&self.original)?;
+            self.original.clear();
+        }
+        if self.owner {
+            self.ctrl.drop_ownership()?;
+            self.owner = false;
+        }
+        Ok(())
+    }
+}
+
+/// Changes options on a dedicated controller connection, and puts them back.
+pub struct ConfigGuard {
+    owned: Arc<Mutex<Owned>>,
+}
+
+impl ConfigGuard {
+    /// Connects to the controller at `addr`, as [`TorController::new`].
+    pub fn new<S: AsRef<str>>(addr: S) -> Result<Self> {
+        let ctrl = TorController::new(addr)?;
+        Ok(Self {
+            owned: Arc::new(Mutex::new(Owned {
+                ctrl,
+                original: Vec::new(),
+                owner: false,
+            })),
+        })
+    }
+
+    // A panic while restoring must not prevent the next attempts
+    fn lock(&self) -> MutexGuard<'_, Owned> {
+        self.owned.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sets `keyword`, recording its value the first time it is changed.
+    pub fn set<D: fmt::Display>(&self, keyword: &str, value: Option<D>) -> Result<()> {
+        let mut owned = self.lock();
+        if !owned
+            .original
+            .iter()
+            .any(|(k, _)| k.eq_ignore_ascii_case(keyword))
+        {
+            let values = owned.ctrl.get_conf_values(&[keyword])?;
+            owned.original.extend(values);
+        }
+        owned.ctrl.set_conf(keyword, value)
+    }
+
+    /// Puts `keyword` back to its value before the first [`set`](Self::set),
+    /// it is no longer restored afterwards.
+    pub fn restore_option(&self, keyword: &str) -> Result<()> {
+        let mut owned = self.lock();
+        let (values, others): (Vec<_>, Vec<_>) = owned
+            .original
+            .drain(..)
+            .partition(|(k, _)| k.eq_ignore_ascii_case(keyword));
+        owned.original = others;
+        if values.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = owned.ctrl.set_conf_values(&values) {
+            owned.original.extend(values);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Makes tor exit when the guard's connection closes without restoring
+    /// the options, for instance when the process is killed.
+    ///
+    /// Only for a tor launched by the application, tor exits instead of
+    /// reverting the options.
+    pub fn take_ownership(&self) -> Result<()> {
+        let mut owned = self.lock();
+        owned.ctrl.take_ownership()?;
+        owned.owner = true;
+        Ok(())
+    }
+
+    /// Puts the options back and gives up the ownership, the guard can be
+    /// used again afterwards.
+    pub fn restore(&self) -> Result<()> {
+        self.lock().restore()
+    }
+
+    /// Restores the options on `SIGINT` and `SIGTERM` before terminating the
+    /// process, as the default handlers would.
+    #[cfg(unix)]
+    pub fn restore_on_signals(&self) -> Result<()> {
+        self.restore_on_signals_with(|| {})
+    }
+
+    /// As [`restore_on_signals`](Self::restore_on_signals), running `cleanup`
+    /// before terminating, for instance to reset the terminal.
+    #[cfg(unix)]
+    pub fn restore_on_signals_with<F>(&self, cleanup: F) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        use signal_hook::consts::{SIGINT, SIGTERM};
+
+        let mut signals = signal_hook::iterator::Signals::new([SIGINT, SIGTERM])?;
+        let owned = Arc::clone(&self.owned);
+        std::thread::spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                let mut owned = owned.lock().unwrap_or_else(|e| e.into_inner());
+                if let Err(e) = owned.restore() {
+                    log::warn!("Could not restore tor configuration: {}", e);
+                }
+                cleanup();
+                let _ = signal_hook::low_level::emulate_default_handler(signal);
+                std::process::exit(128 + signal);
+            }
+        });
+        Ok(())
+    }
+
+    /// Restores the options when the process is about to die of a panic,
+    /// before the previous panic hook runs: when the main thread panics, or
+    /// any thread if panics abort.
+    ///
+    /// Other threads panicking leave the options alone, the application keeps
+    /// running.
+    pub fn restore_on_panic(&self) {
+        let owned = Arc::clone(&self.owned);
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let fatal = cfg!(panic = "abort") || std::thread::current().name() == Some("main");
+            if !fatal {
+                previous(info);
+                return;
+            }
+            // The panicking thread may hold the lock
+            let owned = match owned.try_lock() {
+                Ok(owned) => Some(owned),
+                Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            };
+            match owned.map(|mut owned| owned.restore()) {
+                Some(Ok(())) => {}
+                Some(Err(e)) => log::warn!("Could not restore tor configuration: {}", e),
+                None => log::warn!("Could not restore tor configuration while panicking"),
+            }
+            previous(info);
+        }));
+    }
+}
+
+impl Drop for ConfigGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore() {
+            log::warn!("Could not restore tor configuration: {}", e);
+        }
+    }
+}
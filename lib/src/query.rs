@@ -0,0 +1,452 @@
+//! Small query language over relays.
+//!
+//! A query is a list of space separated terms which must all match, each of them optionally
+//! negated with a leading `!`:
+//!
+//! ```text
+//! flag:Guard&Fast !flag:BadExit country:de,nl bw>5000 port:443
+//! ```
+//!
+//! | term                    | matches                                              |
+//! |-------------------------|------------------------------------------------------|
+//! | `flag:A&B`              | relays having all the flags                          |
+//! | `country:cc[,cc...]`    | relays located in one of the countries               |
+//! | `nick:name`             | relays with this nickname (case-insensitive)         |
+//! | `fp:prefix`             | relays whose fingerprint starts with `prefix`        |
+//! | `addr:ip[/bits]`        | relays whose address belongs to the network          |
+//! | `as:number`             | relays in this autonomous system                     |
+//! | `bw<op>n`, `port<op>n`  | compares bandwidth/OR port with `:`, `=`, `<`, `>`... |
+//! | anything else           | substring of the nickname, fingerprint, address...   |
+
+use std::fmt;
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, tag_no_case, take_while1};
+use nom::character::complete::{digit1, hex_digit1, space0, space1};
+use nom::combinator::{all_consuming, cut, map, map_opt, opt};
+use nom::error::{context, ContextError, ParseError};
+use nom::multi::{separated_list0, separated_list1};
+use nom::sequence::{delimited, preceded, tuple};
+
+use crate::index::Relay;
+use crate::tor::common::Cidr;
+use crate::tor::ns::{OnionRouterFlag, OnionRouterFlags};
+use crate::tor::NomParse;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Comparison {
+    Lower,
+    LowerOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    pub fn compare<T: PartialOrd>(&self, lhs: T, rhs: T) -> bool {
+        match self {
+            Self::Lower => lhs < rhs,
+            Self::LowerOrEqual => lhs <= rhs,
+            Self::Equal => lhs == rhs,
+            Self::GreaterOrEqual => lhs >= rhs,
+            Self::Greater => lhs > rhs,
+        }
+    }
+}
+
+impl NomParse for Comparison {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "comparison",
+            alt((
+                map(tag("<="), |_| Self::LowerOrEqual),
+                map(tag(">="), |_| Self::GreaterOrEqual),
+                map(tag("<"), |_| Self::Lower),
+                map(tag(">"), |_| Self::Greater),
+                map(tag("="), |_| Self::Equal),
+                map(tag(":"), |_| Self::Equal),
+            )),
+        )(input)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lower => f.write_str("<"),
+            Self::LowerOrEqual => f.write_str("<="),
+            Self::Equal => f.write_str(":"),
+            Self::GreaterOrEqual => f.write_str(">="),
+            Self::Greater => f.write_str(">"),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Predicate {
+    /// Relay has all these flags
+    Flags(OnionRouterFlags),
+
+    /// Relay is located in one of these countries (upper case codes)
+    Country(Vec<String>),
+
+    /// Relay nickname, lower case
+    Nickname(String),
+
+    /// Fingerprint prefix, upper case hexadecimal
+    Fingerprint(String),
+
+    /// Relay address is in this network
+    Address(Cidr),
+
+    /// Relay is in this autonomous system
+    As(u32),
+
+    /// Compares the consensus bandwidth
+    Bandwidth(Comparison, u32),
+
+    /// Compares the OR port
+    Port(Comparison, u16),
+
+    /// Free text, lower case
+    Text(String),
+}
+
+fn flags<'a, E>(input: &'a str) -> nom::IResult<&'a str, OnionRouterFlags, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    context(
+        "flags",
+        map(
+            separated_list1(
+                tag("&"),
+                map_opt(
+                    take_while1(|c: char| c.is_ascii_alphanumeric()),
+                    |name: &str| {
+                        OnionRouterFlag::ALL
+                            .iter()
+                            .copied()
+                            .find(|flag| flag.to_string().eq_ignore_ascii_case(name))
+                    },
+                ),
+            ),
+            |flags| flags.into_iter().collect(),
+        ),
+    )(input)
+}
+
+fn not_space<'a, E>(input: &'a str) -> nom::IResult<&'a str, &'a str, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    take_while1(|c: char| !c.is_whitespace())(input)
+}
+
+impl NomParse for Predicate {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "query predicate",
+            alt((
+                map(preceded(tag_no_case("flag:"), cut(flags)), Self::Flags),
+                map(
+                    preceded(
+                        tag_no_case("country:"),
+                        cut(separated_list1(
+                            tag(","),
+                            take_while1(|c: char| c.is_ascii_alphabetic()),
+                        )),
+                    ),
+                    |countries: Vec<&str>| {
+                        Self::Country(countries.iter().map(|c| c.to_uppercase()).collect())
+                    },
+                ),
+                map(
+                    preceded(
+                        alt((tag_no_case("nickname:"), tag_no_case("nick:"))),
+                        cut(take_while1(|c: char| c.is_ascii_alphanumeric())),
+                    ),
+                    |nickname: &str| Self::Nickname(nickname.to_lowercase()),
+                ),
+                map(
+                    preceded(tag_no_case("fp:"), cut(preceded(opt(tag("$")), hex_digit1))),
+                    |fp: &str| Self::Fingerprint(fp.to_uppercase()),
+                ),
+                map(
+                    preceded(
+                        alt((tag_no_case("addr:"), tag_no_case("ip:"))),
+                        cut(Cidr::parse),
+                    ),
+                    Self::Address,
+                ),
+                map(
+                    preceded(
+                        tag_no_case("as:"),
+                        cut(preceded(
+                            opt(tag_no_case("AS")),
+                            map_opt(digit1, |s: &str| s.parse::<u32>().ok()),
+                        )),
+                    ),
+                    Self::As,
+                ),
+                map(
+                    preceded(
+                        tag_no_case("bw"),
+                        tuple((
+                            Comparison::parse,
+                            map_opt(digit1, |s: &str| s.parse::<u32>().ok()),
+                        )),
+                    ),
+                    |(cmp, bw)| Self::Bandwidth(cmp, bw),
+                ),
+                map(
+                    preceded(
+                        tag_no_case("port"),
+                        tuple((
+                            Comparison::parse,
+                            map_opt(digit1, |s: &str| s.parse::<u16>().ok()),
+                        )),
+                    ),
+                    |(cmp, port)| Self::Port(cmp, port),
+                ),
+                map(not_space, |text: &str| Self::Text(text.to_lowercase())),
+            )),
+        )(input)
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flags(flags) => {
+                f.write_str("flag:")?;
+                for (i, flag) in flags.iter().enumerate() {
+                    if i == 0 {
+                        write!(f, "{flag}")?;
+                    } else {
+                        write!(f, "&{flag}")?;
+                    }
+                }
+                Ok(())
+            }
+            Self::Country(countries) => write!(f, "country:{}", countries.join(",")),
+            Self::Nickname(nickname) => write!(f, "nick:{nickname}"),
+            Self::Fingerprint(fp) => write!(f, "fp:{fp}"),
+            Self::Address(cidr) => write!(f, "addr:{cidr}"),
+            Self::As(asn) => write!(f, "as:{asn}"),
+            Self::Bandwidth(cmp, bw) => write!(f, "bw{cmp}{bw}"),
+            Self::Port(cmp, port) => write!(f, "port{cmp}{port}"),
+            Self::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+impl Predicate {
+    pub fn matches(&self, relay: &Relay) -> bool {
+        match self {
+            Self::Flags(flags) => relay.or.flags.contains(*flags),
+            Self::Country(countries) => relay
+                .country
+                .map(|cc| countries.iter().any(|c| c.eq_ignore_ascii_case(cc)))
+                .unwrap_or(false),
+            Self::Nickname(nickname) => relay.or.nickname.eq_ignore_ascii_case(nickname),
+            Self::Fingerprint(fp) => relay.or.identity.to_string().starts_with(fp.as_str()),
+            Self::Address(cidr) => relay.ip().map(|ip| cidr.contains(&ip)).unwrap_or(false),
+            Self::As(asn) => relay.asn.as_ref().map(|a| a.0 == *asn).unwrap_or(false),
+            Self::Bandwidth(cmp, bw) => relay
+                .or
+                .bandwidth
+                .map(|b| cmp.compare(b, *bw))
+                .unwrap_or(false),
+            Self::Port(cmp, port) => cmp.compare(relay.or.target.port, *port),
+            Self::Text(text) => {
+                let country_name = relay.country().map(|c| c.name.to_lowercase());
+                relay.or.nickname.to_lowercase().contains(text.as_str())
+                    || relay
+                        .or
+                        .identity
+                        .to_string()
+                        .to_lowercase()
+                        .contains(text.as_str())
+                    || relay.or.target.to_string().contains(text.as_str())
+                    || relay
+                        .country
+                        .map(|cc| cc.eq_ignore_ascii_case(text))
+                        .unwrap_or(false)
+                    || country_name
+                        .map(|name| name.contains(text.as_str()))
+                        .unwrap_or(false)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Term {
+    pub negated: bool,
+    pub predicate: Predicate,
+}
+
+impl NomParse for Term {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        map(
+            tuple((opt(tag("!")), Predicate::parse)),
+            |(negated, predicate)| Self {
+                negated: negated.is_some(),
+                predicate,
+            },
+        )(input)
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negated {
+            f.write_str("!")?;
+        }
+        write!(f, "{}", self.predicate)
+    }
+}
+
+impl Term {
+    pub fn matches(&self, relay: &Relay) -> bool {
+        self.predicate.matches(relay) != self.negated
+    }
+}
+
+/// Conjunction of terms, an empty query matches every relay.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct Query(pub Vec<Term>);
+
+impl NomParse for Query {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        map(
+            delimited(space0, separated_list0(space1, Term::parse), space0),
+            Self,
+        )(input)
+    }
+}
+
+impl FromStr for Query {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(all_consuming(Query::parse::<nom::error::VerboseError<&str>>)(s)?.1)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Query {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Query {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, term) in self.0.iter().enumerate() {
+            if i == 0 {
+                write!(f, "{term}")?;
+            } else {
+                write!(f, " {term}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Query {
+    pub fn matches(&self, relay: &Relay) -> bool {
+        self.0.iter().all(|term| term.matches(relay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn parse_query() {
+        let mut guard_fast = OnionRouterFlags::new();
+        guard_fast
+            .set(OnionRouterFlag::Guard)
+            .set(OnionRouterFlag::Fast);
+        let mut bad_exit = OnionRouterFlags::new();
+        bad_exit.set(OnionRouterFlag::BadExit);
+
+        let query = Query(vec![
+            Term {
+                negated: false,
+                predicate: Predicate::Flags(guard_fast),
+            },
+            Term {
+                negated: true,
+                predicate: Predicate::Flags(bad_exit),
+            },
+            Term {
+                negated: false,
+                predicate: Predicate::Country(vec!["DE".into()]),
+            },
+            Term {
+                negated: false,
+                predicate: Predicate::Bandwidth(Comparison::Greater, 5000),
+            },
+            Term {
+                negated: false,
+                predicate: Predicate::Port(Comparison::Equal, 443),
+            },
+            Term {
+                negated: false,
+                predicate: Predicate::Address(
+                    Cidr::new(IpAddr::V4(Ipv4Addr::new(185, 80, 0, 0)), 16).unwrap(),
+                ),
+            },
+        ]);
+        assert_eq!(
+            "flag:Guard&Fast !flag:BadExit country:de bw>5000 port:443 addr:185.80.0.0/16"
+                .parse::<Query>()
+                .unwrap(),
+            query
+        );
+        assert!("flag:Gaurd".parse::<Query>().is_err());
+        assert_eq!("".parse::<Query>().unwrap(), Query::default());
+        assert_eq!(
+            "portugal".parse::<Query>().unwrap(),
+            Query(vec![Term {
+                negated: false,
+                predicate: Predicate::Text("portugal".into()),
+            }])
+        );
+    }
+}
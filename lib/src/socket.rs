@@ -1,5 +1,6 @@
 use std::io::{self, Read, Write};
 use std::net::TcpStream;
+use std::time::Duration;
 
 #[cfg(any(
     doc,
@@ -49,6 +50,25 @@ impl Socket {
         }
         Ok(Self::Net(TcpStream::connect(s)?))
     }
+
+    /// Makes reads fail with `WouldBlock` or `TimedOut` after `timeout`,
+    /// `None` blocks indefinitely.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Net(ref tcp) => tcp.set_read_timeout(timeout),
+            #[cfg(any(
+                doc,
+                target_os = "android",
+                target_os = "dragonfly",
+                target_os = "emscripten",
+                target_os = "freebsd",
+                target_os = "linux",
+                target_os = "netbsd",
+                target_os = "openbsd",
+            ))]
+            Self::Unix(ref unix) => unix.set_read_timeout(timeout),
+        }
+    }
 }
 
 impl std::convert::From<TcpStream> for Socket {
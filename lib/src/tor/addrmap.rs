@@ -0,0 +1,318 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr};
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while, take_while1};
+use nom::character::complete::space1;
+use nom::combinator::{map, map_opt, opt};
+use nom::error::{context, ContextError, ParseError};
+use nom::sequence::{delimited, preceded, tuple};
+
+use crate::tor::common::{HostOrAddr, StreamID, Time};
+use crate::tor::NomParse;
+
+/// Mappings listed by `GETINFO address-mappings/<source>`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum MappingSource {
+    All,
+
+    /// `MapAddress` options
+    Config,
+
+    /// DNS answers
+    Cache,
+
+    /// `MAPADDRESS` commands
+    Control,
+}
+
+impl NomParse for MappingSource {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Mapping source",
+            alt((
+                map(tag("all"), |_| Self::All),
+                map(tag("config"), |_| Self::Config),
+                map(tag("cache"), |_| Self::Cache),
+                map(tag("control"), |_| Self::Control),
+            )),
+        )(s)
+    }
+}
+impl_from_str!(MappingSource);
+impl_serde_str!(MappingSource);
+
+impl fmt::Display for MappingSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => f.write_str("all"),
+            Self::Config => f.write_str("config"),
+            Self::Cache => f.write_str("cache"),
+            Self::Control => f.write_str("control"),
+        }
+    }
+}
+
+/// Resolved addresses may be IPv6 ones without brackets.
+fn address<'a, E>(s: &'a str) -> nom::IResult<&'a str, HostOrAddr, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    context(
+        "Mapped address",
+        alt((
+            map_opt(
+                take_while1(|c: char| c.is_ascii_hexdigit() || c == ':'),
+                |s: &str| {
+                    s.parse::<Ipv6Addr>()
+                        .ok()
+                        .map(|ip6| HostOrAddr::Addr(IpAddr::V6(ip6)))
+                },
+            ),
+            HostOrAddr::parse,
+        )),
+    )(s)
+}
+
+/// `"YYYY-MM-DD HH:MM:SS"`, or `NEVER`.
+fn expiry<'a, E>(s: &'a str) -> nom::IResult<&'a str, Option<Time>, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    context(
+        "Mapping expiry",
+        alt((
+            map(tag("NEVER"), |_| None),
+            map(delimited(tag("\""), Time::parse, tag("\"")), Some),
+        )),
+    )(s)
+}
+
+struct IsoTime<'a>(Option<&'a Time>);
+
+impl fmt::Display for IsoTime<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(t) => write!(
+                f,
+                "\"{:04}-{:02}-{:02} {:02}:{:02}:{:02}\"",
+                t.year(),
+                t.month(),
+                t.day(),
+                t.hour(),
+                t.minute(),
+                t.second()
+            ),
+            None => f.write_str("NEVER"),
+        }
+    }
+}
+
+/// A line of `address-mappings/*`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddressMapping {
+    pub address: HostOrAddr,
+    pub new_address: HostOrAddr,
+
+    /// In UTC, `None` when the mapping never expires
+    pub expires: Option<Time>,
+}
+
+impl NomParse for AddressMapping {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (address, _, new_address, _, expires)) = context(
+            "Address mapping",
+            tuple((address, space1, address, space1, expiry)),
+        )(s)?;
+
+        Ok((
+            rest,
+            Self {
+                address,
+                new_address,
+                expires,
+            },
+        ))
+    }
+}
+impl_from_str!(AddressMapping);
+
+impl fmt::Display for AddressMapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.address,
+            self.new_address,
+            IsoTime(self.expires.as_ref())
+        )
+    }
+}
+
+/// `ADDRMAP` event: an address was mapped, or failed to resolve.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddrMap {
+    /// Answer of a reverse lookup, `address` being the IP
+    pub reverse: bool,
+    pub address: HostOrAddr,
+
+    /// `None` when the resolution failed
+    pub new_address: Option<HostOrAddr>,
+
+    /// In local time, `None` when the mapping never expires
+    pub expiry: Option<Time>,
+    pub error: Option<String>,
+
+    /// Same as `expiry`, in UTC
+    pub expires: Option<Time>,
+
+    /// Whether the answer is kept in the DNS cache
+    pub cached: Option<bool>,
+
+    /// Stream which asked the resolution
+    pub stream_id: Option<StreamID>,
+}
+
+impl AddrMap {
+    pub fn mapping(&self) -> Option<AddressMapping> {
+        Some(AddressMapping {
+            address: self.address.clone(),
+            new_address: self.new_address.clone()?,
+            expires: self.expires,
+        })
+    }
+}
+
+impl NomParse for AddrMap {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (reverse, address, _, _, new_address, _, expiry)) = context(
+            "Address map",
+            tuple((
+                map(opt(tag("REVERSE[")), |r| r.is_some()),
+                address,
+                opt(tag("]")),
+                space1,
+                alt((map(tag("<error>"), |_| None), map(address, Some))),
+                space1,
+                expiry,
+            )),
+        )(s)?;
+        let (rest, error) = opt(preceded(
+            tag(" error="),
+            take_while(|c: char| !c.is_ascii_whitespace()),
+        ))(rest)?;
+        let (rest, expires) = opt(preceded(
+            tag(" EXPIRES="),
+            delimited(tag("\""), Time::parse, tag("\"")),
+        ))(rest)?;
+        let (rest, cached) = opt(preceded(
+            tag(" CACHED="),
+            alt((map(tag("\"YES\""), |_| true), map(tag("\"NO\""), |_| false))),
+        ))(rest)?;
+        let (rest, stream_id) = opt(preceded(tag(" STREAMID="), StreamID::parse))(rest)?;
+
+        Ok((
+            rest,
+            Self {
+                reverse,
+                address,
+                new_address,
+                expiry,
+                error: error.map(String::from),
+                expires,
+                cached,
+                stream_id,
+            },
+        ))
+    }
+}
+impl_from_str!(AddrMap);
+
+impl fmt::Display for AddrMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.reverse {
+            write!(f, "REVERSE[{}] ", self.address)?;
+        } else {
+            write!(f, "{} ", self.address)?;
+        }
+        match self.new_address {
+            Some(ref new_address) => write!(f, "{new_address} ")?,
+            None => f.write_str("<error> ")?,
+        }
+        write!(f, "{}", IsoTime(self.expiry.as_ref()))?;
+        if let Some(ref error) = self.error {
+            write!(f, " error={error}")?;
+        }
+        if let Some(ref expires) = self.expires {
+            write!(f, " EXPIRES={}", IsoTime(Some(expires)))?;
+        }
+        if let Some(cached) = self.cached {
+            write!(f, " CACHED=\"{}\"", if cached { "YES" } else { "NO" })?;
+        }
+        if let Some(ref stream_id) = self.stream_id {
+            write!(f, " STREAMID={stream_id}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_addrmap() {
+        let input = "www.torproject.org 116.202.120.165 \"2023-04-11 15:37:42\" \
+                     EXPIRES=\"2023-04-11 13:37:42\" CACHED=\"YES\" STREAMID=12";
+        let addrmap: AddrMap = input.parse().unwrap();
+        assert_eq!(
+            addrmap.address,
+            HostOrAddr::Host("www.torproject.org".into())
+        );
+        assert_eq!(
+            addrmap.new_address,
+            Some(HostOrAddr::Addr("116.202.120.165".parse().unwrap()))
+        );
+        assert_eq!(addrmap.expires.unwrap().hour(), 13);
+        assert_eq!(addrmap.cached, Some(true));
+        assert_eq!(addrmap.stream_id, Some(StreamID("12".into())));
+        assert_eq!(addrmap.to_string(), input);
+
+        let input = "nowhere.example <error> NEVER error=yes CACHED=\"NO\"";
+        let addrmap: AddrMap = input.parse().unwrap();
+        assert_eq!(addrmap.new_address, None);
+        assert_eq!(addrmap.error.as_deref(), Some("yes"));
+        assert!(addrmap.mapping().is_none());
+        assert_eq!(addrmap.to_string(), input);
+
+        let addrmap: AddrMap = "REVERSE[116.202.120.165] www.torproject.org NEVER"
+            .parse()
+            .unwrap();
+        assert!(addrmap.reverse);
+        assert_eq!(
+            addrmap.address,
+            HostOrAddr::Addr("116.202.120.165".parse().unwrap())
+        );
+
+        let mapping: AddressMapping = "ipv6.example 2a01:4f8:fff0:4f:266:37ff:fe2c:5d19 NEVER"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            mapping.new_address,
+            HostOrAddr::Addr(IpAddr::V6(_))
+        ));
+        assert_eq!(mapping.expires, None);
+    }
+}
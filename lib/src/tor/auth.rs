@@ -10,6 +10,11 @@ use crate::tor::utils::{hex_encode, parse_hex, quoted_string};
 use crate::tor::NomParse;
 
 #[derive(Debug, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "UPPERCASE")
+)]
 pub enum AuthMethods {
     /// Null - no authentication. Just issue authenticate command to be authenticated
     Null,
@@ -43,6 +48,7 @@ impl NomParse for AuthMethods {
 }
 
 #[derive(Debug, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ProtocolInfo {
     pub auth_methods: Vec<AuthMethods>,
     pub cookie_file: Option<String>,
@@ -81,8 +87,11 @@ impl NomParse for ProtocolInfo {
 }
 
 #[derive(Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AuthChallengeResponse {
+    #[cfg_attr(feature = "serde", serde(with = "crate::tor::utils::serde_hex"))]
     pub server_hash: [u8; 32],
+    #[cfg_attr(feature = "serde", serde(with = "crate::tor::utils::serde_hex"))]
     pub server_nonce: [u8; 32],
 }
 
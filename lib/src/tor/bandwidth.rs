@@ -0,0 +1,235 @@
+use std::fmt;
+use std::str::FromStr;
+
+use nom::bytes::complete::tag;
+use nom::character::complete::{space1, u64 as parse_u64};
+use nom::combinator::opt;
+use nom::error::{context, ContextError, ParseError};
+use nom::sequence::tuple;
+
+use crate::tor::common::{CircuitID, StreamID, Time};
+use crate::tor::NomParse;
+
+/// `BW` event: bytes read and written by tor during the last second.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bandwidth {
+    pub read: u64,
+    pub written: u64,
+}
+
+impl NomParse for Bandwidth {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (read, _, written)) =
+            context("bandwidth", tuple((parse_u64, space1, parse_u64)))(input)?;
+        Ok((rest, Self { read, written }))
+    }
+}
+impl_from_str!(Bandwidth);
+
+impl fmt::Display for Bandwidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.read, self.written)
+    }
+}
+
+/// `CIRC_BW` event: bytes read and written on an origin circuit since the last event.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CircuitBandwidth {
+    pub id: CircuitID,
+    pub read: u64,
+    pub written: u64,
+    pub time: Option<Time>,
+
+    /// Relay cell payload bytes delivered to or from the application
+    pub delivered_read: Option<u64>,
+    pub delivered_written: Option<u64>,
+
+    /// Unused space of the relay cells read and written
+    pub overhead_read: Option<u64>,
+    pub overhead_written: Option<u64>,
+}
+
+fn optional_field<'a, E>(
+    name: &'static str,
+) -> impl FnMut(&'a str) -> nom::IResult<&'a str, Option<u64>, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    move |input| {
+        let (rest, field) = opt(tuple((space1, tag(name), tag("="), parse_u64)))(input)?;
+        Ok((rest, field.map(|x| x.3)))
+    }
+}
+
+impl NomParse for CircuitBandwidth {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (_, id, _, read, _, written)) = context(
+            "circuit bandwidth",
+            tuple((
+                tag("ID="),
+                CircuitID::parse,
+                tag(" READ="),
+                parse_u64,
+                tag(" WRITTEN="),
+                parse_u64,
+            )),
+        )(input)?;
+        let (rest, opt_time) = context("time", opt(tuple((tag(" TIME="), Time::parse))))(rest)?;
+        let time = opt_time.map(|x| x.1);
+        let (rest, delivered_read) = optional_field("DELIVERED_READ")(rest)?;
+        let (rest, overhead_read) = optional_field("OVERHEAD_READ")(rest)?;
+        let (rest, delivered_written) = optional_field("DELIVERED_WRITTEN")(rest)?;
+        let (rest, overhead_written) = optional_field("OVERHEAD_WRITTEN")(rest)?;
+
+        Ok((
+            rest,
+            Self {
+                id,
+                read,
+                written,
+                time,
+                delivered_read,
+                delivered_written,
+                overhead_read,
+                overhead_written,
+            },
+        ))
+    }
+}
+impl_from_str!(CircuitBandwidth);
+
+impl fmt::Display for CircuitBandwidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ID={} READ={} WRITTEN={}",
+            self.id, self.read, self.written
+        )?;
+        if let Some(ref time) = self.time {
+            write!(f, " TIME={time}")?;
+        }
+        for (name, value) in [
+            ("DELIVERED_READ", self.delivered_read),
+            ("OVERHEAD_READ", self.overhead_read),
+            ("DELIVERED_WRITTEN", self.delivered_written),
+            ("OVERHEAD_WRITTEN", self.overhead_written),
+        ] {
+            if let Some(value) = value {
+                write!(f, " {name}={value}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `STREAM_BW` event: bytes read and written on a stream since the last event.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamBandwidth {
+    pub id: StreamID,
+    pub read: u64,
+    pub written: u64,
+    pub time: Option<Time>,
+}
+
+impl NomParse for StreamBandwidth {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        // Unlike the other events, bytes written come first
+        let (rest, (id, _, written, _, read)) = context(
+            "stream bandwidth",
+            tuple((StreamID::parse, space1, parse_u64, space1, parse_u64)),
+        )(input)?;
+        let (rest, opt_time) = context("time", opt(tuple((space1, Time::parse))))(rest)?;
+        let time = opt_time.map(|x| x.1);
+
+        Ok((
+            rest,
+            Self {
+                id,
+                read,
+                written,
+                time,
+            },
+        ))
+    }
+}
+impl_from_str!(StreamBandwidth);
+
+impl fmt::Display for StreamBandwidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.id, self.written, self.read)?;
+        if let Some(ref time) = self.time {
+            write!(f, " {time}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bandwidth() {
+        assert_eq!(
+            "1024 2048".parse::<Bandwidth>().unwrap(),
+            Bandwidth {
+                read: 1024,
+                written: 2048
+            }
+        );
+        // Extended per connection type counters are ignored
+        assert_eq!(
+            "12 34 DIR=0,0 EXIT=12,34".parse::<Bandwidth>().unwrap(),
+            Bandwidth {
+                read: 12,
+                written: 34
+            }
+        );
+    }
+
+    #[test]
+    fn parse_circuit_bandwidth() {
+        let circuit_bandwidth: CircuitBandwidth = "ID=12 READ=1018 WRITTEN=509 \
+            TIME=2023-04-11T13:37:42.123456 DELIVERED_READ=996 OVERHEAD_READ=22 \
+            DELIVERED_WRITTEN=498 OVERHEAD_WRITTEN=11"
+            .parse()
+            .unwrap();
+        assert_eq!(circuit_bandwidth.id, CircuitID("12".into()));
+        assert_eq!(circuit_bandwidth.read, 1018);
+        assert_eq!(circuit_bandwidth.written, 509);
+        assert_eq!(
+            circuit_bandwidth.time,
+            Some(Time::new(2023, 4, 11, 13, 37, 42, 123456).unwrap())
+        );
+        assert_eq!(circuit_bandwidth.delivered_read, Some(996));
+        assert_eq!(circuit_bandwidth.overhead_read, Some(22));
+        assert_eq!(circuit_bandwidth.delivered_written, Some(498));
+        assert_eq!(circuit_bandwidth.overhead_written, Some(11));
+
+        let circuit_bandwidth: CircuitBandwidth = "ID=3 READ=0 WRITTEN=509".parse().unwrap();
+        assert_eq!(circuit_bandwidth.time, None);
+        assert_eq!(circuit_bandwidth.delivered_read, None);
+    }
+
+    #[test]
+    fn parse_stream_bandwidth() {
+        let stream_bandwidth: StreamBandwidth =
+            "42 498 4980 2023-04-11T13:37:42.123456".parse().unwrap();
+        assert_eq!(stream_bandwidth.id, StreamID("42".into()));
+        assert_eq!(stream_bandwidth.written, 498);
+        assert_eq!(stream_bandwidth.read, 4980);
+        assert!(stream_bandwidth.time.is_some());
+    }
+}
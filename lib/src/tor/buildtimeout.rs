@@ -0,0 +1,193 @@
+use std::fmt;
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while1};
+use nom::character::complete::space1;
+use nom::combinator::map;
+use nom::error::{context, ContextError, ErrorKind, ParseError};
+use nom::multi::many0;
+use nom::sequence::{preceded, separated_pair};
+
+use crate::tor::utils::word;
+use crate::tor::NomParse;
+
+/// Why the circuit build timeout was set.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum BuildTimeoutType {
+    /// Computed from the build times observed
+    Computed,
+
+    /// Reset to the default, after too many timeouts
+    Reset,
+
+    /// Learning suspended, because the network is down
+    Suspended,
+
+    /// Build times discarded, after the network came back
+    Discard,
+
+    /// Learning resumed
+    Resume,
+}
+
+impl NomParse for BuildTimeoutType {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "build timeout type",
+            alt((
+                map(tag("COMPUTED"), |_| Self::Computed),
+                map(tag("RESET"), |_| Self::Reset),
+                map(tag("SUSPENDED"), |_| Self::Suspended),
+                map(tag("DISCARD"), |_| Self::Discard),
+                map(tag("RESUME"), |_| Self::Resume),
+            )),
+        )(input)
+    }
+}
+impl_from_str!(BuildTimeoutType);
+impl_serde_str!(BuildTimeoutType);
+
+impl fmt::Display for BuildTimeoutType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Computed => f.write_str("COMPUTED"),
+            Self::Reset => f.write_str("RESET"),
+            Self::Suspended => f.write_str("SUSPENDED"),
+            Self::Discard => f.write_str("DISCARD"),
+            Self::Resume => f.write_str("RESUME"),
+        }
+    }
+}
+
+/// `BUILDTIMEOUT_SET` event: parameters of the circuit build timeout (CBT).
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BuildTimeout {
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub kind: BuildTimeoutType,
+
+    /// Number of build times the estimation is based on
+    pub total_times: u32,
+
+    /// Circuits taking longer to build time out
+    pub timeout_ms: u64,
+
+    /// Pareto distribution parameters
+    pub xm: u64,
+    pub alpha: f64,
+
+    /// Quantile of the distribution the timeout is set to
+    pub cutoff_quantile: f64,
+
+    /// Ratio of circuits timing out
+    pub timeout_rate: f64,
+
+    /// Timed out circuits are kept until this delay, for measurement
+    pub close_ms: u64,
+
+    /// Ratio of circuits closed after `close_ms`
+    pub close_rate: f64,
+}
+
+impl NomParse for BuildTimeout {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, kind) = BuildTimeoutType::parse(input)?;
+        let (rest, fields) = many0(preceded(
+            space1,
+            separated_pair(
+                word,
+                tag("="),
+                take_while1(|c: char| !c.is_ascii_whitespace()),
+            ),
+        ))(rest)?;
+
+        let mut me = Self {
+            kind,
+            total_times: 0,
+            timeout_ms: 0,
+            xm: 0,
+            alpha: 0.,
+            cutoff_quantile: 0.,
+            timeout_rate: 0.,
+            close_ms: 0,
+            close_rate: 0.,
+        };
+        for (key, value) in fields {
+            let valid = match key {
+                "TOTAL_TIMES" => value.parse().map(|v| me.total_times = v).is_ok(),
+                "TIMEOUT_MS" => value.parse().map(|v| me.timeout_ms = v).is_ok(),
+                "XM" => value.parse().map(|v| me.xm = v).is_ok(),
+                "ALPHA" => value.parse().map(|v| me.alpha = v).is_ok(),
+                "CUTOFF_QUANTILE" => value.parse().map(|v| me.cutoff_quantile = v).is_ok(),
+                "TIMEOUT_RATE" => value.parse().map(|v| me.timeout_rate = v).is_ok(),
+                "CLOSE_MS" => value.parse().map(|v| me.close_ms = v).is_ok(),
+                "CLOSE_RATE" => value.parse().map(|v| me.close_rate = v).is_ok(),
+                _ => true,
+            };
+            if !valid {
+                return Err(nom::Err::Error(E::add_context(
+                    value,
+                    "build timeout value",
+                    E::from_error_kind(value, ErrorKind::Digit),
+                )));
+            }
+        }
+
+        Ok((rest, me))
+    }
+}
+impl_from_str!(BuildTimeout);
+
+impl fmt::Display for BuildTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} TOTAL_TIMES={} TIMEOUT_MS={} XM={} ALPHA={:.6} CUTOFF_QUANTILE={:.6} \
+            TIMEOUT_RATE={:.6} CLOSE_MS={} CLOSE_RATE={:.6}",
+            self.kind,
+            self.total_times,
+            self.timeout_ms,
+            self.xm,
+            self.alpha,
+            self.cutoff_quantile,
+            self.timeout_rate,
+            self.close_ms,
+            self.close_rate
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_build_timeout() {
+        let input = "COMPUTED TOTAL_TIMES=1000 TIMEOUT_MS=1532 XM=498 ALPHA=1.874542 \
+            CUTOFF_QUANTILE=0.800000 TIMEOUT_RATE=0.132450 CLOSE_MS=60000 CLOSE_RATE=0.011921";
+        let build_timeout: BuildTimeout = input.parse().unwrap();
+        assert_eq!(
+            build_timeout,
+            BuildTimeout {
+                kind: BuildTimeoutType::Computed,
+                total_times: 1000,
+                timeout_ms: 1532,
+                xm: 498,
+                alpha: 1.874542,
+                cutoff_quantile: 0.8,
+                timeout_rate: 0.13245,
+                close_ms: 60000,
+                close_rate: 0.011921,
+            }
+        );
+        assert_eq!(build_timeout.to_string(), input);
+        assert!("COMPUTED TIMEOUT_MS=fast".parse::<BuildTimeout>().is_err());
+    }
+}
@@ -1,19 +1,23 @@
 use std::fmt::{self, Write};
 use std::str::FromStr;
+use std::time::Duration;
 
 use nom::branch::alt;
 use nom::bytes::complete::{escaped, tag};
 use nom::character::complete::{alphanumeric1, none_of, one_of, space1};
 use nom::combinator::{map, map_opt, opt, verify};
 use nom::error::{context, ContextError, ParseError};
-use nom::multi::{count, separated_list1};
+use nom::multi::separated_list1;
 use nom::sequence::tuple;
 
+use sha3::{Digest, Sha3_256};
+
 use crate::tor::common::{CircuitID, Time};
-use crate::tor::utils::{base32_word, hex_encode, hex_encode_inplace, parse_hex, word};
+use crate::tor::identity::RelayFingerprint;
+use crate::tor::utils::{base32_word, word};
 use crate::tor::NomParse;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
 pub enum CircuitStatus {
     /// circuit ID assigned to new circuit
     Launched,
@@ -53,6 +57,7 @@ impl NomParse for CircuitStatus {
     }
 }
 impl_from_str!(CircuitStatus);
+impl_serde_str!(CircuitStatus);
 
 impl fmt::Display for CircuitStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -67,7 +72,7 @@ impl fmt::Display for CircuitStatus {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum CircuitBuildFlag {
     /// One-hop circuit, used for tunneled directory conns
     OneHopTunnel,
@@ -99,6 +104,7 @@ impl NomParse for CircuitBuildFlag {
     }
 }
 impl_from_str!(CircuitBuildFlag);
+impl_serde_str!(CircuitBuildFlag);
 
 impl fmt::Display for CircuitBuildFlag {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -111,7 +117,8 @@ impl fmt::Display for CircuitBuildFlag {
     }
 }
 
-#[derive(Debug, Default, Eq, PartialEq)]
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CircuitBuildFlags(Vec<CircuitBuildFlag>);
 
 impl NomParse for CircuitBuildFlags {
@@ -125,6 +132,12 @@ impl NomParse for CircuitBuildFlags {
 }
 impl_from_str!(CircuitBuildFlags);
 
+impl CircuitBuildFlags {
+    pub fn contains(&self, flag: CircuitBuildFlag) -> bool {
+        self.0.contains(&flag)
+    }
+}
+
 impl fmt::Display for CircuitBuildFlags {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for (i, flag) in self.0.iter().enumerate() {
@@ -138,7 +151,7 @@ impl fmt::Display for CircuitBuildFlags {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
 pub enum CircuitPurpose {
     /// Circuit for AP and/or directory request streams
     General,
@@ -201,6 +214,7 @@ impl NomParse for CircuitPurpose {
     }
 }
 impl_from_str!(CircuitPurpose);
+impl_serde_str!(CircuitPurpose);
 
 impl fmt::Display for CircuitPurpose {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -221,7 +235,7 @@ impl fmt::Display for CircuitPurpose {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum HsState {
     /// Client-side introduction-point circuit states, connecting to intro point
     HSCIConnecting,
@@ -283,6 +297,7 @@ impl NomParse for HsState {
     }
 }
 impl_from_str!(HsState);
+impl_serde_str!(HsState);
 
 impl fmt::Display for HsState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -302,7 +317,7 @@ impl fmt::Display for HsState {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
 pub enum CircuitReason {
     None,
     TorProtocol,
@@ -349,6 +364,7 @@ impl NomParse for CircuitReason {
     }
 }
 impl_from_str!(CircuitReason);
+impl_serde_str!(CircuitReason);
 
 impl fmt::Display for CircuitReason {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -372,16 +388,16 @@ impl fmt::Display for CircuitReason {
     }
 }
 
-#[derive(Default, Eq, PartialEq)]
+#[derive(Default, Eq, PartialEq, Clone)]
 pub struct Step {
-    pub fingerprint: [u8; 20],
+    pub fingerprint: RelayFingerprint,
     pub nickname: Option<String>,
 }
 
 impl fmt::Debug for Step {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Step")
-            .field("fingerprint", &hex_encode(self.fingerprint))
+            .field("fingerprint", &self.fingerprint)
             .field("nickname", &self.nickname)
             .finish()
     }
@@ -389,8 +405,7 @@ impl fmt::Debug for Step {
 
 impl fmt::Display for Step {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_char('$')?;
-        hex_encode_inplace(f, self.fingerprint)?;
+        write!(f, "${}", self.fingerprint)?;
         if let Some(ref nickname) = self.nickname {
             f.write_char('~')?;
             f.write_str(nickname)?;
@@ -404,11 +419,15 @@ impl NomParse for Step {
     where
         E: ParseError<&'a str> + ContextError<&'a str>,
     {
-        let (rest, (_dollar, fingerprint)) =
-            context("Step fingerprint", tuple((tag("$"), count(parse_hex, 20))))(s)?;
+        let (rest, (_dollar, fingerprint)) = context(
+            "Step fingerprint",
+            tuple((tag("$"), RelayFingerprint::parse_hex)),
+        )(s)?;
 
-        let mut me = Self::default();
-        me.fingerprint.copy_from_slice(&fingerprint[..]);
+        let mut me = Self {
+            fingerprint,
+            nickname: None,
+        };
 
         let (rest, nickname) = opt(nom::sequence::preceded(
             nom::branch::alt((tag("~"), tag("="))),
@@ -426,8 +445,10 @@ impl NomParse for Step {
     }
 }
 impl_from_str!(Step);
+impl_serde_str!(Step);
 
-#[derive(Default, Debug, Eq, PartialEq)]
+#[derive(Default, Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Path(Vec<Step>);
 
 impl fmt::Display for Path {
@@ -469,40 +490,72 @@ impl NomParse for Path {
 }
 impl_from_str!(Path);
 
-#[derive(PartialEq, Eq)]
-pub enum HsAddress {
-    V2([u8; 10]),
-    V3([u8; 35]),
+/// v3 onion service address: the ed25519 public key of the service, a
+/// checksum and the version.
+///
+/// v2 addresses are not supported, tor dropped them in 0.4.6.
+#[derive(PartialEq, Eq, Clone, Hash)]
+pub struct HsAddress([u8; 35]);
+
+impl HsAddress {
+    pub const VERSION: u8 = 3;
+
+    fn checksum(public_key: &[u8], version: u8) -> [u8; 2] {
+        let mut hasher = Sha3_256::new();
+        hasher.update(b".onion checksum");
+        hasher.update(public_key);
+        hasher.update([version]);
+        let digest = hasher.finalize();
+        [digest[0], digest[1]]
+    }
+
+    pub fn from_public_key(public_key: &[u8; 32]) -> Self {
+        let mut bytes = [0u8; 35];
+        bytes[..32].copy_from_slice(public_key);
+        bytes[32..34].copy_from_slice(&Self::checksum(public_key, Self::VERSION));
+        bytes[34] = Self::VERSION;
+        Self(bytes)
+    }
+
+    /// Decoded address, `None` unless its version and checksum are valid.
+    pub fn from_bytes(bytes: [u8; 35]) -> Option<Self> {
+        let version = bytes[34];
+        if version != Self::VERSION || bytes[32..34] != Self::checksum(&bytes[..32], version) {
+            return None;
+        }
+        Some(Self(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 35] {
+        &self.0
+    }
+
+    /// Ed25519 public key of the service.
+    pub fn public_key(&self) -> &[u8; 32] {
+        self.0[..32].try_into().unwrap()
+    }
+
+    pub fn version(&self) -> u8 {
+        self.0[34]
+    }
+
+    /// `xxx.onion`, as given to a browser.
+    pub fn hostname(&self) -> String {
+        format!("{self}.onion")
+    }
 }
 
 impl fmt::Debug for HsAddress {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::V2(addr) => {
-                let mut s = String::with_capacity(addr.len() * 2);
-                for byte in addr.iter() {
-                    s.push_str(&format!("{:02x}", *byte));
-                }
-                f.debug_tuple("HsAddress::V2").field(&s).finish()
-            }
-            Self::V3(addr) => {
-                let mut s = String::with_capacity(addr.len() * 2);
-                for byte in addr.iter() {
-                    s.push_str(&format!("{:02x}", *byte));
-                }
-                f.debug_tuple("HsAddress::V3").field(&s).finish()
-            }
-        }
+        f.debug_tuple("HsAddress").field(&self.hostname()).finish()
     }
 }
 
+/// Service ID, without the `.onion` suffix, as tor expects it.
 impl fmt::Display for HsAddress {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let alphabet = base32::Alphabet::RFC4648 { padding: true };
-        match self {
-            Self::V2(addr) => f.write_str(&base32::encode(alphabet, &addr[..])),
-            Self::V3(addr) => f.write_str(&base32::encode(alphabet, &addr[..])),
-        }
+        let alphabet = base32::Alphabet::RFC4648 { padding: false };
+        f.write_str(&base32::encode(alphabet, &self.0[..]).to_lowercase())
     }
 }
 
@@ -511,32 +564,24 @@ impl NomParse for HsAddress {
     where
         E: ParseError<&'a str> + ContextError<&'a str>,
     {
-        let (rest, addr) = context(
+        let alphabet = base32::Alphabet::RFC4648 { padding: false };
+        let (rest, address) = context(
             "HS address",
-            verify(base32_word, |s: &str| s.len() == 16 || s.len() == 56),
+            map_opt(verify(base32_word, |s: &str| s.len() == 56), |s: &str| {
+                let bytes = base32::decode(alphabet, s)?;
+                Self::from_bytes(bytes.try_into().ok()?)
+            }),
         )(input)?;
-        let alphabet = base32::Alphabet::RFC4648 { padding: false };
-        let ip = match addr.len() {
-            16 => {
-                let bytes = base32::decode(alphabet, addr).expect("Invalid base32_word func?!");
-                let mut data = [0u8; 10];
-                data.copy_from_slice(&bytes[..]);
-                Self::V2(data)
-            }
-            56 => {
-                let bytes = base32::decode(alphabet, addr).expect("Invalid base32_word func?!");
-                let mut data = [0u8; 35];
-                data.copy_from_slice(&bytes[..]);
-                Self::V3(data)
-            }
-            _ => unreachable!(),
-        };
+        let (rest, _) = opt(tag(".onion"))(rest)?;
 
-        Ok((rest, ip))
+        Ok((rest, address))
     }
 }
+impl_from_str!(HsAddress);
+impl_serde_str!(HsAddress);
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Circuit {
     pub id: CircuitID,
     pub status: CircuitStatus,
@@ -547,10 +592,18 @@ pub struct Circuit {
     pub rend_query: Option<HsAddress>,
     pub time_created: Option<Time>,
     pub reason: Option<CircuitReason>,
+    pub remote_reason: Option<CircuitReason>,
     pub socks_username: Option<String>,
     pub socks_password: Option<String>,
 }
 
+impl Circuit {
+    /// Time elapsed since the circuit creation, if tor reported it.
+    pub fn age(&self) -> Option<Duration> {
+        self.time_created.as_ref().map(Time::elapsed)
+    }
+}
+
 impl fmt::Display for Circuit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -574,6 +627,9 @@ impl fmt::Display for Circuit {
         if let Some(ref reason) = self.reason {
             write!(f, " reason={reason}")?;
         }
+        if let Some(ref remote_reason) = self.remote_reason {
+            write!(f, " remote_reason={remote_reason}")?;
+        }
         if let Some(ref socks_username) = self.socks_username {
             write!(f, " socks_username={socks_username}")?;
         }
@@ -637,6 +693,12 @@ impl NomParse for Circuit {
         )(rest)?;
         let reason = opt_reason.map(|x| x.2);
 
+        let (rest, opt_remote_reason) = context(
+            "remote reason",
+            opt(tuple((space1, tag("REMOTE_REASON="), CircuitReason::parse))),
+        )(rest)?;
+        let remote_reason = opt_remote_reason.map(|x| x.2);
+
         let (rest, opt_socks_username) = context(
             "socks username",
             opt(tuple((
@@ -673,6 +735,7 @@ impl NomParse for Circuit {
                 rend_query,
                 time_created,
                 reason,
+                remote_reason,
                 socks_username,
                 socks_password,
             },
@@ -701,24 +764,24 @@ mod tests {
             status: CircuitStatus::Built,
             path: Path(vec![
                 Step {
-                    fingerprint: [
+                    fingerprint: RelayFingerprint([
                         0x87, 0x37, 0x30, 0x7d, 0xe8, 0x4c, 0x26, 0x21, 0xe6, 0x39, 0x9e, 0x99,
                         0x12, 0x39, 0x67, 0xa9, 0x59, 0x02, 0x97, 0xf2,
-                    ],
+                    ]),
                     nickname: Some("Tor0x800".into()),
                 },
                 Step {
-                    fingerprint: [
+                    fingerprint: RelayFingerprint([
                         0x24, 0x39, 0x96, 0xe4, 0x62, 0x18, 0x66, 0x6c, 0x1c, 0xad, 0xde, 0x17,
                         0xb4, 0x30, 0xea, 0x7f, 0x95, 0x12, 0x4f, 0x96,
-                    ],
+                    ]),
                     nickname: Some("GoofyRooster".into()),
                 },
                 Step {
-                    fingerprint: [
+                    fingerprint: RelayFingerprint([
                         0x3a, 0x94, 0x43, 0x71, 0x02, 0x24, 0xe5, 0x18, 0x28, 0x95, 0xc3, 0x42,
                         0xd1, 0xd3, 0x6c, 0x1d, 0x46, 0x0a, 0x12, 0x06,
-                    ],
+                    ]),
                     nickname: Some("CanterSecure04".into()),
                 },
             ]),
@@ -729,21 +792,14 @@ mod tests {
             ]),
             purpose: Some(CircuitPurpose::HsClientRend),
             hs_state: Some(HsState::HSCRJoined),
-            rend_query: Some(HsAddress::V3([
+            rend_query: HsAddress::from_bytes([
                 0x11, 0x56, 0x08, 0x92, 0x81, 0xf0, 0xe7, 0x4f, 0xb6, 0xb2, 0xda, 0xa0, 0xc7, 0x20,
                 0xc2, 0x48, 0xec, 0x07, 0xe8, 0x19, 0x8a, 0xaf, 0xdc, 0xb8, 0xe1, 0xd6, 0x15, 0xf3,
                 0x41, 0x2a, 0xb3, 0xb0, 0xe9, 0xda, 0x03,
-            ])),
-            time_created: Some(Time {
-                year: 2021,
-                month: 4,
-                day: 30,
-                hour: 13,
-                minute: 28,
-                second: 42,
-                mseconds: 4916,
-            }),
+            ]),
+            time_created: Some(Time::new(2021, 4, 30, 13, 28, 42, 4916).unwrap()),
             reason: None,
+            remote_reason: None,
             socks_username: None,
             socks_password: None,
         };
@@ -752,4 +808,33 @@ mod tests {
             Ok(("", circuit))
         );
     }
+
+    #[test]
+    fn hs_address() {
+        let service_id = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad";
+        let address: HsAddress = service_id.parse().unwrap();
+        assert_eq!(address.version(), HsAddress::VERSION);
+        assert_eq!(address.to_string(), service_id);
+        assert_eq!(address.hostname(), format!("{service_id}.onion"));
+        assert_eq!(
+            format!("{service_id}.onion").parse::<HsAddress>().unwrap(),
+            address
+        );
+        assert_eq!(HsAddress::from_public_key(address.public_key()), address);
+
+        // Wrong checksum
+        assert!("duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczae"
+            .parse::<HsAddress>()
+            .is_err());
+
+        // Valid checksum, for another version
+        let mut bytes = *address.as_bytes();
+        bytes[34] = 4;
+        let checksum = HsAddress::checksum(address.public_key(), 4);
+        bytes[32..34].copy_from_slice(&checksum);
+        assert_eq!(HsAddress::from_bytes(bytes), None);
+
+        // v2
+        assert!("3g2upl4pq6kufc4m".parse::<HsAddress>().is_err());
+    }
 }
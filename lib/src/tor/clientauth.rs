@@ -0,0 +1,260 @@
+use std::fmt;
+use std::str::FromStr;
+
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD},
+    Engine,
+};
+use rand::RngCore;
+
+use nom::bytes::complete::{tag, take_while1};
+use nom::character::complete::space1;
+use nom::combinator::{map_opt, opt};
+use nom::error::{context, ContextError, ParseError};
+use nom::multi::separated_list1;
+use nom::sequence::{preceded, tuple};
+
+use crate::tor::circuit::HsAddress;
+use crate::tor::utils::{base64_word, word};
+use crate::tor::NomParse;
+
+const BASE32: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+/// Client authorization public key, given to the onion service operator.
+#[derive(Default, Eq, PartialEq, Clone, Copy, Hash)]
+pub struct X25519PublicKey(pub [u8; 32]);
+
+impl X25519PublicKey {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Line of an `authorized_clients/<name>.auth` file of the service.
+    pub fn to_auth_line(&self) -> String {
+        format!("descriptor:x25519:{self}")
+    }
+}
+
+impl fmt::Debug for X25519PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("X25519PublicKey")
+            .field(&self.to_string())
+            .finish()
+    }
+}
+
+/// Unpadded base32, as in `ADD_ONION` and the `.auth` files.
+impl fmt::Display for X25519PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&base32::encode(BASE32, &self.0))
+    }
+}
+
+impl NomParse for X25519PublicKey {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "x25519 public key",
+            map_opt(
+                take_while1(|c: char| c.is_ascii_alphanumeric()),
+                |s: &str| {
+                    let bytes = base32::decode(BASE32, &s.to_uppercase())?;
+                    Some(Self(bytes.try_into().ok()?))
+                },
+            ),
+        )(input)
+    }
+}
+impl_from_str!(X25519PublicKey);
+impl_serde_str!(X25519PublicKey);
+
+/// Client authorization private key, kept by tor to reach the service.
+#[derive(Eq, PartialEq, Clone)]
+pub struct X25519PrivateKey([u8; 32]);
+
+impl X25519PrivateKey {
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn public_key(&self) -> X25519PublicKey {
+        let secret = x25519_dalek::StaticSecret::from(self.0);
+        X25519PublicKey(x25519_dalek::PublicKey::from(&secret).to_bytes())
+    }
+
+    /// Line of a `ClientOnionAuthDir/<name>.auth_private` file.
+    pub fn to_auth_line(&self, service_id: &HsAddress) -> String {
+        format!(
+            "{service_id}:descriptor:x25519:{}",
+            base32::encode(BASE32, &self.0)
+        )
+    }
+}
+
+impl fmt::Debug for X25519PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("X25519PrivateKey(..)")
+    }
+}
+
+/// Padded base64, as in `ONION_CLIENT_AUTH_ADD`.
+impl fmt::Display for X25519PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&STANDARD.encode(self.0))
+    }
+}
+
+impl NomParse for X25519PrivateKey {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "x25519 private key",
+            map_opt(base64_word, |s: &str| {
+                let bytes = STANDARD_NO_PAD.decode(s.trim_end_matches('=')).ok()?;
+                Some(Self(bytes.try_into().ok()?))
+            }),
+        )(input)
+    }
+}
+impl_from_str!(X25519PrivateKey);
+impl_serde_str!(X25519PrivateKey);
+
+/// Credentials to reach an onion service requiring client authorization.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClientAuth {
+    pub service_id: HsAddress,
+    pub private_key: X25519PrivateKey,
+    pub client_name: Option<String>,
+
+    /// Saved in `ClientOnionAuthDir`, instead of kept in memory
+    pub permanent: bool,
+}
+
+impl ClientAuth {
+    pub fn new(service_id: HsAddress, private_key: X25519PrivateKey) -> Self {
+        Self {
+            service_id,
+            private_key,
+            client_name: None,
+            permanent: false,
+        }
+    }
+}
+
+impl NomParse for ClientAuth {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (service_id, _, _, private_key)) = context(
+            "Client auth",
+            tuple((
+                HsAddress::parse,
+                space1,
+                tag("x25519:"),
+                X25519PrivateKey::parse,
+            )),
+        )(input)?;
+        let (rest, client_name) = opt(preceded(
+            tag(" ClientName="),
+            take_while1(|c: char| !c.is_ascii_whitespace()),
+        ))(rest)?;
+        let (rest, flags) = opt(preceded(tag(" Flags="), separated_list1(tag(","), word)))(rest)?;
+
+        Ok((
+            rest,
+            Self {
+                service_id,
+                private_key,
+                client_name: client_name.map(String::from),
+                permanent: flags.is_some_and(|flags| flags.contains(&"Permanent")),
+            },
+        ))
+    }
+}
+impl_from_str!(ClientAuth);
+
+/// Arguments of `ONION_CLIENT_AUTH_ADD`, as listed by `ONION_CLIENT_AUTH_VIEW`.
+impl fmt::Display for ClientAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} x25519:{}", self.service_id, self.private_key)?;
+        if let Some(ref client_name) = self.client_name {
+            write!(f, " ClientName={client_name}")?;
+        }
+        if self.permanent {
+            f.write_str(" Flags=Permanent")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE_ID: &str = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad";
+
+    #[test]
+    fn x25519_keys() {
+        // RFC 7748, section 6.1
+        let mut alice = [0u8; 32];
+        let alice_hex = "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a";
+        for (i, byte) in alice.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&alice_hex[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        let private_key = X25519PrivateKey::from_bytes(alice);
+        assert_eq!(
+            crate::tor::utils::hex_encode(private_key.public_key().as_bytes()).to_lowercase(),
+            "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"
+        );
+
+        let public_key = private_key.public_key();
+        assert_eq!(public_key.to_string().len(), 52);
+        assert_eq!(
+            public_key
+                .to_string()
+                .to_lowercase()
+                .parse::<X25519PublicKey>()
+                .unwrap(),
+            public_key
+        );
+
+        let generated = X25519PrivateKey::generate();
+        assert_ne!(generated.as_bytes(), private_key.as_bytes());
+        assert_eq!(
+            generated.to_string().parse::<X25519PrivateKey>().unwrap(),
+            generated
+        );
+    }
+
+    #[test]
+    fn parse_client_auth() {
+        let key = STANDARD.encode([3u8; 32]);
+        let input = format!("{SERVICE_ID} x25519:{key} ClientName=laptop Flags=Permanent");
+        let auth: ClientAuth = input.parse().unwrap();
+        assert_eq!(auth.service_id.to_string(), SERVICE_ID);
+        assert_eq!(auth.private_key.as_bytes(), &[3u8; 32]);
+        assert_eq!(auth.client_name.as_deref(), Some("laptop"));
+        assert!(auth.permanent);
+        assert_eq!(auth.to_string(), input);
+
+        let auth: ClientAuth = format!("{SERVICE_ID} x25519:{key}").parse().unwrap();
+        assert_eq!(auth.client_name, None);
+        assert!(!auth.permanent);
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_while};
@@ -8,9 +10,15 @@ use nom::combinator::{map, map_opt, opt, verify};
 use nom::error::{context, ContextError, ParseError};
 use nom::sequence::tuple;
 
+use crate::error::Error;
 use crate::tor::NomParse;
 
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct StreamID(pub String);
 
 impl NomParse for StreamID {
@@ -35,6 +43,11 @@ impl fmt::Display for StreamID {
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct CircuitID(pub String);
 
 impl NomParse for CircuitID {
@@ -58,7 +71,7 @@ impl fmt::Display for CircuitID {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub enum HostOrAddr {
     Host(String),
     Addr(IpAddr),
@@ -106,6 +119,142 @@ impl NomParse for HostOrAddr {
         )(input)
     }
 }
+impl_from_str!(HostOrAddr);
+impl_serde_str!(HostOrAddr);
+
+impl fmt::Display for HostOrAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Host(ref host) => f.write_str(host),
+            Self::Addr(IpAddr::V4(ref ip4)) => write!(f, "{ip4}"),
+            Self::Addr(IpAddr::V6(ref ip6)) => write!(f, "[{ip6}]"),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        let max_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > max_len {
+            None
+        } else {
+            Some(Self { addr, prefix_len })
+        }
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<IpAddr> for Cidr {
+    fn from(addr: IpAddr) -> Self {
+        let prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        Self { addr, prefix_len }
+    }
+}
+
+impl NomParse for Cidr {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, addr) = context(
+            "CIDR address",
+            alt((
+                map(
+                    map_opt(
+                        take_while(|c: char| c.is_ascii_digit() || c == '.'),
+                        |s: &str| s.parse::<Ipv4Addr>().ok(),
+                    ),
+                    IpAddr::V4,
+                ),
+                map(
+                    map_opt(
+                        tuple((
+                            tag("["),
+                            take_while(|c: char| c.is_ascii_hexdigit() || c == ':'),
+                            tag("]"),
+                        )),
+                        |s: (&str, &str, &str)| s.1.parse::<Ipv6Addr>().ok(),
+                    ),
+                    IpAddr::V6,
+                ),
+                map(
+                    map_opt(
+                        take_while(|c: char| c.is_ascii_hexdigit() || c == ':'),
+                        |s: &str| s.parse::<Ipv6Addr>().ok(),
+                    ),
+                    IpAddr::V6,
+                ),
+            )),
+        )(input)?;
+        let (rest, opt_prefix_len) = context(
+            "CIDR prefix length",
+            opt(map(
+                tuple((tag("/"), map_opt(digit1, |s: &str| s.parse::<u8>().ok()))),
+                |x| x.1,
+            )),
+        )(rest)?;
+
+        let cidr = match opt_prefix_len {
+            Some(prefix_len) => match Self::new(addr, prefix_len) {
+                Some(cidr) => cidr,
+                None => {
+                    return Err(nom::Err::Error(E::add_context(
+                        input,
+                        "CIDR prefix length too large",
+                        E::from_error_kind(rest, nom::error::ErrorKind::Verify),
+                    )))
+                }
+            },
+            None => Self::from(addr),
+        };
+
+        Ok((rest, cidr))
+    }
+}
+impl_from_str!(Cidr);
+impl_serde_str!(Cidr);
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr {
+            IpAddr::V4(ref ip4) => write!(f, "{ip4}")?,
+            IpAddr::V6(ref ip6) => write!(f, "[{ip6}]")?,
+        }
+        if *self != Self::from(self.addr) {
+            write!(f, "/{}", self.prefix_len)?;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Target {
@@ -133,26 +282,248 @@ impl NomParse for Target {
         Ok((rest, Self { addr, port }))
     }
 }
+impl_from_str!(Target);
+impl_serde_str!(Target);
 
 impl fmt::Display for Target {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.addr {
-            HostOrAddr::Host(ref host) => write!(f, "{}:{}", host, self.port),
-            HostOrAddr::Addr(IpAddr::V4(ref ip4)) => write!(f, "{}:{}", ip4, self.port),
-            HostOrAddr::Addr(IpAddr::V6(ref ip6)) => write!(f, "[{}]:{}", ip6, self.port),
-        }
+        write!(f, "{}:{}", self.addr, self.port)
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+/// UTC date and time, with a microsecond precision.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, PartialOrd, Ord)]
 pub struct Time {
-    pub year: u16,
-    pub month: u8,
-    pub day: u8,
-    pub hour: u8,
-    pub minute: u8,
-    pub second: u8,
-    pub mseconds: u32,
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    microseconds: u32,
+}
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+// Days between 1970-01-01 and the given date (proleptic gregorian calendar)
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+impl Time {
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+        microseconds: u32,
+    ) -> Result<Self, Error> {
+        if !(1..=12).contains(&month) {
+            return Err(Error::InvalidTime(format!("month {month} out of range")));
+        }
+        if day == 0 || day > days_in_month(year as i64, month) {
+            return Err(Error::InvalidTime(format!(
+                "day {day} out of range for {year:04}-{month:02}"
+            )));
+        }
+        if hour > 23 || minute > 59 || second > 59 {
+            return Err(Error::InvalidTime(format!(
+                "invalid time of day {hour:02}:{minute:02}:{second:02}"
+            )));
+        }
+        if microseconds >= 1_000_000 {
+            return Err(Error::InvalidTime(format!(
+                "{microseconds} microseconds out of range"
+            )));
+        }
+
+        Ok(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            microseconds,
+        })
+    }
+
+    pub fn now() -> Self {
+        Self::try_from(SystemTime::now()).expect("system clock out of range")
+    }
+
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+
+    pub fn microseconds(&self) -> u32 {
+        self.microseconds
+    }
+
+    /// Builds a time from a number of seconds and microseconds since the UNIX epoch.
+    pub fn from_unix_timestamp(seconds: i64, microseconds: u32) -> Result<Self, Error> {
+        let seconds = seconds + (microseconds / 1_000_000) as i64;
+        let microseconds = microseconds % 1_000_000;
+        let days = seconds.div_euclid(SECONDS_PER_DAY);
+        let seconds_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        let year = u16::try_from(year)
+            .map_err(|_| Error::InvalidTime(format!("year {year} out of range")))?;
+
+        Self::new(
+            year,
+            month,
+            day,
+            (seconds_of_day / 3600) as u8,
+            (seconds_of_day % 3600 / 60) as u8,
+            (seconds_of_day % 60) as u8,
+            microseconds,
+        )
+    }
+
+    /// Number of seconds since the UNIX epoch, microseconds are truncated.
+    pub fn unix_timestamp(&self) -> i64 {
+        days_from_civil(self.year as i64, self.month, self.day) * SECONDS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+    }
+
+    /// Duration elapsed since `earlier`, or `None` if `earlier` is later than `self`.
+    pub fn duration_since(&self, earlier: &Time) -> Option<Duration> {
+        SystemTime::from(*self)
+            .duration_since(SystemTime::from(*earlier))
+            .ok()
+    }
+
+    /// Duration elapsed since `self`, zero if `self` is in the future.
+    pub fn elapsed(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(SystemTime::from(*self))
+            .unwrap_or_default()
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        SystemTime::from(*self)
+            .checked_add(duration)
+            .and_then(|t| Self::try_from(t).ok())
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        SystemTime::from(*self)
+            .checked_sub(duration)
+            .and_then(|t| Self::try_from(t).ok())
+    }
+}
+
+/// Fails if the year does not fit in the [`Time`] range.
+impl TryFrom<SystemTime> for Time {
+    type Error = Error;
+
+    fn try_from(t: SystemTime) -> Result<Self, Self::Error> {
+        match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => Self::from_unix_timestamp(d.as_secs() as i64, d.subsec_micros()),
+            Err(e) => {
+                let d = e.duration();
+                if d.subsec_micros() == 0 {
+                    Self::from_unix_timestamp(-(d.as_secs() as i64), 0)
+                } else {
+                    Self::from_unix_timestamp(
+                        -(d.as_secs() as i64) - 1,
+                        1_000_000 - d.subsec_micros(),
+                    )
+                }
+            }
+        }
+    }
+}
+
+impl From<Time> for SystemTime {
+    fn from(t: Time) -> Self {
+        let seconds = t.unix_timestamp();
+        let since_epoch = Duration::new(seconds.unsigned_abs(), 0);
+        let t0 = if seconds >= 0 {
+            UNIX_EPOCH + since_epoch
+        } else {
+            UNIX_EPOCH - since_epoch
+        };
+        t0 + Duration::from_micros(t.microseconds as u64)
+    }
+}
+
+impl std::ops::Add<Duration> for Time {
+    type Output = Time;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        self.checked_add(rhs)
+            .expect("overflow when adding duration to time")
+    }
+}
+
+impl std::ops::Sub<Duration> for Time {
+    type Output = Time;
+
+    fn sub(self, rhs: Duration) -> Self::Output {
+        self.checked_sub(rhs)
+            .expect("overflow when subtracting duration from time")
+    }
 }
 
 impl NomParse for Time {
@@ -188,26 +559,50 @@ impl NomParse for Time {
                 }),
             )),
         )(input)?;
-        let (rest, opt_mseconds) = opt(tuple((
+        let (rest, opt_microseconds) = opt(tuple((
             tag("."),
             map(verify(digit1, |s: &str| s.len() == 6), |s: &str| {
                 s.parse::<u32>().unwrap()
             }),
         )))(rest)?;
-        let mseconds = opt_mseconds.map(|x| x.1).unwrap_or_default();
-
-        Ok((
-            rest,
-            Self {
-                year,
-                month,
-                day,
-                hour,
-                minute,
-                second,
-                mseconds,
-            },
-        ))
+        let microseconds = opt_microseconds.map(|x| x.1).unwrap_or_default();
+
+        match Self::new(year, month, day, hour, minute, second, microseconds) {
+            Ok(time) => Ok((rest, time)),
+            Err(_) => Err(nom::Err::Error(E::add_context(
+                input,
+                "valid date and time",
+                E::from_error_kind(input, nom::error::ErrorKind::Verify),
+            ))),
+        }
+    }
+}
+impl_from_str!(Time);
+
+/// Times are serialized as RFC 3339 strings, in UTC.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Time {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(&format_args!("{self}Z"))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Time {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        let s = s
+            .strip_suffix('Z')
+            .or_else(|| s.strip_suffix('z'))
+            .or_else(|| s.strip_suffix("+00:00"))
+            .unwrap_or(&s);
+        crate::tor::parse_all(s).map_err(serde::de::Error::custom)
     }
 }
 
@@ -216,7 +611,47 @@ impl fmt::Display for Time {
         write!(
             f,
             "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}",
-            self.year, self.month, self.day, self.hour, self.minute, self.second, self.mseconds
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.microseconds
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time() {
+        let time = Time::new(2021, 4, 30, 13, 28, 42, 4916).unwrap();
+        assert_eq!(time.unix_timestamp(), 1_619_789_322);
+        assert_eq!(
+            Time::from_unix_timestamp(1_619_789_322, 4916).unwrap(),
+            time
+        );
+        assert_eq!(Time::try_from(SystemTime::from(time)).unwrap(), time);
+        assert_eq!(
+            time + Duration::from_secs(86_400 * 2),
+            Time::new(2021, 5, 2, 13, 28, 42, 4916).unwrap()
+        );
+        assert!(Time::new(2021, 2, 29, 0, 0, 0, 0).is_err());
+        assert!(Time::new(2020, 2, 29, 0, 0, 0, 0).is_ok());
+        assert!("2021-13-01 00:00:00".parse::<Time>().is_err());
+        assert!("2021-04-30 13:28:42".parse::<Time>().unwrap() < time);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let time = Time::new(2021, 4, 30, 13, 28, 42, 4916).unwrap();
+        let json = serde_json::to_string(&time).unwrap();
+        assert_eq!(json, "\"2021-04-30T13:28:42.004916Z\"");
+        assert_eq!(serde_json::from_str::<Time>(&json).unwrap(), time);
+
+        let target: Target = serde_json::from_str("\"[2001:db8::1]:443\"").unwrap();
+        assert_eq!(
+            serde_json::to_string(&target).unwrap(),
+            "\"[2001:db8::1]:443\""
+        );
+        assert!(serde_json::from_str::<Cidr>("\"10.0.0.0/8 trailing\"").is_err());
+    }
+}
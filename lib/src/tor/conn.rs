@@ -13,6 +13,7 @@ const TOR_CLIENT_HASH_KEY: &[u8] = b"Tor safe cookie authentication controller-t
 const TOR_SERBER_HASH_KEY: &[u8] = b"Tor safe cookie authentication server-to-controller hash";
 
 #[derive(Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Response {
     pub code: u16,
     pub data: String,
@@ -35,6 +36,20 @@ pub struct Connection<S> {
     async_events: Option<HashMap<String, VecDeque<String>>>,
 }
 
+/// Splits an asynchronous event into its name and body.
+///
+/// Multi-line events (`650+`) end with the `650 OK` line, which is dropped.
+fn split_event(data: &str) -> Option<(&str, &str)> {
+    let idx = data.find([' ', '\r'])?;
+    let (key, val) = data.split_at(idx);
+    let val = val.strip_prefix(' ').or_else(|| val.strip_prefix("\r\n"))?;
+    if val.ends_with("\r\nOK\r\n") {
+        Some((key, &val[..val.len() - "OK\r\n".len()]))
+    } else {
+        Some((key, val))
+    }
+}
+
 impl<S> Connection<S>
 where
     S: Read + Write,
@@ -46,6 +61,10 @@ where
         }
     }
 
+    pub fn get_ref(&self) -> &S {
+        self.conn.get_ref()
+    }
+
     pub fn authenticate(&mut self) -> Result<(), Error> {
         let raw_protocol_info = self.send_command("PROTOCOLINFO 1")?;
         if raw_protocol_info.code != 250 {
@@ -209,7 +228,7 @@ where
                     continue;
                 }
 
-                if let Some((key, val)) = response.data.split_once(' ') {
+                if let Some((key, val)) = split_event(&response.data) {
                     if let Some(events) = self.async_events.as_mut().unwrap().get_mut(key) {
                         events.push_back(val.to_owned());
                     } else {
@@ -236,7 +255,7 @@ where
             let event = event.as_ref().to_owned();
             cmd.push(' ');
             cmd.push_str(event.as_str());
-            async_events.entry(event).or_insert_with(VecDeque::new);
+            async_events.entry(event).or_default();
         }
 
         let response = self.send_command(cmd)?;
@@ -248,6 +267,28 @@ where
         }
     }
 
+    /// Blocks until an asynchronous event is received, already queued events come first.
+    pub fn wait_async_event(&mut self) -> Result<(String, String), Error> {
+        if let Some(event) = self.consome_async_event() {
+            return Ok(event);
+        }
+
+        loop {
+            let response = self.receive_response()?;
+            if response.code != 650 {
+                log::warn!(
+                    "Unexpected response while waiting for events: {:?}",
+                    response
+                );
+                continue;
+            }
+            match split_event(&response.data) {
+                Some((key, val)) => return Ok((key.to_owned(), val.to_owned())),
+                None => log::warn!("Buggy async response, no first word"),
+            }
+        }
+    }
+
     pub fn consome_async_event(&mut self) -> Option<(String, String)> {
         let events = self.async_events.as_mut()?;
 
@@ -0,0 +1,178 @@
+use std::fmt;
+
+use crate::error::Result;
+use crate::tor::addrmap::AddrMap;
+use crate::tor::bandwidth::{Bandwidth, CircuitBandwidth, StreamBandwidth};
+use crate::tor::buildtimeout::BuildTimeout;
+use crate::tor::circuit::Circuit;
+use crate::tor::guard::GuardEvent;
+use crate::tor::hsdesc::{HsDesc, HsDescContent};
+use crate::tor::logmessage::LogMessage;
+use crate::tor::orconn::OrConnection;
+use crate::tor::status::Status;
+use crate::tor::stream::Stream;
+use crate::tor::NomParse;
+
+/// Asynchronous event sent by tor once subscribed with `SETEVENTS`.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "event")
+)]
+pub enum Event {
+    /// `CIRC`: circuit status changed
+    #[cfg_attr(feature = "serde", serde(rename = "CIRC"))]
+    Circuit(Circuit),
+
+    /// `STREAM`: stream status changed
+    #[cfg_attr(feature = "serde", serde(rename = "STREAM"))]
+    Stream(Stream),
+
+    /// `BW`: bytes read and written during the last second
+    #[cfg_attr(feature = "serde", serde(rename = "BW"))]
+    Bandwidth(Bandwidth),
+
+    /// `CIRC_BW`: bytes read and written on a circuit
+    #[cfg_attr(feature = "serde", serde(rename = "CIRC_BW"))]
+    CircuitBandwidth(CircuitBandwidth),
+
+    /// `STREAM_BW`: bytes read and written on a stream
+    #[cfg_attr(feature = "serde", serde(rename = "STREAM_BW"))]
+    StreamBandwidth(StreamBandwidth),
+
+    /// `BUILDTIMEOUT_SET`: circuit build timeout changed
+    #[cfg_attr(feature = "serde", serde(rename = "BUILDTIMEOUT_SET"))]
+    BuildTimeoutSet(BuildTimeout),
+
+    /// `ORCONN`: OR connection status changed
+    #[cfg_attr(feature = "serde", serde(rename = "ORCONN"))]
+    OrConnection(OrConnection),
+
+    /// `STATUS_GENERAL`: status of tor itself
+    #[cfg_attr(feature = "serde", serde(rename = "STATUS_GENERAL"))]
+    GeneralStatus(Status),
+
+    /// `STATUS_CLIENT`: status of tor as a client, bootstrap included
+    #[cfg_attr(feature = "serde", serde(rename = "STATUS_CLIENT"))]
+    ClientStatus(Status),
+
+    /// `STATUS_SERVER`: status of tor as a relay
+    #[cfg_attr(feature = "serde", serde(rename = "STATUS_SERVER"))]
+    ServerStatus(Status),
+
+    /// `ADDRMAP`: address mapped or resolved
+    #[cfg_attr(feature = "serde", serde(rename = "ADDRMAP"))]
+    AddrMap(AddrMap),
+
+    /// `GUARD`: entry guard changed
+    #[cfg_attr(feature = "serde", serde(rename = "GUARD"))]
+    Guard(GuardEvent),
+
+    /// `HS_DESC`: onion service descriptor fetched or uploaded
+    #[cfg_attr(feature = "serde", serde(rename = "HS_DESC"))]
+    HsDesc(HsDesc),
+
+    /// `HS_DESC_CONTENT`: onion service descriptor received
+    #[cfg_attr(feature = "serde", serde(rename = "HS_DESC_CONTENT"))]
+    HsDescContent(HsDescContent),
+
+    /// `DEBUG`, `INFO`, `NOTICE`, `WARN` or `ERR`: tor log message
+    #[cfg_attr(feature = "serde", serde(rename = "LOG"))]
+    Log(LogMessage),
+
+    /// Any event without a dedicated parser
+    #[cfg_attr(feature = "serde", serde(rename = "OTHER"))]
+    Other { name: String, data: String },
+}
+
+impl Event {
+    /// Parses the body of an event named `name`.
+    pub fn from_raw(name: &str, data: &str) -> Result<Self> {
+        let event = match name {
+            "CIRC" => Self::Circuit(Circuit::parse::<nom::error::VerboseError<&str>>(data)?.1),
+            "STREAM" => Self::Stream(Stream::parse::<nom::error::VerboseError<&str>>(data)?.1),
+            "BW" => Self::Bandwidth(Bandwidth::parse::<nom::error::VerboseError<&str>>(data)?.1),
+            "CIRC_BW" => Self::CircuitBandwidth(
+                CircuitBandwidth::parse::<nom::error::VerboseError<&str>>(data)?.1,
+            ),
+            "STREAM_BW" => Self::StreamBandwidth(
+                StreamBandwidth::parse::<nom::error::VerboseError<&str>>(data)?.1,
+            ),
+            "BUILDTIMEOUT_SET" => Self::BuildTimeoutSet(
+                BuildTimeout::parse::<nom::error::VerboseError<&str>>(data)?.1,
+            ),
+            "ORCONN" => {
+                Self::OrConnection(OrConnection::parse::<nom::error::VerboseError<&str>>(data)?.1)
+            }
+            "STATUS_GENERAL" => {
+                Self::GeneralStatus(Status::parse::<nom::error::VerboseError<&str>>(data)?.1)
+            }
+            "STATUS_CLIENT" => {
+                Self::ClientStatus(Status::parse::<nom::error::VerboseError<&str>>(data)?.1)
+            }
+            "STATUS_SERVER" => {
+                Self::ServerStatus(Status::parse::<nom::error::VerboseError<&str>>(data)?.1)
+            }
+            "ADDRMAP" => Self::AddrMap(AddrMap::parse::<nom::error::VerboseError<&str>>(data)?.1),
+            "GUARD" => Self::Guard(GuardEvent::parse::<nom::error::VerboseError<&str>>(data)?.1),
+            "HS_DESC" => Self::HsDesc(HsDesc::parse::<nom::error::VerboseError<&str>>(data)?.1),
+            "HS_DESC_CONTENT" => {
+                Self::HsDescContent(HsDescContent::parse::<nom::error::VerboseError<&str>>(data)?.1)
+            }
+            "DEBUG" | "INFO" | "NOTICE" | "WARN" | "ERR" => {
+                Self::Log(LogMessage::new(name.parse()?, data))
+            }
+            _ => Self::Other {
+                name: name.into(),
+                data: data.trim_end().into(),
+            },
+        };
+        Ok(event)
+    }
+
+    /// Event name, as given to `SETEVENTS`.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Circuit(_) => "CIRC",
+            Self::Stream(_) => "STREAM",
+            Self::Bandwidth(_) => "BW",
+            Self::CircuitBandwidth(_) => "CIRC_BW",
+            Self::StreamBandwidth(_) => "STREAM_BW",
+            Self::BuildTimeoutSet(_) => "BUILDTIMEOUT_SET",
+            Self::OrConnection(_) => "ORCONN",
+            Self::GeneralStatus(_) => "STATUS_GENERAL",
+            Self::ClientStatus(_) => "STATUS_CLIENT",
+            Self::ServerStatus(_) => "STATUS_SERVER",
+            Self::AddrMap(_) => "ADDRMAP",
+            Self::Guard(_) => "GUARD",
+            Self::HsDesc(_) => "HS_DESC",
+            Self::HsDescContent(_) => "HS_DESC_CONTENT",
+            Self::Log(message) => message.severity.as_str(),
+            Self::Other { ref name, .. } => name.as_str(),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Circuit(circuit) => write!(f, "CIRC {circuit}"),
+            Self::Stream(stream) => write!(f, "STREAM {stream}"),
+            Self::Bandwidth(bandwidth) => write!(f, "BW {bandwidth}"),
+            Self::CircuitBandwidth(bandwidth) => write!(f, "CIRC_BW {bandwidth}"),
+            Self::StreamBandwidth(bandwidth) => write!(f, "STREAM_BW {bandwidth}"),
+            Self::BuildTimeoutSet(build_timeout) => write!(f, "BUILDTIMEOUT_SET {build_timeout}"),
+            Self::OrConnection(orconn) => write!(f, "ORCONN {orconn}"),
+            Self::GeneralStatus(status) => write!(f, "STATUS_GENERAL {status}"),
+            Self::ClientStatus(status) => write!(f, "STATUS_CLIENT {status}"),
+            Self::ServerStatus(status) => write!(f, "STATUS_SERVER {status}"),
+            Self::AddrMap(addrmap) => write!(f, "ADDRMAP {addrmap}"),
+            Self::Guard(guard) => write!(f, "GUARD {guard}"),
+            Self::HsDesc(desc) => write!(f, "HS_DESC {desc}"),
+            Self::HsDescContent(content) => write!(f, "HS_DESC_CONTENT {content}"),
+            Self::Log(message) => write!(f, "{message}"),
+            Self::Other { name, data } => write!(f, "{name} {data}"),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::space1;
+use nom::combinator::{map, opt};
+use nom::error::{context, ContextError, ParseError};
+use nom::sequence::{preceded, tuple};
+
+use crate::tor::circuit::Step;
+use crate::tor::common::Time;
+use crate::tor::identity::RelayFingerprint;
+use crate::tor::NomParse;
+
+/// Status of an entry guard, as listed by `entry-guards`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum GuardStatus {
+    Up,
+    NeverConnected,
+    Down,
+
+    /// Not usable, the reason is only in tor's log
+    Unusable,
+
+    /// Not in the consensus anymore
+    Unlisted,
+}
+
+impl NomParse for GuardStatus {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Guard status",
+            alt((
+                map(tag("up"), |_| Self::Up),
+                map(tag("never-connected"), |_| Self::NeverConnected),
+                map(tag("down"), |_| Self::Down),
+                map(tag("unusable"), |_| Self::Unusable),
+                map(tag("unlisted"), |_| Self::Unlisted),
+            )),
+        )(s)
+    }
+}
+impl_from_str!(GuardStatus);
+impl_serde_str!(GuardStatus);
+
+impl fmt::Display for GuardStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Up => f.write_str("up"),
+            Self::NeverConnected => f.write_str("never-connected"),
+            Self::Down => f.write_str("down"),
+            Self::Unusable => f.write_str("unusable"),
+            Self::Unlisted => f.write_str("unlisted"),
+        }
+    }
+}
+
+/// `$fingerprint~nickname`, or a bare fingerprint.
+fn server_id<'a, E>(s: &'a str) -> nom::IResult<&'a str, Step, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    context(
+        "Guard ID",
+        alt((
+            Step::parse,
+            map(RelayFingerprint::parse_hex, |fingerprint| Step {
+                fingerprint,
+                nickname: None,
+            }),
+        )),
+    )(s)
+}
+
+/// A line of `entry-guards`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntryGuard {
+    pub relay: Step,
+    pub status: GuardStatus,
+
+    /// When the guard went down or unusable
+    pub since: Option<Time>,
+}
+
+impl NomParse for EntryGuard {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (relay, _, status)) = context(
+            "Entry guard",
+            tuple((server_id, space1, GuardStatus::parse)),
+        )(s)?;
+        let (rest, since) = opt(preceded(space1, Time::parse))(rest)?;
+
+        Ok((
+            rest,
+            Self {
+                relay,
+                status,
+                since,
+            },
+        ))
+    }
+}
+impl_from_str!(EntryGuard);
+
+impl fmt::Display for EntryGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.relay, self.status)?;
+        if let Some(ref since) = self.since {
+            write!(f, " {since}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum GuardEventStatus {
+    /// Added to the guards
+    New,
+
+    Up,
+    Down,
+
+    /// Removed from the consensus, or made unusable
+    Bad,
+
+    /// Usable again
+    Good,
+
+    /// Removed from the guards
+    Dropped,
+}
+
+impl NomParse for GuardEventStatus {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Guard event status",
+            alt((
+                map(tag("NEW"), |_| Self::New),
+                map(tag("UP"), |_| Self::Up),
+                map(tag("DOWN"), |_| Self::Down),
+                map(tag("BAD"), |_| Self::Bad),
+                map(tag("GOOD"), |_| Self::Good),
+                map(tag("DROPPED"), |_| Self::Dropped),
+            )),
+        )(s)
+    }
+}
+impl_from_str!(GuardEventStatus);
+impl_serde_str!(GuardEventStatus);
+
+impl fmt::Display for GuardEventStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::New => f.write_str("NEW"),
+            Self::Up => f.write_str("UP"),
+            Self::Down => f.write_str("DOWN"),
+            Self::Bad => f.write_str("BAD"),
+            Self::Good => f.write_str("GOOD"),
+            Self::Dropped => f.write_str("DROPPED"),
+        }
+    }
+}
+
+/// `GUARD` event: an entry guard changed.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GuardEvent {
+    pub relay: Step,
+    pub status: GuardEventStatus,
+}
+
+impl NomParse for GuardEvent {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        // `ENTRY` is the only guard type
+        let (rest, (_, _, relay, _, status)) = context(
+            "Guard event",
+            tuple((
+                tag("ENTRY"),
+                space1,
+                server_id,
+                space1,
+                GuardEventStatus::parse,
+            )),
+        )(s)?;
+
+        Ok((rest, Self { relay, status }))
+    }
+}
+impl_from_str!(GuardEvent);
+
+impl fmt::Display for GuardEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ENTRY {} {}", self.relay, self.status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_guards() {
+        let guard: EntryGuard = "$8737307DE84C2621E6399E99123967A9590297F2~Tor0x800 up"
+            .parse()
+            .unwrap();
+        assert_eq!(guard.relay.nickname.as_deref(), Some("Tor0x800"));
+        assert_eq!(guard.status, GuardStatus::Up);
+        assert_eq!(guard.since, None);
+
+        let input = "$243996E46218666C1CADDE17B430EA7F95124F96 down 2021-04-30 13:28:42";
+        let guard: EntryGuard = input.parse().unwrap();
+        assert_eq!(guard.status, GuardStatus::Down);
+        assert_eq!(guard.since.unwrap().hour(), 13);
+
+        let guard: EntryGuard = "243996E46218666C1CADDE17B430EA7F95124F96 never-connected"
+            .parse()
+            .unwrap();
+        assert_eq!(guard.status, GuardStatus::NeverConnected);
+
+        let input = "ENTRY $8737307DE84C2621E6399E99123967A9590297F2~Tor0x800 DROPPED";
+        let event: GuardEvent = input.parse().unwrap();
+        assert_eq!(event.status, GuardEventStatus::Dropped);
+        assert_eq!(event.to_string(), input);
+    }
+}
@@ -0,0 +1,388 @@
+use std::fmt;
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while1};
+use nom::character::complete::{space1, u32 as parse_u32};
+use nom::combinator::{map, opt, verify};
+use nom::error::{context, ContextError, ParseError};
+use nom::sequence::{preceded, tuple};
+
+use crate::tor::circuit::{HsAddress, Step};
+use crate::tor::NomParse;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum HsDescAction {
+    /// A descriptor was requested from a HSDir
+    Requested,
+
+    /// A descriptor is being uploaded to a HSDir
+    Upload,
+
+    Received,
+    Uploaded,
+
+    /// The received descriptor was not newer than the cached one
+    Ignore,
+
+    Failed,
+
+    /// A service built a new descriptor
+    Created,
+}
+
+impl NomParse for HsDescAction {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "HS descriptor action",
+            alt((
+                map(tag("REQUESTED"), |_| Self::Requested),
+                map(tag("UPLOADED"), |_| Self::Uploaded),
+                map(tag("UPLOAD"), |_| Self::Upload),
+                map(tag("RECEIVED"), |_| Self::Received),
+                map(tag("IGNORE"), |_| Self::Ignore),
+                map(tag("FAILED"), |_| Self::Failed),
+                map(tag("CREATED"), |_| Self::Created),
+            )),
+        )(s)
+    }
+}
+impl_from_str!(HsDescAction);
+impl_serde_str!(HsDescAction);
+
+impl fmt::Display for HsDescAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Requested => f.write_str("REQUESTED"),
+            Self::Upload => f.write_str("UPLOAD"),
+            Self::Received => f.write_str("RECEIVED"),
+            Self::Uploaded => f.write_str("UPLOADED"),
+            Self::Ignore => f.write_str("IGNORE"),
+            Self::Failed => f.write_str("FAILED"),
+            Self::Created => f.write_str("CREATED"),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum HsDescAuthType {
+    NoAuth,
+    BasicAuth,
+    StealthAuth,
+    Unknown,
+}
+
+impl NomParse for HsDescAuthType {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "HS descriptor auth type",
+            alt((
+                map(tag("NO_AUTH"), |_| Self::NoAuth),
+                map(tag("BASIC_AUTH"), |_| Self::BasicAuth),
+                map(tag("STEALTH_AUTH"), |_| Self::StealthAuth),
+                map(tag("UNKNOWN"), |_| Self::Unknown),
+            )),
+        )(s)
+    }
+}
+impl_from_str!(HsDescAuthType);
+impl_serde_str!(HsDescAuthType);
+
+impl fmt::Display for HsDescAuthType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoAuth => f.write_str("NO_AUTH"),
+            Self::BasicAuth => f.write_str("BASIC_AUTH"),
+            Self::StealthAuth => f.write_str("STEALTH_AUTH"),
+            Self::Unknown => f.write_str("UNKNOWN"),
+        }
+    }
+}
+
+/// Why a descriptor could not be fetched or uploaded.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum HsDescReason {
+    /// The descriptor was unparseable
+    BadDesc,
+
+    /// The HSDir refused the query
+    QueryRejected,
+
+    /// The HSDir refused the upload
+    UploadRejected,
+
+    /// The HSDir has no such descriptor
+    NotFound,
+
+    /// No HSDir is left to be queried
+    QueryNoHsDir,
+
+    /// Queried too many times in a short period
+    QueryRateLimited,
+
+    Unexpected,
+}
+
+impl NomParse for HsDescReason {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "HS descriptor reason",
+            alt((
+                map(tag("BAD_DESC"), |_| Self::BadDesc),
+                map(tag("QUERY_REJECTED"), |_| Self::QueryRejected),
+                map(tag("UPLOAD_REJECTED"), |_| Self::UploadRejected),
+                map(tag("NOT_FOUND"), |_| Self::NotFound),
+                map(tag("QUERY_NO_HSDIR"), |_| Self::QueryNoHsDir),
+                map(tag("QUERY_RATE_LIMITED"), |_| Self::QueryRateLimited),
+                map(tag("UNEXPECTED"), |_| Self::Unexpected),
+            )),
+        )(s)
+    }
+}
+impl_from_str!(HsDescReason);
+impl_serde_str!(HsDescReason);
+
+impl fmt::Display for HsDescReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadDesc => f.write_str("BAD_DESC"),
+            Self::QueryRejected => f.write_str("QUERY_REJECTED"),
+            Self::UploadRejected => f.write_str("UPLOAD_REJECTED"),
+            Self::NotFound => f.write_str("NOT_FOUND"),
+            Self::QueryNoHsDir => f.write_str("QUERY_NO_HSDIR"),
+            Self::QueryRateLimited => f.write_str("QUERY_RATE_LIMITED"),
+            Self::Unexpected => f.write_str("UNEXPECTED"),
+        }
+    }
+}
+
+/// `UNKNOWN`, or what `parser` parses.
+fn or_unknown<'a, O, E, F>(parser: F) -> impl FnMut(&'a str) -> nom::IResult<&'a str, Option<O>, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+    F: FnMut(&'a str) -> nom::IResult<&'a str, O, E>,
+{
+    alt((map(tag("UNKNOWN"), |_| None), map(parser, Some)))
+}
+
+/// Blinded key (v3) or descriptor ID (v2), not to be mistaken with the
+/// `KEY=value` arguments following it.
+fn descriptor_id<'a, E>(s: &'a str) -> nom::IResult<&'a str, &'a str, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    context(
+        "HS descriptor ID",
+        verify(
+            take_while1(|c: char| !c.is_ascii_whitespace()),
+            |id: &str| !id.contains('='),
+        ),
+    )(s)
+}
+
+fn write_or_unknown<T: fmt::Display>(f: &mut fmt::Formatter<'_>, value: Option<&T>) -> fmt::Result {
+    match value {
+        Some(value) => write!(f, "{value}"),
+        None => f.write_str("UNKNOWN"),
+    }
+}
+
+/// `HS_DESC` event: a step of the fetch or the upload of an onion service
+/// descriptor.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HsDesc {
+    pub action: HsDescAction,
+    pub address: Option<HsAddress>,
+    pub auth_type: HsDescAuthType,
+
+    /// Directory queried or uploaded to
+    pub hs_dir: Option<Step>,
+    pub descriptor_id: Option<String>,
+    pub reason: Option<HsDescReason>,
+    pub replica: Option<u32>,
+    pub hs_dir_index: Option<String>,
+}
+
+impl NomParse for HsDesc {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (action, _, address, _, auth_type, _, hs_dir)) = context(
+            "HS descriptor",
+            tuple((
+                HsDescAction::parse,
+                space1,
+                or_unknown(HsAddress::parse),
+                space1,
+                HsDescAuthType::parse,
+                space1,
+                or_unknown(Step::parse),
+            )),
+        )(s)?;
+        let (rest, descriptor_id) = opt(preceded(space1, descriptor_id))(rest)?;
+        let (rest, reason) = opt(preceded(tag(" REASON="), HsDescReason::parse))(rest)?;
+        let (rest, replica) = opt(preceded(tag(" REPLICA="), parse_u32))(rest)?;
+        let (rest, hs_dir_index) = opt(preceded(
+            tag(" HSDIR_INDEX="),
+            take_while1(|c: char| c.is_ascii_hexdigit()),
+        ))(rest)?;
+
+        Ok((
+            rest,
+            Self {
+                action,
+                address,
+                auth_type,
+                hs_dir,
+                descriptor_id: descriptor_id.map(String::from),
+                reason,
+                replica,
+                hs_dir_index: hs_dir_index.map(String::from),
+            },
+        ))
+    }
+}
+impl_from_str!(HsDesc);
+
+impl fmt::Display for HsDesc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.action)?;
+        write_or_unknown(f, self.address.as_ref())?;
+        write!(f, " {} ", self.auth_type)?;
+        write_or_unknown(f, self.hs_dir.as_ref())?;
+        if let Some(ref descriptor_id) = self.descriptor_id {
+            write!(f, " {descriptor_id}")?;
+        }
+        if let Some(ref reason) = self.reason {
+            write!(f, " REASON={reason}")?;
+        }
+        if let Some(ref replica) = self.replica {
+            write!(f, " REPLICA={replica}")?;
+        }
+        if let Some(ref hs_dir_index) = self.hs_dir_index {
+            write!(f, " HSDIR_INDEX={hs_dir_index}")?;
+        }
+        Ok(())
+    }
+}
+
+/// `HS_DESC_CONTENT` event: a descriptor as received from a HSDir.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HsDescContent {
+    pub address: Option<HsAddress>,
+    pub descriptor_id: Option<String>,
+    pub hs_dir: Option<Step>,
+
+    /// Empty when the HSDir did not have it
+    pub descriptor: String,
+}
+
+impl HsDescContent {
+    pub fn is_found(&self) -> bool {
+        !self.descriptor.is_empty()
+    }
+}
+
+impl NomParse for HsDescContent {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (address, _, descriptor_id, _, hs_dir)) = context(
+            "HS descriptor content",
+            tuple((
+                or_unknown(HsAddress::parse),
+                space1,
+                or_unknown(descriptor_id),
+                space1,
+                or_unknown(Step::parse),
+            )),
+        )(s)?;
+        let descriptor = rest.strip_prefix("\r\n").unwrap_or(rest);
+
+        Ok((
+            "",
+            Self {
+                address,
+                descriptor_id: descriptor_id.map(String::from),
+                hs_dir,
+                descriptor: descriptor.trim_end().replace("\r\n", "\n"),
+            },
+        ))
+    }
+}
+impl_from_str!(HsDescContent);
+
+impl fmt::Display for HsDescContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_or_unknown(f, self.address.as_ref())?;
+        f.write_str(" ")?;
+        write_or_unknown(f, self.descriptor_id.as_ref())?;
+        f.write_str(" ")?;
+        write_or_unknown(f, self.hs_dir.as_ref())?;
+        write!(f, " ({} bytes)", self.descriptor.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE_ID: &str = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad";
+    const HS_DIR: &str = "$14AE2154A26F1D42510F7D5F4E4A9E6C7B8D9E0F~hsdir";
+
+    #[test]
+    fn parse_hs_desc() {
+        let input = format!(
+            "REQUESTED {SERVICE_ID} NO_AUTH {HS_DIR} ZkvlA2G3F2Hf5hzs4jYc7nwdJVIxj34Xv0vAuVjuFQI HSDIR_INDEX=0A1B"
+        );
+        let desc: HsDesc = input.parse().unwrap();
+        assert_eq!(desc.action, HsDescAction::Requested);
+        assert_eq!(desc.address.as_ref().unwrap().to_string(), SERVICE_ID);
+        assert_eq!(
+            desc.hs_dir.as_ref().unwrap().nickname.as_deref(),
+            Some("hsdir")
+        );
+        assert_eq!(
+            desc.descriptor_id.as_deref(),
+            Some("ZkvlA2G3F2Hf5hzs4jYc7nwdJVIxj34Xv0vAuVjuFQI")
+        );
+        assert_eq!(desc.hs_dir_index.as_deref(), Some("0A1B"));
+        assert_eq!(desc.to_string(), input);
+
+        let input = format!("FAILED {SERVICE_ID} NO_AUTH UNKNOWN REASON=QUERY_NO_HSDIR");
+        let desc: HsDesc = input.parse().unwrap();
+        assert_eq!(desc.hs_dir, None);
+        assert_eq!(desc.descriptor_id, None);
+        assert_eq!(desc.reason, Some(HsDescReason::QueryNoHsDir));
+        assert_eq!(desc.to_string(), input);
+
+        let content: HsDescContent = format!(
+            "{SERVICE_ID} ZkvlA2G3F2Hf5hzs4jYc7nwdJVIxj34Xv0vAuVjuFQI {HS_DIR}\r\nhs-descriptor 3\r\ndescriptor-lifetime 180\r\n"
+        )
+        .parse()
+        .unwrap();
+        assert!(content.is_found());
+        assert_eq!(
+            content.descriptor,
+            "hs-descriptor 3\ndescriptor-lifetime 180"
+        );
+
+        let content: HsDescContent = format!("{SERVICE_ID} UNKNOWN {HS_DIR}\r\n")
+            .parse()
+            .unwrap();
+        assert!(!content.is_found());
+    }
+}
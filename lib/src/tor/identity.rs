@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::alphanumeric1;
+use nom::combinator::{map, map_opt, not, opt, peek, verify};
+use nom::error::{context, ContextError, ParseError};
+use nom::multi::count;
+use nom::sequence::{preceded, terminated};
+
+use crate::tor::utils::{base64_word, hex_encode_inplace, parse_hex};
+use crate::tor::NomParse;
+
+/// Relay identity: SHA-1 digest of the relay RSA identity key.
+#[derive(Default, Eq, PartialEq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct RelayFingerprint(pub [u8; 20]);
+
+impl RelayFingerprint {
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+
+    /// Unpadded base64 encoding, as found in consensus documents.
+    pub fn to_base64(&self) -> String {
+        STANDARD_NO_PAD.encode(self.0)
+    }
+
+    pub(crate) fn parse_hex<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "hexadecimal fingerprint",
+            map(count(parse_hex, 20), |bytes| {
+                let mut fingerprint = Self::default();
+                fingerprint.0.copy_from_slice(&bytes[..]);
+                fingerprint
+            }),
+        )(input)
+    }
+
+    pub(crate) fn parse_base64<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "base64 fingerprint",
+            map_opt(verify(base64_word, |s: &str| s.len() == 27), |s: &str| {
+                let bytes = STANDARD_NO_PAD.decode(s).ok()?;
+                let mut fingerprint = Self::default();
+                fingerprint.0.copy_from_slice(bytes.get(..20)?);
+                Some(fingerprint)
+            }),
+        )(input)
+    }
+}
+
+impl AsRef<[u8]> for RelayFingerprint {
+    fn as_ref(&self) -> &[u8] {
+        &self.0[..]
+    }
+}
+
+impl From<[u8; 20]> for RelayFingerprint {
+    fn from(bytes: [u8; 20]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl fmt::Debug for RelayFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RelayFingerprint(")?;
+        hex_encode_inplace(f, self.0)?;
+        f.write_str(")")
+    }
+}
+
+impl fmt::Display for RelayFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        hex_encode_inplace(f, self.0)
+    }
+}
+
+impl NomParse for RelayFingerprint {
+    /// Accepts `hex`, `$hex`, `$hex~nickname` (the nickname is dropped) and unpadded base64.
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "relay fingerprint",
+            alt((
+                terminated(
+                    preceded(opt(tag("$")), Self::parse_hex),
+                    alt((
+                        map(preceded(alt((tag("~"), tag("="))), alphanumeric1), |_| ()),
+                        map(not(peek(base64_word_char)), |_| ()),
+                    )),
+                ),
+                Self::parse_base64,
+            )),
+        )(input)
+    }
+}
+impl_from_str!(RelayFingerprint);
+impl_serde_str!(RelayFingerprint);
+
+fn base64_word_char<'a, E>(input: &'a str) -> nom::IResult<&'a str, char, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    nom::character::complete::satisfy(|c: char| c.is_ascii_alphanumeric() || c == '/' || c == '+')(
+        input,
+    )
+}
+
+/// Relay Ed25519 master identity key.
+#[derive(Default, Eq, PartialEq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct Ed25519Identity(pub [u8; 32]);
+
+impl Ed25519Identity {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Ed25519Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Ed25519Identity")
+            .field(&self.to_string())
+            .finish()
+    }
+}
+
+impl fmt::Display for Ed25519Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&STANDARD_NO_PAD.encode(self.0))
+    }
+}
+
+impl NomParse for Ed25519Identity {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "ed25519 identity",
+            map_opt(
+                verify(base64_word, |s: &str| s.trim_end_matches('=').len() == 43),
+                |s: &str| {
+                    let bytes = STANDARD_NO_PAD.decode(s.trim_end_matches('=')).ok()?;
+                    let mut identity = Self::default();
+                    identity.0.copy_from_slice(bytes.get(..32)?);
+                    Some(identity)
+                },
+            ),
+        )(input)
+    }
+}
+impl_from_str!(Ed25519Identity);
+impl_serde_str!(Ed25519Identity);
+
+/// Known associations between RSA fingerprints and Ed25519 identities.
+///
+/// The two keys are unrelated, so the map is fed from microdescriptors (see
+/// [`crate::TorController::get_ed25519_identity`]).
+#[derive(Debug, Default)]
+pub struct IdentityMap {
+    by_fingerprint: HashMap<RelayFingerprint, Ed25519Identity>,
+    by_ed25519: HashMap<Ed25519Identity, RelayFingerprint>,
+}
+
+impl IdentityMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, fingerprint: RelayFingerprint, ed25519: Ed25519Identity) {
+        if let Some(old) = self.by_fingerprint.insert(fingerprint, ed25519) {
+            self.by_ed25519.remove(&old);
+        }
+        self.by_ed25519.insert(ed25519, fingerprint);
+    }
+
+    pub fn ed25519(&self, fingerprint: &RelayFingerprint) -> Option<&Ed25519Identity> {
+        self.by_fingerprint.get(fingerprint)
+    }
+
+    pub fn fingerprint(&self, ed25519: &Ed25519Identity) -> Option<&RelayFingerprint> {
+        self.by_ed25519.get(ed25519)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_fingerprint.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_fingerprint.is_empty()
+    }
+}
+
+/// Serialized as a map from fingerprints to Ed25519 identities.
+#[cfg(feature = "serde")]
+impl serde::Serialize for IdentityMap {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_map(self.by_fingerprint.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for IdentityMap {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let by_fingerprint =
+            <HashMap<RelayFingerprint, Ed25519Identity> as serde::Deserialize>::deserialize(
+                deserializer,
+            )?;
+        let mut map = Self::new();
+        for (fingerprint, ed25519) in by_fingerprint {
+            map.insert(fingerprint, ed25519);
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_fingerprint() {
+        let fingerprint = RelayFingerprint([
+            0x87, 0x37, 0x30, 0x7d, 0xe8, 0x4c, 0x26, 0x21, 0xe6, 0x39, 0x9e, 0x99, 0x12, 0x39,
+            0x67, 0xa9, 0x59, 0x02, 0x97, 0xf2,
+        ]);
+        for input in [
+            "8737307DE84C2621E6399E99123967A9590297F2",
+            "$8737307de84c2621e6399e99123967a9590297f2",
+            "$8737307DE84C2621E6399E99123967A9590297F2~Tor0x800",
+            "hzcwfehMJiHmOZ6ZEjlnqVkCl/I",
+        ] {
+            assert_eq!(input.parse::<RelayFingerprint>().unwrap(), fingerprint);
+        }
+        assert_eq!(
+            fingerprint.to_string(),
+            "8737307DE84C2621E6399E99123967A9590297F2"
+        );
+        assert_eq!(fingerprint.to_base64(), "hzcwfehMJiHmOZ6ZEjlnqVkCl/I");
+    }
+}
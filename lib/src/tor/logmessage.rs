@@ -0,0 +1,119 @@
+use std::fmt;
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::combinator::map;
+use nom::error::{context, ContextError, ParseError};
+
+use crate::tor::NomParse;
+
+/// Severity of a tor log message, each one is an event of the same name.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum LogSeverity {
+    Debug,
+    Info,
+    Notice,
+    Warn,
+    Error,
+}
+
+impl LogSeverity {
+    pub const ALL: [Self; 5] = [
+        Self::Debug,
+        Self::Info,
+        Self::Notice,
+        Self::Warn,
+        Self::Error,
+    ];
+
+    /// Event name, as given to `SETEVENTS`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO",
+            Self::Notice => "NOTICE",
+            Self::Warn => "WARN",
+            Self::Error => "ERR",
+        }
+    }
+
+    /// This severity and the more severe ones, to subscribe to messages from
+    /// this severity on.
+    pub fn and_above(self) -> impl Iterator<Item = Self> {
+        Self::ALL
+            .into_iter()
+            .filter(move |severity| *severity >= self)
+    }
+}
+
+impl NomParse for LogSeverity {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Log severity",
+            alt((
+                map(tag("DEBUG"), |_| Self::Debug),
+                map(tag("INFO"), |_| Self::Info),
+                map(tag("NOTICE"), |_| Self::Notice),
+                map(tag("WARN"), |_| Self::Warn),
+                map(tag("ERR"), |_| Self::Error),
+            )),
+        )(s)
+    }
+}
+impl_from_str!(LogSeverity);
+impl_serde_str!(LogSeverity);
+
+impl fmt::Display for LogSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `DEBUG`, `INFO`, `NOTICE`, `WARN` and `ERR` events: a line of tor's log.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LogMessage {
+    pub severity: LogSeverity,
+
+    /// Lines are separated by `\n`
+    pub message: String,
+}
+
+impl LogMessage {
+    /// Message of the `severity` event, which body is `data`.
+    pub fn new(severity: LogSeverity, data: &str) -> Self {
+        Self {
+            severity,
+            message: data.trim_end().replace("\r\n", "\n"),
+        }
+    }
+}
+
+impl fmt::Display for LogMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.severity, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_severity() {
+        assert_eq!("ERR".parse::<LogSeverity>().unwrap(), LogSeverity::Error);
+        assert_eq!(
+            LogSeverity::Notice
+                .and_above()
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>(),
+            ["NOTICE", "WARN", "ERR"]
+        );
+        let message = LogMessage::new(LogSeverity::Warn, "first line\r\nsecond line\r\n");
+        assert_eq!(message.message, "first line\nsecond line");
+    }
+}
@@ -12,12 +12,53 @@ macro_rules! impl_from_str {
     };
 }
 
+/// Serializes a type as its `Display` string, and deserializes it with its `NomParse` parser.
+macro_rules! impl_serde_str {
+    ($type:ty) => {
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $type {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                serializer.collect_str(self)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $type {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+                $crate::tor::parse_all(&s).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+pub mod addrmap;
 pub mod auth;
+pub mod bandwidth;
+pub mod buildtimeout;
 pub mod circuit;
+pub mod clientauth;
 pub mod common;
 pub mod conn;
+pub mod event;
+pub mod guard;
+pub mod hsdesc;
+pub mod identity;
+pub mod logmessage;
 pub mod ns;
+pub mod onion;
+pub mod orconn;
+pub mod policy;
 pub mod protocol;
+pub mod routerset;
+pub mod signal;
+pub mod status;
 pub mod stream;
 pub mod utils;
 
@@ -26,3 +67,9 @@ pub trait NomParse: Sized {
     where
         E: ParseError<&'a str> + ContextError<&'a str>;
 }
+
+/// Parses `s` entirely, trailing data is an error.
+#[cfg(feature = "serde")]
+pub(crate) fn parse_all<T: NomParse>(s: &str) -> crate::error::Result<T> {
+    Ok(nom::combinator::all_consuming(T::parse::<nom::error::VerboseError<&str>>)(s)?.1)
+}
@@ -1,5 +1,7 @@
 use std::fmt;
 use std::net::Ipv6Addr;
+use std::str::FromStr;
+use std::time::Duration;
 
 use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
 
@@ -12,6 +14,7 @@ use nom::multi::many0;
 use nom::sequence::tuple;
 
 use crate::tor::common::{Target, Time};
+use crate::tor::identity::RelayFingerprint;
 use crate::tor::utils::{base64_word, word};
 use crate::tor::NomParse;
 
@@ -55,6 +58,23 @@ pub enum OnionRouterFlag {
     V2Dir,
 }
 
+impl OnionRouterFlag {
+    pub const ALL: [OnionRouterFlag; 12] = [
+        OnionRouterFlag::Authority,
+        OnionRouterFlag::BadExit,
+        OnionRouterFlag::Exit,
+        OnionRouterFlag::Fast,
+        OnionRouterFlag::Guard,
+        OnionRouterFlag::HSDir,
+        OnionRouterFlag::NoEdConsensus,
+        OnionRouterFlag::Stable,
+        OnionRouterFlag::StaleDesc,
+        OnionRouterFlag::Running,
+        OnionRouterFlag::Valid,
+        OnionRouterFlag::V2Dir,
+    ];
+}
+
 impl NomParse for OnionRouterFlag {
     fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
     where
@@ -79,6 +99,8 @@ impl NomParse for OnionRouterFlag {
         )(input)
     }
 }
+impl_from_str!(OnionRouterFlag);
+impl_serde_str!(OnionRouterFlag);
 
 impl fmt::Display for OnionRouterFlag {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -135,33 +157,69 @@ impl OnionRouterFlags {
         let flag = 1u32 << (flag as u32);
         self.flags & flag == flag
     }
+
+    /// Returns `true` if every flag of `other` is also set in `self`.
+    pub fn contains(&self, other: OnionRouterFlags) -> bool {
+        self.flags & other.flags == other.flags
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flags == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = OnionRouterFlag> + '_ {
+        OnionRouterFlag::ALL
+            .iter()
+            .copied()
+            .filter(move |flag| self.is_set(*flag))
+    }
+}
+
+impl fmt::Debug for OnionRouterFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl std::iter::FromIterator<OnionRouterFlag> for OnionRouterFlags {
+    fn from_iter<I: IntoIterator<Item = OnionRouterFlag>>(iter: I) -> Self {
+        let mut flags = Self::new();
+        for flag in iter {
+            flags.set(flag);
+        }
+        flags
+    }
+}
+
+/// Serialized as the list of flags set.
+#[cfg(feature = "serde")]
+impl serde::Serialize for OnionRouterFlags {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for OnionRouterFlags {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let flags = <Vec<OnionRouterFlag> as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(flags.into_iter().collect())
+    }
 }
 
 impl fmt::Display for OnionRouterFlags {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let flags = [
-            OnionRouterFlag::Authority,
-            OnionRouterFlag::BadExit,
-            OnionRouterFlag::Exit,
-            OnionRouterFlag::Fast,
-            OnionRouterFlag::Guard,
-            OnionRouterFlag::HSDir,
-            OnionRouterFlag::NoEdConsensus,
-            OnionRouterFlag::Stable,
-            OnionRouterFlag::StaleDesc,
-            OnionRouterFlag::Running,
-            OnionRouterFlag::Valid,
-            OnionRouterFlag::V2Dir,
-        ];
-        let mut first = true;
-        for flag in flags.iter() {
-            if self.is_set(*flag) {
-                if first {
-                    first = false;
-                    write!(f, "{flag}")?;
-                } else {
-                    write!(f, "|{flag}")?;
-                }
+        for (i, flag) in self.iter().enumerate() {
+            if i == 0 {
+                write!(f, "{flag}")?;
+            } else {
+                write!(f, "|{flag}")?;
             }
         }
 
@@ -170,9 +228,11 @@ impl fmt::Display for OnionRouterFlags {
 }
 
 #[derive(Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OnionRouter {
     pub nickname: String,
-    pub identity: [u8; 20],
+    pub identity: RelayFingerprint,
+    #[cfg_attr(feature = "serde", serde(with = "crate::tor::utils::serde_hex"))]
     pub digest: [u8; 20],
     pub publication: Time,
     pub target: Target,
@@ -182,29 +242,28 @@ pub struct OnionRouter {
     pub bandwidth: Option<u32>,
 }
 
+impl OnionRouter {
+    /// Time elapsed since the router descriptor was published.
+    pub fn publication_age(&self) -> Duration {
+        self.publication.elapsed()
+    }
+}
+
 impl fmt::Display for OnionRouter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "$")?;
-        for byte in self.identity.iter() {
-            write!(f, "{:02x}", *byte)?;
-        }
-        write!(f, "~{}", self.nickname)
+        write!(f, "${}~{}", self.identity, self.nickname)
     }
 }
 
 impl fmt::Debug for OnionRouter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut identity = String::with_capacity(self.identity.len() * 2);
-        for byte in self.identity.iter() {
-            identity.push_str(format!("{:02x}", *byte).as_str());
-        }
         let mut digest = String::with_capacity(self.digest.len() * 2);
         for byte in self.digest.iter() {
             digest.push_str(format!("{:02x}", *byte).as_str());
         }
         let mut dbg = f.debug_struct("OnionRouter");
         dbg.field("nickname", &self.nickname)
-            .field("identity", &identity)
+            .field("identity", &self.identity)
             .field("digest", &digest)
             .field("publication", &self.publication)
             .field("target", &self.target);
@@ -231,7 +290,6 @@ impl NomParse for OnionRouter {
                 dest[..src.len()].copy_from_slice(src);
             }
         }
-        let mut identity = [0u8; 20];
         let mut digest = [0u8; 20];
         let mut buf = Vec::new();
 
@@ -241,12 +299,8 @@ impl NomParse for OnionRouter {
         let (rest, (_, nickname)) =
             context("nickname", tuple((space1, map(word, String::from))))(rest)?;
 
-        buf.clear();
-        let (rest, (_, identity64)) = context("identity", tuple((space1, base64_word)))(rest)?;
-        STANDARD_NO_PAD
-            .decode_vec(identity64, &mut buf)
-            .expect("Invalid base64_word function?!");
-        copy_slice(&mut identity[..], &buf[..]);
+        let (rest, (_, identity)) =
+            context("identity", tuple((space1, RelayFingerprint::parse_base64)))(rest)?;
 
         buf.clear();
         let (rest, (_, digest64)) = context("digest", tuple((space1, base64_word)))(rest)?;
@@ -315,23 +369,15 @@ mod tests {
         let input = "r Tor0x800 hzcwfehMJiHmOZ6ZEjlnqVkCl/I psMf4zW8kU7rScOKz7Qowqe63oc 2021-05-01 01:11:24 185.80.30.102 9001 9030\n";
         let or = OnionRouter {
             nickname: "Tor0x800".into(),
-            identity: [
+            identity: RelayFingerprint([
                 0x87, 0x37, 0x30, 0x7d, 0xe8, 0x4c, 0x26, 0x21, 0xe6, 0x39, 0x9e, 0x99, 0x12, 0x39,
                 0x67, 0xa9, 0x59, 0x02, 0x97, 0xf2,
-            ],
+            ]),
             digest: [
                 0xa6, 0xc3, 0x1f, 0xe3, 0x35, 0xbc, 0x91, 0x4e, 0xeb, 0x49, 0xc3, 0x8a, 0xcf, 0xb4,
                 0x28, 0xc2, 0xa7, 0xba, 0xde, 0x87,
             ],
-            publication: Time {
-                year: 2021,
-                month: 5,
-                day: 1,
-                hour: 1,
-                minute: 11,
-                second: 24,
-                mseconds: 0,
-            },
+            publication: Time::new(2021, 5, 1, 1, 11, 24, 0).unwrap(),
             target: Target {
                 addr: crate::tor::common::HostOrAddr::Addr(IpAddr::V4(Ipv4Addr::new(
                     185, 80, 30, 102,
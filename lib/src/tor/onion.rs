@@ -0,0 +1,272 @@
+use std::fmt;
+use std::str::FromStr;
+
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD},
+    Engine,
+};
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::combinator::{map, map_opt};
+use nom::error::{context, ContextError, ParseError};
+use nom::sequence::preceded;
+
+use crate::error::Error;
+use crate::tor::circuit::HsAddress;
+use crate::tor::clientauth::X25519PublicKey;
+use crate::tor::utils::{base64_word, parse_single_key_value};
+use crate::tor::NomParse;
+
+/// Private key of an onion service, given to and returned by `ADD_ONION`.
+#[derive(Eq, PartialEq, Clone)]
+pub enum OnionKey {
+    /// Let tor generate a new ed25519 key
+    New,
+
+    /// Expanded ed25519 secret key
+    Ed25519V3([u8; 64]),
+}
+
+impl fmt::Debug for OnionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::New => f.write_str("OnionKey::New"),
+            Self::Ed25519V3(_) => f.write_str("OnionKey::Ed25519V3(..)"),
+        }
+    }
+}
+
+impl fmt::Display for OnionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::New => f.write_str("NEW:ED25519-V3"),
+            Self::Ed25519V3(key) => write!(f, "ED25519-V3:{}", STANDARD.encode(key)),
+        }
+    }
+}
+
+impl NomParse for OnionKey {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Onion key",
+            alt((
+                map(alt((tag("NEW:ED25519-V3"), tag("NEW:BEST"))), |_| Self::New),
+                map_opt(preceded(tag("ED25519-V3:"), base64_word), |blob: &str| {
+                    let bytes = STANDARD_NO_PAD.decode(blob.trim_end_matches('=')).ok()?;
+                    Some(Self::Ed25519V3(bytes.try_into().ok()?))
+                }),
+            )),
+        )(input)
+    }
+}
+impl_from_str!(OnionKey);
+impl_serde_str!(OnionKey);
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum OnionFlag {
+    /// Tor does not return the private key
+    DiscardPK,
+
+    /// The service outlives the control connection, until `DEL_ONION`
+    Detach,
+
+    /// Clients need one of the `ClientAuthV3` keys
+    V3Auth,
+
+    /// Single onion service, needs `HiddenServiceNonAnonymousMode`
+    NonAnonymous,
+
+    /// Close the circuit exceeding `MaxStreams` instead of the stream
+    MaxStreamsCloseCircuit,
+}
+
+impl NomParse for OnionFlag {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Onion flag",
+            alt((
+                map(tag("DiscardPK"), |_| Self::DiscardPK),
+                map(tag("Detach"), |_| Self::Detach),
+                map(tag("V3Auth"), |_| Self::V3Auth),
+                map(tag("NonAnonymous"), |_| Self::NonAnonymous),
+                map(tag("MaxStreamsCloseCircuit"), |_| {
+                    Self::MaxStreamsCloseCircuit
+                }),
+            )),
+        )(input)
+    }
+}
+impl_from_str!(OnionFlag);
+impl_serde_str!(OnionFlag);
+
+impl fmt::Display for OnionFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DiscardPK => f.write_str("DiscardPK"),
+            Self::Detach => f.write_str("Detach"),
+            Self::V3Auth => f.write_str("V3Auth"),
+            Self::NonAnonymous => f.write_str("NonAnonymous"),
+            Self::MaxStreamsCloseCircuit => f.write_str("MaxStreamsCloseCircuit"),
+        }
+    }
+}
+
+/// Virtual port of an onion service, and where its connections go.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OnionPort {
+    pub virt_port: u16,
+
+    /// `host:port` or `unix:path`, the same port on localhost by default
+    pub target: Option<String>,
+}
+
+impl fmt::Display for OnionPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.target {
+            Some(ref target) => write!(f, "{},{}", self.virt_port, target),
+            None => write!(f, "{}", self.virt_port),
+        }
+    }
+}
+
+/// Arguments of `ADD_ONION`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct AddOnion {
+    pub key: OnionKey,
+    pub ports: Vec<OnionPort>,
+    pub flags: Vec<OnionFlag>,
+
+    /// Streams allowed per rendezvous circuit, 0 for no limit
+    pub max_streams: Option<u16>,
+
+    /// Public keys of the authorized clients
+    pub client_auth: Vec<X25519PublicKey>,
+}
+
+impl AddOnion {
+    pub fn new(key: OnionKey) -> Self {
+        Self {
+            key,
+            ports: Vec::new(),
+            flags: Vec::new(),
+            max_streams: None,
+            client_auth: Vec::new(),
+        }
+    }
+
+    pub fn port(mut self, virt_port: u16, target: Option<&str>) -> Self {
+        self.ports.push(OnionPort {
+            virt_port,
+            target: target.map(String::from),
+        });
+        self
+    }
+
+    pub fn flag(mut self, flag: OnionFlag) -> Self {
+        if !self.flags.contains(&flag) {
+            self.flags.push(flag);
+        }
+        self
+    }
+
+    pub fn max_streams(mut self, max_streams: u16) -> Self {
+        self.max_streams = Some(max_streams);
+        self
+    }
+
+    /// Authorizes a client, `V3Auth` is implied.
+    pub fn client_auth(mut self, public_key: X25519PublicKey) -> Self {
+        self.client_auth.push(public_key);
+        self.flag(OnionFlag::V3Auth)
+    }
+}
+
+impl fmt::Display for AddOnion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ADD_ONION {}", self.key)?;
+        for (i, flag) in self.flags.iter().enumerate() {
+            if i == 0 {
+                write!(f, " Flags={flag}")?;
+            } else {
+                write!(f, ",{flag}")?;
+            }
+        }
+        if let Some(max_streams) = self.max_streams {
+            write!(f, " MaxStreams={max_streams}")?;
+        }
+        for port in &self.ports {
+            write!(f, " Port={port}")?;
+        }
+        for client in &self.client_auth {
+            write!(f, " ClientAuthV3={client}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Onion service created by `ADD_ONION`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct OnionService {
+    pub service_id: HsAddress,
+
+    /// Generated key, unless `DiscardPK` was given
+    pub private_key: Option<OnionKey>,
+}
+
+impl OnionService {
+    /// Reads the reply of `ADD_ONION`.
+    pub fn from_reply(data: &str) -> Result<Self, Error> {
+        let mut service_id = None;
+        let mut private_key = None;
+        for line in data.lines() {
+            match parse_single_key_value(line) {
+                Some(("ServiceID", id)) => service_id = Some(id.parse()?),
+                Some(("PrivateKey", key)) => private_key = Some(key.parse()?),
+                _ => {}
+            }
+        }
+        let service_id =
+            service_id.ok_or_else(|| Error::Protocol(format!("No ServiceID in {data:?}")))?;
+        Ok(Self {
+            service_id,
+            private_key,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE_ID: &str = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad";
+
+    #[test]
+    fn add_onion() {
+        let cmd = AddOnion::new(OnionKey::New)
+            .port(80, Some("127.0.0.1:8080"))
+            .port(443, None)
+            .flag(OnionFlag::Detach)
+            .max_streams(10);
+        assert_eq!(
+            cmd.to_string(),
+            "ADD_ONION NEW:ED25519-V3 Flags=Detach MaxStreams=10 Port=80,127.0.0.1:8080 Port=443"
+        );
+
+        let key = format!("ED25519-V3:{}", STANDARD.encode([7u8; 64]));
+        let reply = format!("ServiceID={SERVICE_ID}\r\nPrivateKey={key}\r\nOK\r\n");
+        let service = OnionService::from_reply(&reply).unwrap();
+        assert_eq!(service.service_id.to_string(), SERVICE_ID);
+        assert_eq!(service.private_key, Some(OnionKey::Ed25519V3([7u8; 64])));
+        assert_eq!(service.private_key.unwrap().to_string(), key);
+
+        assert!(OnionService::from_reply("OK\r\n").is_err());
+    }
+}
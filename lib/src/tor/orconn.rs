@@ -0,0 +1,268 @@
+use std::fmt;
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{alphanumeric1, space1, u32 as parse_u32};
+use nom::combinator::{map, opt, verify};
+use nom::error::{context, ContextError, ParseError};
+use nom::sequence::{preceded, tuple};
+
+use crate::tor::circuit::Step;
+use crate::tor::common::Target;
+use crate::tor::identity::RelayFingerprint;
+use crate::tor::NomParse;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum OrConnStatus {
+    New,
+    Launched,
+    Connected,
+    Failed,
+    Closed,
+}
+
+impl NomParse for OrConnStatus {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "OR connection status",
+            alt((
+                map(tag("NEW"), |_| Self::New),
+                map(tag("LAUNCHED"), |_| Self::Launched),
+                map(tag("CONNECTED"), |_| Self::Connected),
+                map(tag("FAILED"), |_| Self::Failed),
+                map(tag("CLOSED"), |_| Self::Closed),
+            )),
+        )(s)
+    }
+}
+impl_from_str!(OrConnStatus);
+impl_serde_str!(OrConnStatus);
+
+impl fmt::Display for OrConnStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::New => f.write_str("NEW"),
+            Self::Launched => f.write_str("LAUNCHED"),
+            Self::Connected => f.write_str("CONNECTED"),
+            Self::Failed => f.write_str("FAILED"),
+            Self::Closed => f.write_str("CLOSED"),
+        }
+    }
+}
+
+/// Why an OR connection failed or was closed.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum OrConnReason {
+    Misc,
+    Done,
+    ConnectRefused,
+    Identity,
+    ConnectReset,
+    Timeout,
+    NoRoute,
+    IoError,
+    ResourceLimit,
+    PtMissing,
+}
+
+impl NomParse for OrConnReason {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "OR connection reason",
+            alt((
+                map(tag("MISC"), |_| Self::Misc),
+                map(tag("DONE"), |_| Self::Done),
+                map(tag("CONNECTREFUSED"), |_| Self::ConnectRefused),
+                map(tag("IDENTITY"), |_| Self::Identity),
+                map(tag("CONNECTRESET"), |_| Self::ConnectReset),
+                map(tag("TIMEOUT"), |_| Self::Timeout),
+                map(tag("NOROUTE"), |_| Self::NoRoute),
+                map(tag("IOERROR"), |_| Self::IoError),
+                map(tag("RESOURCELIMIT"), |_| Self::ResourceLimit),
+                map(tag("PT_MISSING"), |_| Self::PtMissing),
+            )),
+        )(s)
+    }
+}
+impl_from_str!(OrConnReason);
+impl_serde_str!(OrConnReason);
+
+impl fmt::Display for OrConnReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Misc => f.write_str("MISC"),
+            Self::Done => f.write_str("DONE"),
+            Self::ConnectRefused => f.write_str("CONNECTREFUSED"),
+            Self::Identity => f.write_str("IDENTITY"),
+            Self::ConnectReset => f.write_str("CONNECTRESET"),
+            Self::Timeout => f.write_str("TIMEOUT"),
+            Self::NoRoute => f.write_str("NOROUTE"),
+            Self::IoError => f.write_str("IOERROR"),
+            Self::ResourceLimit => f.write_str("RESOURCELIMIT"),
+            Self::PtMissing => f.write_str("PT_MISSING"),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct OrConnID(pub String);
+
+impl NomParse for OrConnID {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "OR connection ID",
+            map(
+                verify(alphanumeric1, |id: &str| id.len() <= 16),
+                |id: &str| Self(id.into()),
+            ),
+        )(input)
+    }
+}
+
+impl fmt::Display for OrConnID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Relay at the other end of an OR connection, only known by its address
+/// until the TLS handshake is done.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OrConnTarget {
+    Relay(Step),
+    Address(Target),
+}
+
+impl NomParse for OrConnTarget {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "OR connection target",
+            alt((
+                map(Step::parse, Self::Relay),
+                map(Target::parse, Self::Address),
+            )),
+        )(s)
+    }
+}
+
+impl fmt::Display for OrConnTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Relay(step) => write!(f, "{step}"),
+            Self::Address(target) => write!(f, "{target}"),
+        }
+    }
+}
+
+/// A TLS connection to a relay, from `orconn-status` or an `ORCONN` event.
+///
+/// `orconn-status` only gives the target and the status.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrConnection {
+    pub target: OrConnTarget,
+    pub status: OrConnStatus,
+    pub reason: Option<OrConnReason>,
+
+    /// Number of circuits using the connection
+    pub ncircs: Option<u32>,
+    pub id: Option<OrConnID>,
+}
+
+impl OrConnection {
+    pub fn fingerprint(&self) -> Option<&RelayFingerprint> {
+        match self.target {
+            OrConnTarget::Relay(ref step) => Some(&step.fingerprint),
+            OrConnTarget::Address(_) => None,
+        }
+    }
+}
+
+impl NomParse for OrConnection {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (target, _, status)) = context(
+            "OR connection",
+            tuple((OrConnTarget::parse, space1, OrConnStatus::parse)),
+        )(s)?;
+        let (rest, reason) = opt(preceded(tag(" REASON="), OrConnReason::parse))(rest)?;
+        let (rest, ncircs) = opt(preceded(tag(" NCIRCS="), parse_u32))(rest)?;
+        let (rest, id) = opt(preceded(tag(" ID="), OrConnID::parse))(rest)?;
+
+        Ok((
+            rest,
+            Self {
+                target,
+                status,
+                reason,
+                ncircs,
+                id,
+            },
+        ))
+    }
+}
+impl_from_str!(OrConnection);
+
+impl fmt::Display for OrConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.target, self.status)?;
+        if let Some(reason) = self.reason {
+            write!(f, " REASON={reason}")?;
+        }
+        if let Some(ncircs) = self.ncircs {
+            write!(f, " NCIRCS={ncircs}")?;
+        }
+        if let Some(ref id) = self.id {
+            write!(f, " ID={id}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_or_connection() {
+        let input =
+            "$8737307DE84C2621E6399E99123967A9590297F2~relay CLOSED REASON=DONE NCIRCS=2 ID=14";
+        let orconn: OrConnection = input.parse().unwrap();
+        assert_eq!(orconn.status, OrConnStatus::Closed);
+        assert_eq!(orconn.reason, Some(OrConnReason::Done));
+        assert_eq!(orconn.ncircs, Some(2));
+        assert_eq!(orconn.id, Some(OrConnID("14".into())));
+        assert_eq!(
+            orconn.fingerprint().unwrap().to_string(),
+            "8737307DE84C2621E6399E99123967A9590297F2"
+        );
+        assert_eq!(orconn.to_string(), input);
+
+        let orconn: OrConnection = "192.0.2.1:9001 LAUNCHED ID=15".parse().unwrap();
+        assert_eq!(orconn.fingerprint(), None);
+        assert_eq!(orconn.status, OrConnStatus::Launched);
+        assert_eq!(orconn.reason, None);
+        assert_eq!(orconn.to_string(), "192.0.2.1:9001 LAUNCHED ID=15");
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{space1, u16 as parse_u16};
+use nom::combinator::{map, opt, verify};
+use nom::error::{context, ContextError, ParseError};
+use nom::multi::separated_list1;
+use nom::sequence::{preceded, separated_pair, tuple};
+
+use crate::tor::common::{HostOrAddr, Target};
+use crate::tor::NomParse;
+
+/// Exit policy summary of a microdescriptor: `accept 80,443,6660-6669` or
+/// `reject 1-65535`.
+///
+/// Summaries only list the ports a relay exits to for most addresses, private
+/// networks and the few addresses a relay rejects are left out.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct PortPolicy {
+    pub accept: bool,
+
+    /// Inclusive port ranges
+    pub ports: Vec<(u16, u16)>,
+}
+
+impl PortPolicy {
+    pub fn allows(&self, port: u16) -> bool {
+        let listed = self
+            .ports
+            .iter()
+            .any(|(low, high)| (*low..=*high).contains(&port));
+        listed == self.accept
+    }
+}
+
+impl NomParse for PortPolicy {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (accept, ports)) = context(
+            "port policy",
+            separated_pair(
+                alt((map(tag("accept"), |_| true), map(tag("reject"), |_| false))),
+                space1,
+                separated_list1(
+                    tag(","),
+                    map(
+                        verify(
+                            tuple((parse_u16, opt(preceded(tag("-"), parse_u16)))),
+                            |(low, high)| high.is_none_or(|high| *low <= high),
+                        ),
+                        |(low, high)| (low, high.unwrap_or(low)),
+                    ),
+                ),
+            ),
+        )(input)?;
+        Ok((rest, Self { accept, ports }))
+    }
+}
+impl_from_str!(PortPolicy);
+impl_serde_str!(PortPolicy);
+
+impl fmt::Display for PortPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.accept { "accept " } else { "reject " })?;
+        for (i, (low, high)) in self.ports.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            if low == high {
+                write!(f, "{low}")?;
+            } else {
+                write!(f, "{low}-{high}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Exit policy summaries of a relay, from the `p` and `p6` lines of its
+/// microdescriptor. A missing summary rejects everything.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExitPolicy {
+    pub ipv4: Option<PortPolicy>,
+    pub ipv6: Option<PortPolicy>,
+}
+
+impl ExitPolicy {
+    /// Reads the summaries of a microdescriptor, other lines are ignored.
+    pub fn from_microdescriptor(md: &str) -> crate::error::Result<Self> {
+        let mut policy = Self::default();
+        for line in md.lines() {
+            if let Some(summary) = line.strip_prefix("p ") {
+                policy.ipv4 = Some(summary.trim().parse()?);
+            } else if let Some(summary) = line.strip_prefix("p6 ") {
+                policy.ipv6 = Some(summary.trim().parse()?);
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Whether the relay may exit to `target`, hostnames being resolved by
+    /// the exit as IPv4 addresses.
+    pub fn allows(&self, target: &Target) -> bool {
+        let summary = match target.addr {
+            HostOrAddr::Addr(IpAddr::V6(_)) => &self.ipv6,
+            _ => &self.ipv4,
+        };
+        summary
+            .as_ref()
+            .is_some_and(|summary| summary.allows(target.port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_policy() {
+        let md = "onion-key\n\
+            ntor-onion-key Sb9SfGBK4mFvzm6x3NlMDSk1R4cqOLUXi8SGLkAZ+2s\n\
+            p accept 80,443,6660-6669\n\
+            p6 reject 1-65535\n\
+            id ed25519 zO3zGbtzWUwGvMLydLRdTSEHaeCSzOTxUAqj3M3bMXE\n";
+        let policy = ExitPolicy::from_microdescriptor(md).unwrap();
+        assert_eq!(
+            policy.ipv4,
+            Some(PortPolicy {
+                accept: true,
+                ports: vec![(80, 80), (443, 443), (6660, 6669)],
+            })
+        );
+        assert_eq!(policy.ipv6.as_ref().unwrap().to_string(), "reject 1-65535");
+
+        let target = |s: &str| s.parse::<Target>().unwrap();
+        assert!(policy.allows(&target("example.com:443")));
+        assert!(policy.allows(&target("192.0.2.1:6667")));
+        assert!(!policy.allows(&target("example.com:22")));
+        assert!(!policy.allows(&target("[2001:db8::1]:443")));
+        assert!(!ExitPolicy::default().allows(&target("example.com:443")));
+
+        let policy: PortPolicy = "reject 25,119,135-139".parse().unwrap();
+        assert!(policy.allows(443));
+        assert!(!policy.allows(137));
+        assert!("accept 443-80".parse::<PortPolicy>().is_err());
+    }
+}
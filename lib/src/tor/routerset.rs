@@ -0,0 +1,370 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while1, take_while_m_n};
+use nom::character::complete::{alpha1, space0, u16 as parse_u16};
+use nom::combinator::{map, map_opt, not, opt, peek, verify};
+use nom::error::{context, ContextError, ParseError};
+use nom::multi::separated_list0;
+use nom::sequence::{delimited, preceded, separated_pair, terminated, tuple};
+
+use crate::geoip::GeoIP;
+use crate::tor::circuit::Step;
+use crate::tor::common::{Cidr, HostOrAddr};
+use crate::tor::identity::RelayFingerprint;
+use crate::tor::ns::OnionRouter;
+use crate::tor::NomParse;
+
+/// Address pattern of tor's address policies: `*`, `*4`, `*6`, `1.2.3.*`,
+/// `addr/bits` or `addr/netmask`, optionally followed by `:port`, `:low-high`
+/// or `:*`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct AddressPattern {
+    /// `None` for any address
+    pub network: Option<Cidr>,
+
+    /// Inclusive range of OR ports, `None` for any port
+    pub ports: Option<(u16, u16)>,
+}
+
+impl AddressPattern {
+    pub fn matches(&self, addr: &IpAddr, port: u16) -> bool {
+        self.network.is_none_or(|network| network.contains(addr)) && self.matches_port(port)
+    }
+
+    fn matches_port(&self, port: u16) -> bool {
+        self.ports
+            .is_none_or(|(low, high)| (low..=high).contains(&port))
+    }
+}
+
+/// `1.2.3.*`, `1.2.*.*`...
+fn ipv4_wildcard<'a, E>(input: &'a str) -> nom::IResult<&'a str, Cidr, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    map_opt(
+        take_while1(|c: char| c.is_ascii_digit() || c == '.' || c == '*'),
+        |s: &str| {
+            let parts: Vec<&str> = s.split('.').collect();
+            let fixed = parts.iter().take_while(|part| **part != "*").count();
+            if parts.len() != 4 || fixed == 4 || parts[fixed..].iter().any(|part| *part != "*") {
+                return None;
+            }
+            let mut octets = [0u8; 4];
+            for (octet, part) in octets.iter_mut().zip(&parts[..fixed]) {
+                *octet = part.parse().ok()?;
+            }
+            Cidr::new(IpAddr::V4(Ipv4Addr::from(octets)), fixed as u8 * 8)
+        },
+    )(input)
+}
+
+/// `1.2.0.0/255.255.0.0`, the mask bits must be contiguous.
+fn ipv4_netmask<'a, E>(input: &'a str) -> nom::IResult<&'a str, Cidr, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str>,
+{
+    let ipv4 = || {
+        map_opt(
+            take_while1(|c: char| c.is_ascii_digit() || c == '.'),
+            |s: &str| s.parse::<Ipv4Addr>().ok(),
+        )
+    };
+    map_opt(separated_pair(ipv4(), tag("/"), ipv4()), |(addr, mask)| {
+        let mask = u32::from(mask);
+        if mask.leading_ones() + mask.trailing_zeros() != 32 {
+            return None;
+        }
+        Cidr::new(IpAddr::V4(addr), mask.leading_ones() as u8)
+    })(input)
+}
+
+impl NomParse for AddressPattern {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let any4 = Cidr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+        let any6 = Cidr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0);
+        let (rest, (network, ports)) = context(
+            "address pattern",
+            tuple((
+                alt((
+                    map(tag("*4"), |_| any4),
+                    map(tag("*6"), |_| any6),
+                    map(tag("*"), |_| None),
+                    map(ipv4_wildcard, Some),
+                    map(ipv4_netmask, Some),
+                    map(Cidr::parse, Some),
+                )),
+                opt(preceded(
+                    tag(":"),
+                    alt((
+                        map(tag("*"), |_| None),
+                        map(
+                            verify(
+                                tuple((parse_u16, opt(preceded(tag("-"), parse_u16)))),
+                                |(low, high)| high.is_none_or(|high| *low <= high),
+                            ),
+                            |(low, high)| Some((low, high.unwrap_or(low))),
+                        ),
+                    )),
+                )),
+            )),
+        )(input)?;
+
+        Ok((
+            rest,
+            Self {
+                network,
+                ports: ports.flatten(),
+            },
+        ))
+    }
+}
+impl_from_str!(AddressPattern);
+impl_serde_str!(AddressPattern);
+
+impl fmt::Display for AddressPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.network {
+            Some(ref network) => write!(f, "{network}")?,
+            None => f.write_str("*")?,
+        }
+        match self.ports {
+            Some((low, high)) if low == high => write!(f, ":{low}"),
+            Some((low, high)) => write!(f, ":{low}-{high}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// One element of a node specification, as used by `ExitNodes`, `ExcludeNodes`...
+#[derive(Debug, Eq, PartialEq)]
+pub enum RouterSetEntry {
+    /// `$fingerprint`, `$fingerprint~nickname` or a bare fingerprint
+    Identity(Step),
+
+    /// Relay nickname, compared case-insensitively
+    Nickname(String),
+
+    /// `{cc}` country code (upper case), `??` stands for unknown countries
+    Country(String),
+
+    /// Address, network or `*`, with the OR ports
+    Address(AddressPattern),
+}
+
+impl RouterSetEntry {
+    pub fn matches(&self, or: &OnionRouter, gi: &GeoIP) -> bool {
+        match self {
+            Self::Identity(step) => {
+                step.fingerprint == or.identity
+                    && step
+                        .nickname
+                        .as_ref()
+                        .map(|n| n.eq_ignore_ascii_case(&or.nickname))
+                        .unwrap_or(true)
+            }
+            Self::Nickname(nickname) => nickname.eq_ignore_ascii_case(&or.nickname),
+            Self::Country(country) => {
+                let or_country = match or.target.addr {
+                    HostOrAddr::Addr(addr) => gi.lookup_ip(addr),
+                    HostOrAddr::Host(_) => None,
+                };
+                match or_country {
+                    Some(cc) => cc.eq_ignore_ascii_case(country),
+                    None => country == "??",
+                }
+            }
+            Self::Address(pattern) => {
+                let ipv6_matches = or
+                    .advertise_ipv6
+                    .map(|(ip6, port)| pattern.matches(&IpAddr::V6(ip6), port))
+                    .unwrap_or(false);
+                match or.target.addr {
+                    HostOrAddr::Addr(ref addr) => {
+                        pattern.matches(addr, or.target.port) || ipv6_matches
+                    }
+                    HostOrAddr::Host(_) => {
+                        ipv6_matches
+                            || pattern.network.is_none() && pattern.matches_port(or.target.port)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl NomParse for RouterSetEntry {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let alnum = |c: char| c.is_ascii_alphanumeric();
+        context(
+            "node specification",
+            alt((
+                map(
+                    delimited(tag("{"), alt((alpha1, tag("??"))), tag("}")),
+                    |cc: &str| Self::Country(cc.to_uppercase()),
+                ),
+                map(Step::parse, Self::Identity),
+                map(
+                    terminated(RelayFingerprint::parse_hex, not(peek(take_while1(alnum)))),
+                    |fingerprint| {
+                        Self::Identity(Step {
+                            fingerprint,
+                            nickname: None,
+                        })
+                    },
+                ),
+                map(
+                    terminated(AddressPattern::parse, not(peek(take_while1(alnum)))),
+                    Self::Address,
+                ),
+                map(
+                    terminated(take_while_m_n(1, 19, alnum), not(peek(take_while1(alnum)))),
+                    |nickname: &str| Self::Nickname(nickname.into()),
+                ),
+            )),
+        )(input)
+    }
+}
+impl_from_str!(RouterSetEntry);
+impl_serde_str!(RouterSetEntry);
+
+impl fmt::Display for RouterSetEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Identity(step) => write!(f, "{step}"),
+            Self::Nickname(nickname) => f.write_str(nickname),
+            Self::Country(country) => write!(f, "{{{}}}", country.to_lowercase()),
+            Self::Address(pattern) => write!(f, "{pattern}"),
+        }
+    }
+}
+
+/// Tor's routerset syntax: a comma separated list of node specifications
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct RouterSet(pub Vec<RouterSetEntry>);
+
+impl RouterSet {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns `true` if `or` belongs to the set.
+    pub fn contains(&self, or: &OnionRouter, gi: &GeoIP) -> bool {
+        self.0.iter().any(|entry| entry.matches(or, gi))
+    }
+
+    /// Keeps the routers belonging to the set.
+    pub fn filter<'a>(&self, ors: &'a [OnionRouter], gi: &GeoIP) -> Vec<&'a OnionRouter> {
+        ors.iter().filter(|or| self.contains(or, gi)).collect()
+    }
+}
+
+impl NomParse for RouterSet {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, entries) = delimited(
+            space0,
+            separated_list0(tuple((space0, tag(","), space0)), RouterSetEntry::parse),
+            space0,
+        )(input)?;
+        Ok((rest, Self(entries)))
+    }
+}
+impl_from_str!(RouterSet);
+impl_serde_str!(RouterSet);
+
+impl fmt::Display for RouterSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, entry) in self.0.iter().enumerate() {
+            if i == 0 {
+                write!(f, "{entry}")?;
+            } else {
+                write!(f, ",{entry}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_router_set() {
+        let input = "$8737307DE84C2621E6399E99123967A9590297F2~Tor0x800, {de},{??},GoofyRooster,\
+            185.80.0.0/16,243996E46218666C1CADDE17B430EA7F95124F96";
+        let fp1 = RelayFingerprint([
+            0x87, 0x37, 0x30, 0x7d, 0xe8, 0x4c, 0x26, 0x21, 0xe6, 0x39, 0x9e, 0x99, 0x12, 0x39,
+            0x67, 0xa9, 0x59, 0x02, 0x97, 0xf2,
+        ]);
+        let fp2 = RelayFingerprint([
+            0x24, 0x39, 0x96, 0xe4, 0x62, 0x18, 0x66, 0x6c, 0x1c, 0xad, 0xde, 0x17, 0xb4, 0x30,
+            0xea, 0x7f, 0x95, 0x12, 0x4f, 0x96,
+        ]);
+        let router_set = RouterSet(vec![
+            RouterSetEntry::Identity(Step {
+                fingerprint: fp1,
+                nickname: Some("Tor0x800".into()),
+            }),
+            RouterSetEntry::Country("DE".into()),
+            RouterSetEntry::Country("??".into()),
+            RouterSetEntry::Nickname("GoofyRooster".into()),
+            RouterSetEntry::Address(AddressPattern {
+                network: Cidr::new(IpAddr::V4(Ipv4Addr::new(185, 80, 0, 0)), 16),
+                ports: None,
+            }),
+            RouterSetEntry::Identity(Step {
+                fingerprint: fp2,
+                nickname: None,
+            }),
+        ]);
+
+        assert_eq!(
+            RouterSet::parse::<nom::error::VerboseError<&str>>(input),
+            Ok(("", router_set))
+        );
+        assert_eq!(
+            input.parse::<RouterSet>().unwrap().to_string(),
+            "$8737307DE84C2621E6399E99123967A9590297F2~Tor0x800,{de},{??},GoofyRooster,\
+            185.80.0.0/16,$243996E46218666C1CADDE17B430EA7F95124F96"
+        );
+    }
+
+    #[test]
+    fn parse_address_patterns() {
+        let set: RouterSet = "1.2.3.*, 10.0.0.0/255.0.0.0:9001, [2001:db8::]/32:443-9001, *:80, *6"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            set.to_string(),
+            "1.2.3.0/24,10.0.0.0/8:9001,[2001:db8::]/32:443-9001,*:80,[::]/0"
+        );
+
+        let pattern: AddressPattern = "10.0.0.0/255.0.0.0:9001-9030".parse().unwrap();
+        assert!(pattern.matches(&"10.1.2.3".parse().unwrap(), 9001));
+        assert!(!pattern.matches(&"10.1.2.3".parse().unwrap(), 443));
+        assert!(!pattern.matches(&"11.1.2.3".parse().unwrap(), 9001));
+        let any: AddressPattern = "*".parse().unwrap();
+        assert!(any.matches(&"2001:db8::1".parse().unwrap(), 1));
+
+        // Non contiguous netmasks, wildcards in the middle, reversed port ranges
+        for input in ["10.0.0.0/255.0.255.0", "1.*.3.*", "1.2.3.4:443-80"] {
+            let result = nom::combinator::all_consuming(
+                RouterSet::parse::<nom::error::VerboseError<&str>>,
+            )(input);
+            assert!(result.is_err(), "{input} should be rejected");
+        }
+    }
+}
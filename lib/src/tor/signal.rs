@@ -0,0 +1,108 @@
+use std::fmt;
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::tag_no_case;
+use nom::combinator::map;
+use nom::error::{context, ContextError, ParseError};
+
+use crate::tor::NomParse;
+
+/// Signals accepted by the `SIGNAL` command.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Signal {
+    /// Reload configuration (`HUP`)
+    Reload,
+
+    /// Controlled shutdown (`INT`)
+    Shutdown,
+
+    /// Dump stats (`USR1`)
+    Dump,
+
+    /// Switch all open logs to loglevel debug (`USR2`)
+    Debug,
+
+    /// Immediate shutdown (`TERM`)
+    Halt,
+
+    /// Forget the client-side cached IPs for all hostnames
+    ClearDnsCache,
+
+    /// Switch to clean circuits, so new requests don't share circuits with old ones
+    NewNym,
+
+    /// Make tor dump an unscheduled heartbeat message to log
+    Heartbeat,
+
+    /// Tell tor to become dormant
+    Dormant,
+
+    /// Tell tor to stop being dormant
+    Active,
+}
+
+impl NomParse for Signal {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "signal",
+            alt((
+                map(alt((tag_no_case("RELOAD"), tag_no_case("HUP"))), |_| {
+                    Self::Reload
+                }),
+                map(alt((tag_no_case("SHUTDOWN"), tag_no_case("INT"))), |_| {
+                    Self::Shutdown
+                }),
+                map(alt((tag_no_case("DUMP"), tag_no_case("USR1"))), |_| {
+                    Self::Dump
+                }),
+                map(alt((tag_no_case("DEBUG"), tag_no_case("USR2"))), |_| {
+                    Self::Debug
+                }),
+                map(alt((tag_no_case("HALT"), tag_no_case("TERM"))), |_| {
+                    Self::Halt
+                }),
+                map(tag_no_case("CLEARDNSCACHE"), |_| Self::ClearDnsCache),
+                map(tag_no_case("NEWNYM"), |_| Self::NewNym),
+                map(tag_no_case("HEARTBEAT"), |_| Self::Heartbeat),
+                map(tag_no_case("DORMANT"), |_| Self::Dormant),
+                map(tag_no_case("ACTIVE"), |_| Self::Active),
+            )),
+        )(input)
+    }
+}
+impl_from_str!(Signal);
+impl_serde_str!(Signal);
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reload => f.write_str("RELOAD"),
+            Self::Shutdown => f.write_str("SHUTDOWN"),
+            Self::Dump => f.write_str("DUMP"),
+            Self::Debug => f.write_str("DEBUG"),
+            Self::Halt => f.write_str("HALT"),
+            Self::ClearDnsCache => f.write_str("CLEARDNSCACHE"),
+            Self::NewNym => f.write_str("NEWNYM"),
+            Self::Heartbeat => f.write_str("HEARTBEAT"),
+            Self::Dormant => f.write_str("DORMANT"),
+            Self::Active => f.write_str("ACTIVE"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_signal() {
+        assert_eq!("NEWNYM".parse::<Signal>().unwrap(), Signal::NewNym);
+        assert_eq!("newnym".parse::<Signal>().unwrap(), Signal::NewNym);
+        assert_eq!("hup".parse::<Signal>().unwrap(), Signal::Reload);
+        assert_eq!(Signal::ClearDnsCache.to_string(), "CLEARDNSCACHE");
+    }
+}
@@ -0,0 +1,429 @@
+use std::fmt;
+use std::str::FromStr;
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while1};
+use nom::character::complete::space1;
+use nom::combinator::map;
+use nom::error::{context, ContextError, ErrorKind, ParseError};
+use nom::multi::many0;
+use nom::sequence::{preceded, separated_pair, tuple};
+
+use crate::tor::utils::{quoted_string, word};
+use crate::tor::NomParse;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum StatusSeverity {
+    Notice,
+    Warn,
+    Error,
+}
+
+impl NomParse for StatusSeverity {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Status severity",
+            alt((
+                map(tag("NOTICE"), |_| Self::Notice),
+                map(tag("WARN"), |_| Self::Warn),
+                map(tag("ERR"), |_| Self::Error),
+            )),
+        )(s)
+    }
+}
+impl_from_str!(StatusSeverity);
+impl_serde_str!(StatusSeverity);
+
+impl fmt::Display for StatusSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Notice => f.write_str("NOTICE"),
+            Self::Warn => f.write_str("WARN"),
+            Self::Error => f.write_str("ERR"),
+        }
+    }
+}
+
+/// Bootstrap progress, from `status/bootstrap-phase` or a `BOOTSTRAP` status.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bootstrap {
+    /// Percentage, 100 once bootstrapped
+    pub progress: u8,
+    pub tag: String,
+    pub summary: String,
+
+    /// Why bootstrapping is stuck
+    pub warning: Option<String>,
+    pub reason: Option<String>,
+    pub count: Option<u32>,
+    pub recommendation: Option<String>,
+    pub host: Option<String>,
+    pub hostaddr: Option<String>,
+}
+
+impl Bootstrap {
+    pub fn is_done(&self) -> bool {
+        self.progress >= 100
+    }
+}
+
+impl fmt::Display for Bootstrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PROGRESS={} TAG={} SUMMARY={}",
+            self.progress,
+            self.tag,
+            Quoted(&self.summary)
+        )?;
+        if let Some(ref warning) = self.warning {
+            write!(f, " WARNING={}", Quoted(warning))?;
+        }
+        if let Some(ref reason) = self.reason {
+            write!(f, " REASON={reason}")?;
+        }
+        if let Some(count) = self.count {
+            write!(f, " COUNT={count}")?;
+        }
+        if let Some(ref recommendation) = self.recommendation {
+            write!(f, " RECOMMENDATION={recommendation}")?;
+        }
+        if let Some(ref host) = self.host {
+            write!(f, " HOST={host}")?;
+        }
+        if let Some(ref hostaddr) = self.hostaddr {
+            write!(f, " HOSTADDR={hostaddr}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a `STATUS_*` event reports, the most common ones have their own variant.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "action", rename_all = "SCREAMING_SNAKE_CASE")
+)]
+pub enum StatusAction {
+    Bootstrap(Bootstrap),
+
+    /// Tor can build circuits again
+    CircuitEstablished,
+    CircuitNotEstablished {
+        reason: String,
+    },
+
+    /// Our clock is `skew` seconds ahead of `source`'s
+    ClockSkew {
+        skew: Option<i64>,
+        source: String,
+    },
+
+    /// Tor noticed the clock jumped by `time` seconds
+    ClockJumped {
+        time: Option<i64>,
+    },
+
+    /// An application connected to a port usually used by plain text protocols
+    DangerousPort {
+        port: u16,
+        result: String,
+    },
+    DangerousSocks {
+        protocol: String,
+        address: String,
+    },
+    SocksBadHostname {
+        hostname: String,
+    },
+    DangerousVersion {
+        current: String,
+        reason: String,
+        recommended: String,
+    },
+
+    /// A directory authority rejected our descriptor
+    BadServerDescriptor {
+        dirauth: String,
+        reason: String,
+    },
+    ConsensusArrived,
+    EnoughDirInfo,
+    NotEnoughDirInfo,
+
+    /// Any action without a dedicated variant
+    Other {
+        name: String,
+        arguments: Vec<(String, String)>,
+    },
+}
+
+impl StatusAction {
+    /// Action name, as sent by tor.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Bootstrap(_) => "BOOTSTRAP",
+            Self::CircuitEstablished => "CIRCUIT_ESTABLISHED",
+            Self::CircuitNotEstablished { .. } => "CIRCUIT_NOT_ESTABLISHED",
+            Self::ClockSkew { .. } => "CLOCK_SKEW",
+            Self::ClockJumped { .. } => "CLOCK_JUMPED",
+            Self::DangerousPort { .. } => "DANGEROUS_PORT",
+            Self::DangerousSocks { .. } => "DANGEROUS_SOCKS",
+            Self::SocksBadHostname { .. } => "SOCKS_BAD_HOSTNAME",
+            Self::DangerousVersion { .. } => "DANGEROUS_VERSION",
+            Self::BadServerDescriptor { .. } => "BAD_SERVER_DESCRIPTOR",
+            Self::ConsensusArrived => "CONSENSUS_ARRIVED",
+            Self::EnoughDirInfo => "ENOUGH_DIR_INFO",
+            Self::NotEnoughDirInfo => "NOT_ENOUGH_DIR_INFO",
+            Self::Other { ref name, .. } => name.as_str(),
+        }
+    }
+
+    fn from_arguments(name: &str, mut arguments: Vec<(String, String)>) -> Option<Self> {
+        let mut take = |key: &str| {
+            arguments
+                .iter()
+                .position(|(k, _)| k == key)
+                .map(|idx| arguments.remove(idx).1)
+        };
+        let action = match name {
+            "BOOTSTRAP" => Self::Bootstrap(Bootstrap {
+                progress: take("PROGRESS")?.parse().ok()?,
+                tag: take("TAG").unwrap_or_default(),
+                summary: take("SUMMARY").unwrap_or_default(),
+                warning: take("WARNING"),
+                reason: take("REASON"),
+                count: take("COUNT").and_then(|c| c.parse().ok()),
+                recommendation: take("RECOMMENDATION"),
+                host: take("HOST"),
+                hostaddr: take("HOSTADDR"),
+            }),
+            "CIRCUIT_ESTABLISHED" => Self::CircuitEstablished,
+            "CIRCUIT_NOT_ESTABLISHED" => Self::CircuitNotEstablished {
+                reason: take("REASON").unwrap_or_default(),
+            },
+            "CLOCK_SKEW" => Self::ClockSkew {
+                skew: take("SKEW").and_then(|s| s.parse().ok()),
+                source: take("SOURCE").unwrap_or_default(),
+            },
+            "CLOCK_JUMPED" => Self::ClockJumped {
+                time: take("TIME").and_then(|t| t.parse().ok()),
+            },
+            "DANGEROUS_PORT" => Self::DangerousPort {
+                port: take("PORT")?.parse().ok()?,
+                result: take("RESULT").unwrap_or_default(),
+            },
+            "DANGEROUS_SOCKS" => Self::DangerousSocks {
+                protocol: take("PROTOCOL").unwrap_or_default(),
+                address: take("ADDRESS").unwrap_or_default(),
+            },
+            "SOCKS_BAD_HOSTNAME" => Self::SocksBadHostname {
+                hostname: take("HOSTNAME").unwrap_or_default(),
+            },
+            "DANGEROUS_VERSION" => Self::DangerousVersion {
+                current: take("CURRENT").unwrap_or_default(),
+                reason: take("REASON").unwrap_or_default(),
+                recommended: take("RECOMMENDED").unwrap_or_default(),
+            },
+            "BAD_SERVER_DESCRIPTOR" => Self::BadServerDescriptor {
+                dirauth: take("DIRAUTH").unwrap_or_default(),
+                reason: take("REASON").unwrap_or_default(),
+            },
+            "CONSENSUS_ARRIVED" => Self::ConsensusArrived,
+            "ENOUGH_DIR_INFO" => Self::EnoughDirInfo,
+            "NOT_ENOUGH_DIR_INFO" => Self::NotEnoughDirInfo,
+            _ => Self::Other {
+                name: name.into(),
+                arguments,
+            },
+        };
+        Some(action)
+    }
+}
+
+impl fmt::Display for StatusAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())?;
+        match self {
+            Self::Bootstrap(bootstrap) => write!(f, " {bootstrap}"),
+            Self::CircuitNotEstablished { reason } => write!(f, " REASON={reason}"),
+            Self::ClockSkew { skew, source } => {
+                if let Some(skew) = skew {
+                    write!(f, " SKEW={skew}")?;
+                }
+                write!(f, " SOURCE={source}")
+            }
+            Self::ClockJumped { time } => match time {
+                Some(time) => write!(f, " TIME={time}"),
+                None => Ok(()),
+            },
+            Self::DangerousPort { port, result } => write!(f, " PORT={port} RESULT={result}"),
+            Self::DangerousSocks { protocol, address } => {
+                write!(f, " PROTOCOL={protocol} ADDRESS={address}")
+            }
+            Self::SocksBadHostname { hostname } => write!(f, " HOSTNAME={}", Quoted(hostname)),
+            Self::DangerousVersion {
+                current,
+                reason,
+                recommended,
+            } => write!(
+                f,
+                " CURRENT={current} REASON={reason} RECOMMENDED={}",
+                Quoted(recommended)
+            ),
+            Self::BadServerDescriptor { dirauth, reason } => {
+                write!(f, " DIRAUTH={dirauth} REASON={}", Quoted(reason))
+            }
+            Self::Other { arguments, .. } => {
+                for (key, value) in arguments {
+                    if value.is_empty() || value.contains([' ', '"']) {
+                        write!(f, " {key}={}", Quoted(value))?;
+                    } else {
+                        write!(f, " {key}={value}")?;
+                    }
+                }
+                Ok(())
+            }
+            Self::CircuitEstablished
+            | Self::ConsensusArrived
+            | Self::EnoughDirInfo
+            | Self::NotEnoughDirInfo => Ok(()),
+        }
+    }
+}
+
+/// Body of a `STATUS_GENERAL`, `STATUS_CLIENT` or `STATUS_SERVER` event.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Status {
+    pub severity: StatusSeverity,
+    pub action: StatusAction,
+}
+
+impl NomParse for Status {
+    fn parse<'a, E>(s: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (rest, (severity, _, name)) = context(
+            "Status",
+            tuple((StatusSeverity::parse, space1, take_while1(is_action_char))),
+        )(s)?;
+        let (rest, arguments) = many0(preceded(
+            space1,
+            separated_pair(
+                map(word, String::from),
+                tag("="),
+                alt((
+                    map(quoted_string, unescape),
+                    map(
+                        take_while1(|c: char| !c.is_ascii_whitespace()),
+                        String::from,
+                    ),
+                )),
+            ),
+        ))(rest)?;
+
+        match StatusAction::from_arguments(name, arguments) {
+            Some(action) => Ok((rest, Self { severity, action })),
+            None => Err(nom::Err::Error(E::add_context(
+                s,
+                "Status arguments",
+                E::from_error_kind(s, ErrorKind::Verify),
+            ))),
+        }
+    }
+}
+impl_from_str!(Status);
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.severity, self.action)
+    }
+}
+
+fn is_action_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("\\\"", "\"").replace("\\\\", "\\")
+}
+
+/// Writes a string between double quotes, escaping it.
+struct Quoted<'a>(&'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\"{}\"",
+            self.0.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bootstrap() {
+        let input = "WARN BOOTSTRAP PROGRESS=10 TAG=conn_done SUMMARY=\"Connected to a relay\" \
+            WARNING=\"Connection refused\" REASON=CONNECTREFUSED COUNT=3 RECOMMENDATION=warn \
+            HOST=8737307DE84C2621E6399E99123967A9590297F2 HOSTADDR=192.0.2.1:9001";
+        let status: Status = input.parse().unwrap();
+        assert_eq!(status.severity, StatusSeverity::Warn);
+        match status.action {
+            StatusAction::Bootstrap(ref bootstrap) => {
+                assert_eq!(bootstrap.progress, 10);
+                assert_eq!(bootstrap.tag, "conn_done");
+                assert_eq!(bootstrap.summary, "Connected to a relay");
+                assert_eq!(bootstrap.warning.as_deref(), Some("Connection refused"));
+                assert_eq!(bootstrap.count, Some(3));
+                assert!(!bootstrap.is_done());
+            }
+            ref action => panic!("Unexpected action {action:?}"),
+        }
+        assert_eq!(status.to_string(), input);
+
+        assert!("NOTICE BOOTSTRAP TAG=done".parse::<Status>().is_err());
+    }
+
+    #[test]
+    fn parse_status() {
+        let status: Status = "WARN CLOCK_SKEW SKEW=-3600 SOURCE=OR:192.0.2.1:9001"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            status.action,
+            StatusAction::ClockSkew {
+                skew: Some(-3600),
+                source: "OR:192.0.2.1:9001".into()
+            }
+        );
+
+        let status: Status = "WARN DANGEROUS_PORT PORT=23 RESULT=REJECT".parse().unwrap();
+        assert_eq!(
+            status.action,
+            StatusAction::DangerousPort {
+                port: 23,
+                result: "REJECT".into()
+            }
+        );
+
+        let status: Status = "NOTICE CIRCUIT_ESTABLISHED".parse().unwrap();
+        assert_eq!(status.action, StatusAction::CircuitEstablished);
+        assert_eq!(status.to_string(), "NOTICE CIRCUIT_ESTABLISHED");
+
+        let input = "NOTICE EXTERNAL_ADDRESS ADDRESS=192.0.2.1 METHOD=DIRSERV";
+        let status: Status = input.parse().unwrap();
+        assert_eq!(status.action.name(), "EXTERNAL_ADDRESS");
+        assert_eq!(status.to_string(), input);
+    }
+}
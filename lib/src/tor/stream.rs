@@ -1,16 +1,18 @@
 use std::fmt;
+use std::str::FromStr;
 
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::character::complete::space1;
 use nom::combinator::{map, opt};
 use nom::error::{context, ContextError, ParseError};
-use nom::sequence::tuple;
+use nom::sequence::{preceded, tuple};
 
 use crate::tor::common::{CircuitID, StreamID, Target};
+use crate::tor::utils::quoted_string;
 use crate::tor::NomParse;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
 pub enum StreamStatus {
     /// New request to connect
     New,
@@ -51,8 +53,8 @@ impl NomParse for StreamStatus {
         context(
             "Circuit status",
             alt((
-                map(tag("NEW"), |_| Self::New),
                 map(tag("NEWRESOLVE"), |_| Self::NewResolve),
+                map(tag("NEW"), |_| Self::New),
                 map(tag("REMAP"), |_| Self::Remap),
                 map(tag("SENTCONNECT"), |_| Self::SentConnect),
                 map(tag("SENTRESOLVE"), |_| Self::SentResolve),
@@ -65,6 +67,7 @@ impl NomParse for StreamStatus {
         )(input)
     }
 }
+impl_serde_str!(StreamStatus);
 
 impl fmt::Display for StreamStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -83,12 +86,220 @@ impl fmt::Display for StreamStatus {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+impl StreamStatus {
+    /// Waiting to be attached to a circuit.
+    pub fn is_pending(&self) -> bool {
+        matches!(
+            self,
+            Self::New | Self::NewResolve | Self::ControllerWait | Self::Detached
+        )
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed | Self::Failed)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum StreamReason {
+    Misc,
+    ResolveFailed,
+    ConnectRefused,
+    ExitPolicy,
+    Destroy,
+    Done,
+    Timeout,
+    NoRoute,
+    Hibernating,
+    Internal,
+    ResourceLimit,
+    ConnReset,
+    TorProtocol,
+    NotDirectory,
+
+    /// The remote side closed the stream, the reason is in `REMOTE_REASON`
+    End,
+
+    /// The target is a private address
+    PrivateAddr,
+}
+
+impl StreamReason {
+    /// Code of the reason in `RELAY_END` cells, `END` and `PRIVATE_ADDR` have none.
+    pub fn code(&self) -> Option<u8> {
+        match self {
+            Self::Misc => Some(1),
+            Self::ResolveFailed => Some(2),
+            Self::ConnectRefused => Some(3),
+            Self::ExitPolicy => Some(4),
+            Self::Destroy => Some(5),
+            Self::Done => Some(6),
+            Self::Timeout => Some(7),
+            Self::NoRoute => Some(8),
+            Self::Hibernating => Some(9),
+            Self::Internal => Some(10),
+            Self::ResourceLimit => Some(11),
+            Self::ConnReset => Some(12),
+            Self::TorProtocol => Some(13),
+            Self::NotDirectory => Some(14),
+            Self::End | Self::PrivateAddr => None,
+        }
+    }
+}
+
+impl NomParse for StreamReason {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Stream reason",
+            alt((
+                map(tag("MISC"), |_| Self::Misc),
+                map(tag("RESOLVEFAILED"), |_| Self::ResolveFailed),
+                map(tag("CONNECTREFUSED"), |_| Self::ConnectRefused),
+                map(tag("EXITPOLICY"), |_| Self::ExitPolicy),
+                map(tag("DESTROY"), |_| Self::Destroy),
+                map(tag("DONE"), |_| Self::Done),
+                map(tag("TIMEOUT"), |_| Self::Timeout),
+                map(tag("NOROUTE"), |_| Self::NoRoute),
+                map(tag("HIBERNATING"), |_| Self::Hibernating),
+                map(tag("INTERNAL"), |_| Self::Internal),
+                map(tag("RESOURCELIMIT"), |_| Self::ResourceLimit),
+                map(tag("CONNRESET"), |_| Self::ConnReset),
+                map(tag("TORPROTOCOL"), |_| Self::TorProtocol),
+                map(tag("NOTDIRECTORY"), |_| Self::NotDirectory),
+                map(tag("END"), |_| Self::End),
+                map(tag("PRIVATE_ADDR"), |_| Self::PrivateAddr),
+            )),
+        )(input)
+    }
+}
+impl_from_str!(StreamReason);
+impl_serde_str!(StreamReason);
+
+impl fmt::Display for StreamReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Misc => f.write_str("MISC"),
+            Self::ResolveFailed => f.write_str("RESOLVEFAILED"),
+            Self::ConnectRefused => f.write_str("CONNECTREFUSED"),
+            Self::ExitPolicy => f.write_str("EXITPOLICY"),
+            Self::Destroy => f.write_str("DESTROY"),
+            Self::Done => f.write_str("DONE"),
+            Self::Timeout => f.write_str("TIMEOUT"),
+            Self::NoRoute => f.write_str("NOROUTE"),
+            Self::Hibernating => f.write_str("HIBERNATING"),
+            Self::Internal => f.write_str("INTERNAL"),
+            Self::ResourceLimit => f.write_str("RESOURCELIMIT"),
+            Self::ConnReset => f.write_str("CONNRESET"),
+            Self::TorProtocol => f.write_str("TORPROTOCOL"),
+            Self::NotDirectory => f.write_str("NOTDIRECTORY"),
+            Self::End => f.write_str("END"),
+            Self::PrivateAddr => f.write_str("PRIVATE_ADDR"),
+        }
+    }
+}
+
+/// Where the answer of a `REMAP` comes from.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum StreamSource {
+    /// Tor's DNS cache
+    Cache,
+
+    /// The exit relay
+    Exit,
+}
+
+impl NomParse for StreamSource {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Stream source",
+            alt((
+                map(tag("CACHE"), |_| Self::Cache),
+                map(tag("EXIT"), |_| Self::Exit),
+            )),
+        )(input)
+    }
+}
+impl_from_str!(StreamSource);
+impl_serde_str!(StreamSource);
+
+impl fmt::Display for StreamSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cache => f.write_str("CACHE"),
+            Self::Exit => f.write_str("EXIT"),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum StreamPurpose {
+    DirFetch,
+    DirUpload,
+    DnsRequest,
+    DirportTest,
+
+    /// Stream of an application
+    User,
+}
+
+impl NomParse for StreamPurpose {
+    fn parse<'a, E>(input: &'a str) -> nom::IResult<&'a str, Self, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "Stream purpose",
+            alt((
+                map(tag("DIR_FETCH"), |_| Self::DirFetch),
+                map(tag("DIR_UPLOAD"), |_| Self::DirUpload),
+                map(tag("DNS_REQUEST"), |_| Self::DnsRequest),
+                map(tag("DIRPORT_TEST"), |_| Self::DirportTest),
+                map(tag("USER"), |_| Self::User),
+            )),
+        )(input)
+    }
+}
+impl_from_str!(StreamPurpose);
+impl_serde_str!(StreamPurpose);
+
+impl fmt::Display for StreamPurpose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DirFetch => f.write_str("DIR_FETCH"),
+            Self::DirUpload => f.write_str("DIR_UPLOAD"),
+            Self::DnsRequest => f.write_str("DNS_REQUEST"),
+            Self::DirportTest => f.write_str("DIRPORT_TEST"),
+            Self::User => f.write_str("USER"),
+        }
+    }
+}
+
+/// Source address of the streams tor opens itself.
+const TOR_INTERNAL: &str = "(Tor_internal):0";
+
+/// A stream, from `stream-status` or a `STREAM` event; only events have the
+/// optional fields.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Stream {
     pub id: StreamID,
     pub status: StreamStatus,
     pub circuit_id: CircuitID,
     pub target: Target,
+    pub reason: Option<StreamReason>,
+    pub remote_reason: Option<StreamReason>,
+    pub source: Option<StreamSource>,
+
+    /// Client of the stream, `None` for the streams tor opens itself
+    pub source_addr: Option<Target>,
+    pub purpose: Option<StreamPurpose>,
+    pub socks_username: Option<String>,
 }
 
 impl NomParse for Stream {
@@ -101,6 +312,16 @@ impl NomParse for Stream {
         let (rest, (_, status)) = tuple((space1, StreamStatus::parse))(rest)?;
         let (rest, (_, circuit_id)) = tuple((space1, CircuitID::parse))(rest)?;
         let (rest, (_, target)) = tuple((space1, Target::parse))(rest)?;
+        let (rest, reason) = opt(preceded(tag(" REASON="), StreamReason::parse))(rest)?;
+        let (rest, remote_reason) =
+            opt(preceded(tag(" REMOTE_REASON="), StreamReason::parse))(rest)?;
+        let (rest, source) = opt(preceded(tag(" SOURCE="), StreamSource::parse))(rest)?;
+        let (rest, source_addr) = opt(preceded(
+            tag(" SOURCE_ADDR="),
+            alt((map(tag(TOR_INTERNAL), |_| None), map(Target::parse, Some))),
+        ))(rest)?;
+        let (rest, purpose) = opt(preceded(tag(" PURPOSE="), StreamPurpose::parse))(rest)?;
+        let (rest, socks_username) = opt(preceded(tag(" SOCKS_USERNAME="), quoted_string))(rest)?;
 
         Ok((
             rest,
@@ -109,7 +330,73 @@ impl NomParse for Stream {
                 status,
                 circuit_id,
                 target,
+                reason,
+                remote_reason,
+                source,
+                source_addr: source_addr.flatten(),
+                purpose,
+                socks_username: socks_username.map(String::from),
             },
         ))
     }
 }
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.id, self.status, self.circuit_id, self.target
+        )?;
+        if let Some(ref reason) = self.reason {
+            write!(f, " REASON={reason}")?;
+        }
+        if let Some(ref remote_reason) = self.remote_reason {
+            write!(f, " REMOTE_REASON={remote_reason}")?;
+        }
+        if let Some(ref source) = self.source {
+            write!(f, " SOURCE={source}")?;
+        }
+        if let Some(ref source_addr) = self.source_addr {
+            write!(f, " SOURCE_ADDR={source_addr}")?;
+        }
+        if let Some(ref purpose) = self.purpose {
+            write!(f, " PURPOSE={purpose}")?;
+        }
+        if let Some(ref socks_username) = self.socks_username {
+            write!(f, " SOCKS_USERNAME=\"{socks_username}\"")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stream() {
+        let input = "42 SUCCEEDED 7 www.torproject.org:443 \
+                     SOURCE_ADDR=127.0.0.1:51234 PURPOSE=USER SOCKS_USERNAME=\"alice\"";
+        let stream = Stream::parse::<nom::error::VerboseError<&str>>(input)
+            .unwrap()
+            .1;
+        assert_eq!(stream.status, StreamStatus::Succeeded);
+        assert_eq!(stream.target.port, 443);
+        assert_eq!(stream.source_addr.as_ref().unwrap().port, 51234);
+        assert_eq!(stream.purpose, Some(StreamPurpose::User));
+        assert_eq!(stream.socks_username.as_deref(), Some("alice"));
+        assert_eq!(stream.to_string(), input);
+
+        let input = "43 CLOSED 7 93.184.216.34:80 REASON=END REMOTE_REASON=DONE \
+                     SOURCE_ADDR=(Tor_internal):0 PURPOSE=DIR_FETCH";
+        let stream = Stream::parse::<nom::error::VerboseError<&str>>(input)
+            .unwrap()
+            .1;
+        assert!(stream.status.is_closed());
+        assert_eq!(stream.reason, Some(StreamReason::End));
+        assert_eq!(stream.remote_reason.and_then(|r| r.code()), Some(6));
+        assert_eq!(stream.source_addr, None);
+        assert_eq!(stream.purpose, Some(StreamPurpose::DirFetch));
+    }
+}
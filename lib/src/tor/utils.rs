@@ -92,3 +92,30 @@ pub(crate) fn parse_single_key_value(s: &str) -> Option<(&str, &str)> {
         None
     }
 }
+
+/// `serde(with = ...)` helper storing byte arrays as hexadecimal strings.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_hex {
+    use nom::combinator::all_consuming;
+    use nom::multi::count;
+
+    pub fn serialize<S, const N: usize>(bytes: &[u8; N], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&super::hex_encode(bytes))
+    }
+
+    pub fn deserialize<'de, D, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        let (_, bytes) =
+            all_consuming(count(super::parse_hex::<nom::error::VerboseError<&str>>, N))(&s)
+                .map_err(|e| serde::de::Error::custom(crate::error::Error::from(e)))?;
+        let mut array = [0u8; N];
+        array.copy_from_slice(&bytes[..]);
+        Ok(array)
+    }
+}
@@ -0,0 +1,361 @@
+//! Circuit lifecycles, rebuilt from `CIRC` events.
+//!
+//! A build failure is blamed on the position being extended to, and on the
+//! last relay reached, which could not extend the circuit any further.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use crate::tor::circuit::{Circuit, CircuitPurpose, CircuitReason, CircuitStatus, Path};
+use crate::tor::common::{CircuitID, Time};
+use crate::tor::event::Event;
+use crate::tor::identity::RelayFingerprint;
+
+/// A status change of a circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Transition {
+    pub time: Time,
+    pub status: CircuitStatus,
+
+    /// Number of hops built at that time
+    pub hops: usize,
+}
+
+/// Everything seen about a circuit, from its launch to its closing.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CircuitHistory {
+    pub id: CircuitID,
+    pub purpose: Option<CircuitPurpose>,
+    pub path: Path,
+    pub time_created: Option<Time>,
+    pub transitions: Vec<Transition>,
+    pub reason: Option<CircuitReason>,
+    pub remote_reason: Option<CircuitReason>,
+}
+
+impl CircuitHistory {
+    fn new(circuit: &Circuit) -> Self {
+        Self {
+            id: circuit.id.clone(),
+            purpose: circuit.purpose,
+            path: Path::default(),
+            time_created: circuit.time_created,
+            transitions: Vec::new(),
+            reason: None,
+            remote_reason: None,
+        }
+    }
+
+    fn time_of(&self, status: CircuitStatus) -> Option<Time> {
+        self.transitions
+            .iter()
+            .find(|t| t.status == status)
+            .map(|t| t.time)
+    }
+
+    pub fn status(&self) -> Option<CircuitStatus> {
+        self.transitions.last().map(|t| t.status)
+    }
+
+    /// When the circuit was launched, or created if the launch was missed.
+    pub fn launched(&self) -> Option<Time> {
+        self.time_of(CircuitStatus::Launched).or(self.time_created)
+    }
+
+    pub fn built(&self) -> Option<Time> {
+        self.time_of(CircuitStatus::Built)
+    }
+
+    pub fn build_time(&self) -> Option<Duration> {
+        self.built()?.duration_since(&self.launched()?)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(
+            self.status(),
+            Some(CircuitStatus::Closed | CircuitStatus::Failed)
+        )
+    }
+
+    /// Whether the circuit failed before being built.
+    pub fn is_failed(&self) -> bool {
+        self.time_of(CircuitStatus::Failed).is_some() && self.built().is_none()
+    }
+}
+
+/// Circuits through a relay, or reaching a position.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FailureStats {
+    pub attempts: u64,
+    pub failures: u64,
+}
+
+impl FailureStats {
+    pub fn failure_rate(&self) -> f64 {
+        if self.attempts == 0 {
+            0.
+        } else {
+            self.failures as f64 / self.attempts as f64
+        }
+    }
+}
+
+/// Follows circuits through their `CIRC` events.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CircuitTracker {
+    capacity: usize,
+    open: HashMap<CircuitID, CircuitHistory>,
+    closed: VecDeque<CircuitHistory>,
+    build_times: VecDeque<Duration>,
+    built: u64,
+    failed: u64,
+    relays: HashMap<RelayFingerprint, FailureStats>,
+    positions: Vec<FailureStats>,
+}
+
+impl Default for CircuitTracker {
+    fn default() -> Self {
+        Self::new(1000)
+    }
+}
+
+impl CircuitTracker {
+    /// Keeps the last `capacity` closed circuits and build times.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            open: HashMap::new(),
+            closed: VecDeque::with_capacity(capacity),
+            build_times: VecDeque::with_capacity(capacity),
+            built: 0,
+            failed: 0,
+            relays: HashMap::new(),
+            positions: Vec::new(),
+        }
+    }
+
+    /// Records `CIRC` events, returns whether the event was one.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::Circuit(circuit) => {
+                self.update(circuit, Time::now());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Records the status of `circuit` as seen at `time`.
+    pub fn update(&mut self, circuit: &Circuit, time: Time) {
+        if circuit.status == CircuitStatus::Closed && !self.open.contains_key(&circuit.id) {
+            // tor closes failed circuits as well
+            if let Some(history) = self.closed.iter_mut().rev().find(|h| h.id == circuit.id) {
+                if history.status() == Some(CircuitStatus::Failed) {
+                    history.transitions.push(Transition {
+                        time,
+                        status: circuit.status,
+                        hops: circuit.path.len(),
+                    });
+                }
+            }
+            return;
+        }
+        let history = self
+            .open
+            .entry(circuit.id.clone())
+            .or_insert_with(|| CircuitHistory::new(circuit));
+        if history.status() == Some(circuit.status) && history.path.len() == circuit.path.len() {
+            return;
+        }
+        history.transitions.push(Transition {
+            time,
+            status: circuit.status,
+            hops: circuit.path.len(),
+        });
+        history.path = circuit.path.clone();
+        if circuit.purpose.is_some() {
+            history.purpose = circuit.purpose;
+        }
+        if circuit.reason.is_some() {
+            history.reason = circuit.reason;
+        }
+        if circuit.remote_reason.is_some() {
+            history.remote_reason = circuit.remote_reason;
+        }
+
+        match circuit.status {
+            CircuitStatus::Built => {
+                let build_time = history.build_time();
+                let path = history.path.clone();
+                if let Some(build_time) = build_time {
+                    if self.build_times.len() == self.capacity {
+                        self.build_times.pop_front();
+                    }
+                    self.build_times.push_back(build_time);
+                }
+                self.built += 1;
+                self.account(&path, false);
+            }
+            CircuitStatus::Closed | CircuitStatus::Failed => {
+                let history = self.open.remove(&circuit.id).unwrap();
+                if history.is_failed() {
+                    self.failed += 1;
+                    self.account(&history.path, true);
+                }
+                if self.closed.len() == self.capacity {
+                    self.closed.pop_front();
+                }
+                self.closed.push_back(history);
+            }
+            _ => {}
+        }
+    }
+
+    fn account(&mut self, path: &Path, failed: bool) {
+        let reached = if failed { path.len() + 1 } else { path.len() };
+        if self.positions.len() < reached {
+            self.positions.resize(reached, FailureStats::default());
+        }
+        for position in &mut self.positions[..reached] {
+            position.attempts += 1;
+        }
+        for step in path.iter() {
+            self.relays.entry(step.fingerprint).or_default().attempts += 1;
+        }
+        if failed {
+            self.positions[path.len()].failures += 1;
+            if let Some(step) = path.last() {
+                self.relays.get_mut(&step.fingerprint).unwrap().failures += 1;
+            }
+        }
+    }
+
+    pub fn open(&self) -> impl Iterator<Item = &CircuitHistory> {
+        self.open.values()
+    }
+
+    pub fn get(&self, id: &CircuitID) -> Option<&CircuitHistory> {
+        self.open
+            .get(id)
+            .or_else(|| self.closed.iter().rev().find(|h| h.id == *id))
+    }
+
+    /// Last closed circuits, oldest first.
+    pub fn closed(&self) -> impl DoubleEndedIterator<Item = &CircuitHistory> {
+        self.closed.iter()
+    }
+
+    /// Last build times, oldest first.
+    pub fn build_times(&self) -> impl DoubleEndedIterator<Item = &Duration> + ExactSizeIterator {
+        self.build_times.iter()
+    }
+
+    /// Build time under which `quantile` (between 0 and 1) of the circuits were built.
+    pub fn build_time_quantile(&self, quantile: f64) -> Option<Duration> {
+        let mut times: Vec<Duration> = self.build_times.iter().copied().collect();
+        times.sort_unstable();
+        let last = times.len().checked_sub(1)?;
+        let idx = (quantile.clamp(0., 1.) * last as f64).round() as usize;
+        Some(times[idx])
+    }
+
+    /// Number of build times in each `bin` wide bucket, starting from zero.
+    pub fn build_time_histogram(&self, bin: Duration) -> Vec<u64> {
+        let mut histogram = Vec::new();
+        if bin.is_zero() {
+            return histogram;
+        }
+        for time in &self.build_times {
+            let idx = (time.as_micros() / bin.as_micros()) as usize;
+            if histogram.len() <= idx {
+                histogram.resize(idx + 1, 0);
+            }
+            histogram[idx] += 1;
+        }
+        histogram
+    }
+
+    /// Circuits built and circuits failed before being built.
+    pub fn build_counts(&self) -> (u64, u64) {
+        (self.built, self.failed)
+    }
+
+    pub fn relay_stats(&self) -> impl Iterator<Item = (&RelayFingerprint, &FailureStats)> {
+        self.relays.iter()
+    }
+
+    pub fn relay(&self, fingerprint: &RelayFingerprint) -> Option<&FailureStats> {
+        self.relays.get(fingerprint)
+    }
+
+    /// Attempts and failures to extend to each hop, the first being the guard.
+    pub fn position_stats(&self) -> &[FailureStats] {
+        &self.positions[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circuit(id: &str, status: &str, hops: usize) -> Circuit {
+        let path = [
+            "$8737307DE84C2621E6399E99123967A9590297F2~A",
+            "$243996E46218666C1CADDE17B430EA7F95124F96~B",
+            "$3A944371022AE51828A5C342D1D36C1D460A1206~C",
+        ][..hops]
+            .join(",");
+        let input = if path.is_empty() {
+            format!("{id} {status}\r\n")
+        } else {
+            format!("{id} {status} {path}\r\n")
+        };
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn lifecycle() {
+        let start = Time::new(2023, 1, 1, 0, 0, 0, 0).unwrap();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut tracker = CircuitTracker::new(10);
+
+        tracker.update(&circuit("1", "LAUNCHED", 0), at(0));
+        tracker.update(&circuit("1", "EXTENDED", 1), at(100));
+        tracker.update(&circuit("1", "EXTENDED", 2), at(200));
+        tracker.update(&circuit("1", "EXTENDED", 3), at(300));
+        tracker.update(&circuit("1", "BUILT", 3), at(350));
+        tracker.update(&circuit("2", "LAUNCHED", 0), at(0));
+        tracker.update(&circuit("2", "EXTENDED", 1), at(100));
+        tracker.update(&circuit("2", "FAILED", 1), at(2000));
+        tracker.update(&circuit("2", "CLOSED", 1), at(2000));
+        tracker.update(&circuit("1", "CLOSED", 3), at(5000));
+
+        assert_eq!(tracker.open().count(), 0);
+        assert_eq!(tracker.closed().count(), 2);
+        let history = tracker.get(&CircuitID("1".into())).unwrap();
+        assert_eq!(history.transitions.len(), 6);
+        assert_eq!(history.build_time(), Some(Duration::from_millis(350)));
+        assert!(tracker.get(&CircuitID("2".into())).unwrap().is_failed());
+
+        assert_eq!(tracker.build_counts(), (1, 1));
+        assert_eq!(
+            tracker.build_time_quantile(0.5),
+            Some(Duration::from_millis(350))
+        );
+        assert_eq!(
+            tracker.build_time_histogram(Duration::from_millis(100)),
+            [0, 0, 0, 1]
+        );
+        let positions = tracker.position_stats();
+        assert_eq!(positions[0].attempts, 2);
+        assert_eq!(positions[1].attempts, 2);
+        assert_eq!(positions[1].failures, 1);
+        assert_eq!(positions[2].attempts, 1);
+        let guard = circuit("1", "BUILT", 1).path[0].fingerprint;
+        assert_eq!(tracker.relay(&guard).unwrap().failure_rate(), 0.5);
+    }
+}
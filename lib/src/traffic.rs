@@ -0,0 +1,245 @@
+//! Rolling per-second bandwidth series, fed by `BW`, `CIRC_BW` and `STREAM_BW` events.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::tor::circuit::CircuitStatus;
+use crate::tor::common::{CircuitID, StreamID, Time};
+use crate::tor::event::Event;
+use crate::tor::stream::StreamStatus;
+
+/// Events to subscribe to for a `TrafficMonitor`.
+pub const TRAFFIC_EVENTS: &[&str] = &["BW", "CIRC_BW", "STREAM_BW", "CIRC", "STREAM"];
+
+/// Bytes read and written during one second.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sample {
+    /// Unix timestamp of the second
+    pub timestamp: i64,
+    pub read: u64,
+    pub written: u64,
+}
+
+/// Traffic of the last `capacity` seconds, one sample per second.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Series {
+    capacity: usize,
+    samples: VecDeque<Sample>,
+}
+
+impl Series {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Accounts bytes to the second `timestamp`, seconds without traffic are zero filled.
+    pub fn add(&mut self, timestamp: i64, read: u64, written: u64) {
+        let last = match self.samples.back() {
+            Some(sample) => sample.timestamp,
+            None => timestamp - 1,
+        };
+        if timestamp <= last {
+            if let Some(sample) = self
+                .samples
+                .iter_mut()
+                .rev()
+                .find(|s| s.timestamp == timestamp)
+            {
+                sample.read += read;
+                sample.written += written;
+            }
+            return;
+        }
+
+        let gap = (timestamp - last - 1).min(self.capacity as i64);
+        for t in timestamp - gap..timestamp {
+            self.push(Sample {
+                timestamp: t,
+                ..Default::default()
+            });
+        }
+        self.push(Sample {
+            timestamp,
+            read,
+            written,
+        });
+    }
+
+    fn push(&mut self, sample: Sample) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn samples(&self) -> impl DoubleEndedIterator<Item = &Sample> + ExactSizeIterator {
+        self.samples.iter()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn last(&self) -> Option<&Sample> {
+        self.samples.back()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Average bytes read and written per second over the `seconds` before `now`.
+    pub fn rate(&self, now: i64, seconds: u32) -> (f64, f64) {
+        if seconds == 0 {
+            return (0., 0.);
+        }
+        let (read, written) = self
+            .samples
+            .iter()
+            .rev()
+            .take_while(|s| s.timestamp > now - seconds as i64)
+            .filter(|s| s.timestamp <= now)
+            .fold((0, 0), |(r, w), s| (r + s.read, w + s.written));
+        (
+            read as f64 / seconds as f64,
+            written as f64 / seconds as f64,
+        )
+    }
+
+    /// Highest per-second read or written value, handy to scale graphs.
+    pub fn peak(&self) -> u64 {
+        self.samples
+            .iter()
+            .map(|s| s.read.max(s.written))
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// Bandwidth of tor as a whole, and of each circuit and stream.
+///
+/// Series of closed circuits and streams are dropped, as are the ones without
+/// traffic for longer than the capacity.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TrafficMonitor {
+    capacity: usize,
+    total: Series,
+    circuits: HashMap<CircuitID, Series>,
+    streams: HashMap<StreamID, Series>,
+}
+
+impl Default for TrafficMonitor {
+    fn default() -> Self {
+        Self::new(300)
+    }
+}
+
+fn timestamp(time: Option<&Time>) -> i64 {
+    time.copied().unwrap_or_else(Time::now).unix_timestamp()
+}
+
+impl TrafficMonitor {
+    /// Keeps `capacity` seconds of history.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            total: Series::new(capacity),
+            circuits: HashMap::new(),
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Updates the series, returns whether the event was relevant.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::Bandwidth(bw) => {
+                let now = Time::now().unix_timestamp();
+                self.total.add(now, bw.read, bw.written);
+                self.prune(now);
+            }
+            Event::CircuitBandwidth(bw) => self
+                .circuits
+                .entry(bw.id.clone())
+                .or_insert_with(|| Series::new(self.capacity))
+                .add(timestamp(bw.time.as_ref()), bw.read, bw.written),
+            Event::StreamBandwidth(bw) => self
+                .streams
+                .entry(bw.id.clone())
+                .or_insert_with(|| Series::new(self.capacity))
+                .add(timestamp(bw.time.as_ref()), bw.read, bw.written),
+            Event::Circuit(circuit)
+                if matches!(
+                    circuit.status,
+                    CircuitStatus::Closed | CircuitStatus::Failed
+                ) =>
+            {
+                self.circuits.remove(&circuit.id);
+            }
+            Event::Stream(stream)
+                if matches!(stream.status, StreamStatus::Closed | StreamStatus::Failed) =>
+            {
+                self.streams.remove(&stream.id);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn prune(&mut self, now: i64) {
+        let oldest = now - self.capacity as i64;
+        let active = |series: &Series| series.last().is_some_and(|s| s.timestamp > oldest);
+        self.circuits.retain(|_, series| active(series));
+        self.streams.retain(|_, series| active(series));
+    }
+
+    /// Traffic of tor as a whole, from `BW` events.
+    pub fn total(&self) -> &Series {
+        &self.total
+    }
+
+    pub fn circuit(&self, id: &CircuitID) -> Option<&Series> {
+        self.circuits.get(id)
+    }
+
+    pub fn stream(&self, id: &StreamID) -> Option<&Series> {
+        self.streams.get(id)
+    }
+
+    pub fn circuits(&self) -> impl Iterator<Item = (&CircuitID, &Series)> {
+        self.circuits.iter()
+    }
+
+    pub fn streams(&self) -> impl Iterator<Item = (&StreamID, &Series)> {
+        self.streams.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series() {
+        let mut series = Series::new(4);
+        series.add(10, 100, 10);
+        series.add(10, 100, 10);
+        series.add(13, 50, 5);
+        assert_eq!(
+            series
+                .samples()
+                .map(|s| (s.timestamp, s.read))
+                .collect::<Vec<_>>(),
+            [(10, 200), (11, 0), (12, 0), (13, 50)]
+        );
+        series.add(14, 1, 1);
+        assert_eq!(series.samples().len(), 4);
+        assert_eq!(series.samples().next().unwrap().timestamp, 11);
+        assert_eq!(series.rate(14, 2), (25.5, 3.));
+        assert_eq!(series.peak(), 50);
+    }
+}
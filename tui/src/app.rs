@@ -0,0 +1,299 @@
+use std::collections::HashSet;
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::widgets::TableState;
+
+use tor_analyzer_lib::country::Country;
+use tor_analyzer_lib::error::Error;
+use tor_analyzer_lib::prelude::*;
+use tor_analyzer_lib::tor::circuit::CircuitStatus;
+use tor_analyzer_lib::tor::stream::StreamStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Circuits,
+    Relays,
+    Streams,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 3] = [Tab::Circuits, Tab::Relays, Tab::Streams];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Circuits => "Circuits",
+            Self::Relays => "Relays",
+            Self::Streams => "Streams",
+        }
+    }
+
+    fn index(&self) -> usize {
+        Self::ALL.iter().position(|t| t == self).unwrap()
+    }
+}
+
+pub enum Mode {
+    Normal,
+
+    /// Typing a relay query
+    Filter,
+
+    /// Choosing the circuit a stream gets attached to
+    Attach {
+        stream: StreamID,
+    },
+}
+
+pub struct App {
+    ctrl: TorController,
+    gi: Option<GeoIP>,
+    pub tab: Tab,
+    pub mode: Mode,
+    pub circuits: Vec<Circuit>,
+    pub streams: Vec<Stream>,
+    pub relays: RelayIndex,
+    pub filter: String,
+    query: Query,
+    pub status: Option<String>,
+    pub circuits_state: TableState,
+    pub relays_state: TableState,
+    pub streams_state: TableState,
+    pub attach_state: TableState,
+    pub quit: bool,
+}
+
+impl App {
+    pub fn new(ctrl: TorController, gi: Option<GeoIP>) -> Result<Self, Error> {
+        let mut app = Self {
+            ctrl,
+            gi,
+            tab: Tab::Circuits,
+            mode: Mode::Normal,
+            circuits: Vec::new(),
+            streams: Vec::new(),
+            relays: RelayIndex::default(),
+            filter: String::new(),
+            query: Query::default(),
+            status: None,
+            circuits_state: TableState::default(),
+            relays_state: TableState::default(),
+            streams_state: TableState::default(),
+            attach_state: TableState::default(),
+            quit: false,
+        };
+        app.reload()?;
+        Ok(app)
+    }
+
+    /// Reads circuits, streams and the consensus again.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        self.relays = RelayIndex::new(self.ctrl.get_all_onion_router()?, self.gi.as_ref());
+        self.circuits = self.ctrl.get_circuits()?;
+        self.streams = self.ctrl.get_streams()?;
+        Ok(())
+    }
+
+    pub fn country(&self, fingerprint: &RelayFingerprint) -> Option<&'static Country> {
+        self.relays.get(fingerprint).and_then(Relay::country)
+    }
+
+    /// Country of the last hop of the circuit.
+    pub fn exit_country(&self, circuit: &Circuit) -> Option<&'static Country> {
+        circuit
+            .path
+            .last()
+            .and_then(|step| self.country(&step.fingerprint))
+    }
+
+    pub fn visible_relays(&self) -> Vec<&Relay> {
+        self.relays.query(&self.query)
+    }
+
+    /// Built circuits not already carrying a stream.
+    pub fn attachable_circuits(&self) -> Vec<&Circuit> {
+        let occupied: HashSet<&CircuitID> = self
+            .streams
+            .iter()
+            .filter(|s| s.circuit_id.0 != "0")
+            .map(|s| &s.circuit_id)
+            .collect();
+        self.circuits
+            .iter()
+            .filter(|c| c.status == CircuitStatus::Built && !occupied.contains(&c.id))
+            .collect()
+    }
+
+    pub fn handle_tor_event(&mut self, event: Event) {
+        match event {
+            Event::Circuit(circuit) => {
+                let closed = matches!(
+                    circuit.status,
+                    CircuitStatus::Closed | CircuitStatus::Failed
+                );
+                let idx = self.circuits.iter().position(|c| c.id == circuit.id);
+                match (idx, closed) {
+                    (Some(idx), true) => {
+                        self.circuits.remove(idx);
+                    }
+                    (Some(idx), false) => self.circuits[idx] = circuit,
+                    (None, false) => self.circuits.push(circuit),
+                    (None, true) => {}
+                }
+            }
+            Event::Stream(stream) => {
+                let closed = matches!(stream.status, StreamStatus::Closed | StreamStatus::Failed);
+                let idx = self.streams.iter().position(|s| s.id == stream.id);
+                match (idx, closed) {
+                    (Some(idx), true) => {
+                        self.streams.remove(idx);
+                    }
+                    (Some(idx), false) => self.streams[idx] = stream,
+                    (None, false) => self.streams.push(stream),
+                    (None, true) => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn current_len(&self) -> usize {
+        match self.mode {
+            Mode::Attach { .. } => self.attachable_circuits().len(),
+            _ => match self.tab {
+                Tab::Circuits => self.circuits.len(),
+                Tab::Relays => self.visible_relays().len(),
+                Tab::Streams => self.streams.len(),
+            },
+        }
+    }
+
+    fn current_state(&mut self) -> &mut TableState {
+        match self.mode {
+            Mode::Attach { .. } => &mut self.attach_state,
+            _ => match self.tab {
+                Tab::Circuits => &mut self.circuits_state,
+                Tab::Relays => &mut self.relays_state,
+                Tab::Streams => &mut self.streams_state,
+            },
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let len = self.current_len();
+        let state = self.current_state();
+        if len == 0 {
+            state.select(None);
+            return;
+        }
+        let selected = state.selected().unwrap_or(0) as isize + delta;
+        state.select(Some(selected.clamp(0, len as isize - 1) as usize));
+    }
+
+    fn update_filter(&mut self) {
+        match self.filter.parse::<Query>() {
+            Ok(query) => {
+                self.query = query;
+                self.status = None;
+                self.relays_state.select(Some(0));
+            }
+            Err(e) => self.status = Some(format!("Invalid query: {e}")),
+        }
+    }
+
+    fn attach_selected(&mut self, stream: StreamID) {
+        let circuit = self
+            .attach_state
+            .selected()
+            .and_then(|idx| self.attachable_circuits().get(idx).map(|c| c.id.clone()));
+        let Some(circuit) = circuit else {
+            self.status = Some("No circuit selected".into());
+            return;
+        };
+        self.status = Some(
+            match self.ctrl.attach_stream(stream.clone(), circuit.clone()) {
+                Ok(_) => format!("Stream {stream} attached to circuit {circuit}"),
+                Err(e) => format!("Could not attach stream {stream}: {e}"),
+            },
+        );
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.quit = true;
+            return;
+        }
+
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Filter => match key.code {
+                KeyCode::Enter | KeyCode::Esc => {}
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.update_filter();
+                    self.mode = Mode::Filter;
+                }
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.update_filter();
+                    self.mode = Mode::Filter;
+                }
+                _ => self.mode = Mode::Filter,
+            },
+            Mode::Attach { stream } => match key.code {
+                KeyCode::Esc => {}
+                KeyCode::Enter => self.attach_selected(stream),
+                code => {
+                    self.mode = Mode::Attach { stream };
+                    self.handle_movement(code);
+                }
+            },
+            Mode::Normal => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                KeyCode::Tab => self.tab = Tab::ALL[(self.tab.index() + 1) % Tab::ALL.len()],
+                KeyCode::BackTab => {
+                    self.tab = Tab::ALL[(self.tab.index() + Tab::ALL.len() - 1) % Tab::ALL.len()]
+                }
+                KeyCode::Char(c @ '1'..='3') => {
+                    self.tab = Tab::ALL[c as usize - '1' as usize];
+                }
+                KeyCode::Char('r') => {
+                    self.status = match self.reload() {
+                        Ok(()) => None,
+                        Err(e) => Some(format!("Could not reload: {e}")),
+                    };
+                }
+                KeyCode::Char('/') if self.tab == Tab::Relays => self.mode = Mode::Filter,
+                KeyCode::Char('a') | KeyCode::Enter if self.tab == Tab::Streams => {
+                    let stream = self
+                        .streams_state
+                        .selected()
+                        .and_then(|idx| self.streams.get(idx));
+                    match stream {
+                        Some(stream) if stream.status.is_pending() => {
+                            self.mode = Mode::Attach {
+                                stream: stream.id.clone(),
+                            };
+                            self.attach_state.select(Some(0));
+                        }
+                        Some(stream) => {
+                            self.status = Some(format!("Stream {} is not pending", stream.id))
+                        }
+                        None => {}
+                    }
+                }
+                code => self.handle_movement(code),
+            },
+        }
+    }
+
+    fn handle_movement(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::PageDown => self.move_selection(20),
+            KeyCode::PageUp => self.move_selection(-20),
+            KeyCode::Home => self.move_selection(isize::MIN / 2),
+            KeyCode::End => self.move_selection(isize::MAX / 2),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyEventKind};
+use ratatui::DefaultTerminal;
+
+use tor_analyzer_lib::error::Error;
+use tor_analyzer_lib::prelude::*;
+
+mod app;
+mod ui;
+
+use app::App;
+
+enum Message {
+    Tor(Event),
+    Error(String),
+}
+
+/// Listens to circuit and stream events on a dedicated control connection.
+fn spawn_event_listener(control: String, tx: Sender<Message>) {
+    thread::spawn(move || {
+        let result = (|| -> Result<(), Error> {
+            let mut ctrl = TorController::new(&control)?;
+            ctrl.set_events(&["CIRC", "STREAM"])?;
+            loop {
+                let message = match ctrl.wait_event() {
+                    Ok(event) => Message::Tor(event),
+                    Err(Error::Io(e)) => return Err(Error::Io(e)),
+                    Err(e) => Message::Error(format!("Invalid event: {e}")),
+                };
+                if tx.send(message).is_err() {
+                    return Ok(());
+                }
+            }
+        })();
+        if let Err(e) = result {
+            let _ = tx.send(Message::Error(format!("Event listener stopped: {e}")));
+        }
+    });
+}
+
+fn run(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    rx: Receiver<Message>,
+) -> std::io::Result<()> {
+    while !app.quit {
+        while let Ok(message) = rx.try_recv() {
+            match message {
+                Message::Tor(event) => app.handle_tor_event(event),
+                Message::Error(e) => app.status = Some(e),
+            }
+        }
+
+        terminal.draw(|frame| ui::draw(frame, app))?;
+
+        if event::poll(Duration::from_millis(200))? {
+            if let TermEvent::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    env_logger::init();
+    // For a tor started for the application only, tor then exits with it
+    let take_ownership = std::env::args().any(|arg| arg == "--take-ownership");
+    let first_arg = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .unwrap_or_else(|| "127.0.0.1:9051".into());
+
+    // Restored when dropped at the end of main, or if the TUI dies
+    let config = ConfigGuard::new(&first_arg)?;
+    if take_ownership {
+        config.take_ownership()?;
+    }
+    config.set("__LeaveStreamsUnattached", Some(1))?;
+    #[cfg(unix)]
+    config.restore_on_signals_with(ratatui::restore)?;
+    config.restore_on_panic();
+
+    let ctrl = TorController::new(&first_arg)?;
+    let gi = GeoIP::open_default()
+        .map_err(|e| log::warn!("GeoIP databases unavailable: {}", e))
+        .ok();
+    let mut app = App::new(ctrl, gi)?;
+
+    let (tx, rx) = mpsc::channel();
+    spawn_event_listener(first_arg, tx);
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut app, rx);
+    ratatui::restore();
+    drop(config);
+
+    Ok(result?)
+}
@@ -0,0 +1,256 @@
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, Tabs};
+use ratatui::Frame;
+
+use tor_analyzer_lib::country::Country;
+use tor_analyzer_lib::prelude::*;
+
+use crate::app::{App, Mode, Tab};
+
+fn flag(country: Option<&'static Country>) -> &'static str {
+    country.map(|c| c.flag).unwrap_or("??")
+}
+
+fn format_age(circuit: &Circuit) -> String {
+    match circuit.age() {
+        Some(age) if age.as_secs() >= 3600 => {
+            format!("{}h{:02}m", age.as_secs() / 3600, age.as_secs() % 3600 / 60)
+        }
+        Some(age) => format!("{}m{:02}s", age.as_secs() / 60, age.as_secs() % 60),
+        None => String::new(),
+    }
+}
+
+fn table<'a>(
+    title: &'a str,
+    header: &'a [&'a str],
+    rows: Vec<Row<'a>>,
+    widths: &'a [Constraint],
+) -> Table<'a> {
+    Table::new(rows, widths.iter().copied())
+        .header(Row::new(header.iter().copied()).style(Style::new().bold()))
+        .block(Block::new().borders(Borders::ALL).title(title))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+}
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [tabs_area, main_area, status_area] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let titles = Tab::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, t)| format!("{} {}", i + 1, t.title()));
+    let selected = Tab::ALL.iter().position(|t| *t == app.tab).unwrap();
+    frame.render_widget(
+        Tabs::new(titles)
+            .select(selected)
+            .highlight_style(Style::new().bold().reversed()),
+        tabs_area,
+    );
+
+    match app.tab {
+        Tab::Circuits => draw_circuits(frame, app, main_area),
+        Tab::Relays => draw_relays(frame, app, main_area),
+        Tab::Streams => draw_streams(frame, app, main_area),
+    }
+
+    let status = match (&app.mode, &app.status) {
+        (Mode::Filter, Some(status)) => Line::from(vec![
+            Span::raw(format!("/{}", app.filter)),
+            Span::raw("  "),
+            Span::raw(status.as_str()).red(),
+        ]),
+        (Mode::Filter, None) => Line::raw(format!("/{}", app.filter)),
+        (_, Some(status)) => Line::raw(status.as_str()),
+        (Mode::Attach { .. }, None) => Line::raw("enter: attach  esc: cancel"),
+        (Mode::Normal, None) => Line::raw(
+            "q: quit  tab/1-3: switch view  j/k: move  r: reload  /: filter relays  a: attach stream",
+        ),
+    };
+    frame.render_widget(Paragraph::new(status), status_area);
+
+    if let Mode::Attach { ref stream } = app.mode {
+        draw_attach(frame, app, stream.clone(), main_area);
+    }
+}
+
+fn draw_circuits(frame: &mut Frame, app: &mut App, area: Rect) {
+    let rows: Vec<Row> = app
+        .circuits
+        .iter()
+        .map(|c| {
+            let path = c
+                .path
+                .iter()
+                .map(|step| {
+                    let name = step
+                        .nickname
+                        .clone()
+                        .unwrap_or_else(|| step.fingerprint.to_string());
+                    format!("{} {}", flag(app.country(&step.fingerprint)), name)
+                })
+                .collect::<Vec<_>>()
+                .join(" → ");
+            Row::new(vec![
+                Cell::from(c.id.to_string()),
+                Cell::from(c.status.to_string()),
+                Cell::from(
+                    c.purpose
+                        .as_ref()
+                        .map(|p| p.to_string())
+                        .unwrap_or_default(),
+                ),
+                Cell::from(format_age(c)),
+                Cell::from(path),
+            ])
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(6),
+        Constraint::Length(10),
+        Constraint::Length(18),
+        Constraint::Length(7),
+        Constraint::Fill(1),
+    ];
+    let table = table(
+        "Circuits",
+        &["Id", "Status", "Purpose", "Age", "Path"],
+        rows,
+        &widths,
+    );
+    frame.render_stateful_widget(table, area, &mut app.circuits_state);
+}
+
+fn draw_relays(frame: &mut Frame, app: &mut App, area: Rect) {
+    let relays = app.visible_relays();
+    let title = format!("Relays ({}/{})", relays.len(), app.relays.len());
+    let rows: Vec<Row> = relays
+        .iter()
+        .map(|r| {
+            Row::new(vec![
+                Cell::from(r.or.identity.to_string()),
+                Cell::from(r.or.nickname.clone()),
+                Cell::from(r.or.target.to_string()),
+                Cell::from(format!("{} {}", flag(r.country()), r.country.unwrap_or(""))),
+                Cell::from(r.or.flags.to_string()),
+                Cell::from(r.or.bandwidth.map(|bw| bw.to_string()).unwrap_or_default()),
+            ])
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(40),
+        Constraint::Length(19),
+        Constraint::Length(22),
+        Constraint::Length(7),
+        Constraint::Fill(1),
+        Constraint::Length(9),
+    ];
+    let table = table(
+        &title,
+        &[
+            "Fingerprint",
+            "Nickname",
+            "Address",
+            "Country",
+            "Flags",
+            "Bandwidth",
+        ],
+        rows,
+        &widths,
+    );
+    frame.render_stateful_widget(table, area, &mut app.relays_state);
+}
+
+fn draw_streams(frame: &mut Frame, app: &mut App, area: Rect) {
+    let rows: Vec<Row> = app
+        .streams
+        .iter()
+        .map(|s| {
+            let exit = app
+                .circuits
+                .iter()
+                .find(|c| c.id == s.circuit_id)
+                .map(|c| flag(app.exit_country(c)))
+                .unwrap_or_default();
+            let row = Row::new(vec![
+                Cell::from(s.id.to_string()),
+                Cell::from(s.status.to_string()),
+                Cell::from(s.target.to_string()),
+                Cell::from(s.circuit_id.to_string()),
+                Cell::from(exit),
+            ]);
+            if s.status.is_pending() {
+                row.yellow()
+            } else {
+                row
+            }
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(6),
+        Constraint::Length(16),
+        Constraint::Fill(1),
+        Constraint::Length(8),
+        Constraint::Length(5),
+    ];
+    let table = table(
+        "Streams",
+        &["Id", "Status", "Target", "Circuit", "Exit"],
+        rows,
+        &widths,
+    );
+    frame.render_stateful_widget(table, area, &mut app.streams_state);
+}
+
+fn draw_attach(frame: &mut Frame, app: &mut App, stream: StreamID, area: Rect) {
+    let [_, popup, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Percentage(60),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    let [_, popup, _] = Layout::horizontal([
+        Constraint::Fill(1),
+        Constraint::Percentage(70),
+        Constraint::Fill(1),
+    ])
+    .areas(popup);
+
+    let rows: Vec<Row> = app
+        .attachable_circuits()
+        .iter()
+        .map(|c| {
+            let exit = app.exit_country(c);
+            Row::new(vec![
+                Cell::from(c.id.to_string()),
+                Cell::from(format!(
+                    "{} {}",
+                    flag(exit),
+                    exit.map(|country| country.name).unwrap_or("unknown")
+                )),
+                Cell::from(
+                    c.purpose
+                        .as_ref()
+                        .map(|p| p.to_string())
+                        .unwrap_or_default(),
+                ),
+            ])
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(6),
+        Constraint::Fill(1),
+        Constraint::Length(18),
+    ];
+    let title = format!("Attach stream {stream} to");
+    let table = table(&title, &["Circuit", "Exit", "Purpose"], rows, &widths);
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(table, popup, &mut app.attach_state);
+}